@@ -0,0 +1,237 @@
+use nix::{
+    fcntl::{fcntl, FcntlArg, SealFlag},
+    sys::{
+        memfd::{memfd_create, MemFdCreateFlag},
+        mman::{mmap, mprotect, munmap, MapFlags, ProtFlags},
+        stat::fstat,
+    },
+};
+use bytemuck::Pod;
+use std::{
+    ffi::CStr,
+    io,
+    os::unix::io::{AsRawFd, FromRawFd, OwnedFd},
+    ptr::NonNull,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::ProtocolError;
+
+/// Byte strings at least this long go through the shared arena when one is attached.
+pub const ARENA_MIN_PAYLOAD: usize = 16 * 1024;
+
+const ALIGN: usize = 8;
+
+// the first page of each arena holds the count of values placed there that the reader hasn't
+// taken yet, a page of its own so the rest can be mapped read-only on the reading side
+fn header() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+// an arena that could shrink under a mapping would fault on reads past its new end
+const SEALS: SealFlag = SealFlag::F_SEAL_SHRINK.union(SealFlag::F_SEAL_GROW);
+
+struct Mapping {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// only the side that owns a mapping as its outbound arena writes to it, see SharedArenas::place
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    // the mapping outlives the descriptor. Only the header of an inbound arena is writable
+    fn new(fd: OwnedFd, inbound: bool) -> io::Result<Self> {
+        let seals = fcntl(fd.as_raw_fd(), FcntlArg::F_GET_SEALS).map_err(io::Error::from)?;
+        if !SealFlag::from_bits_truncate(seals).contains(SEALS) {
+            return Err(ProtocolError::ArenaNotSealed.into());
+        }
+        let len = fstat(fd.as_raw_fd()).map_err(io::Error::from)?.st_size as usize;
+        if len <= header() {
+            return Err(ProtocolError::ArenaTooSmall(len).into());
+        }
+        let ptr = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                len,
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                MapFlags::MAP_SHARED,
+                fd.as_raw_fd(),
+                0,
+            )
+        }
+        .map_err(io::Error::from)?;
+        let mapping = Mapping {
+            ptr: NonNull::new(ptr.cast()).expect("mmap does not map page zero"),
+            len,
+        };
+        if inbound {
+            unsafe {
+                let payload = mapping.ptr.as_ptr().add(header());
+                mprotect(payload.cast(), len - header(), ProtFlags::PROT_READ)
+            }
+            .map_err(io::Error::from)?;
+        }
+        Ok(mapping)
+    }
+
+    fn outstanding(&self) -> &AtomicU64 {
+        unsafe { &*(self.ptr.as_ptr() as *const AtomicU64) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        let _ = unsafe { munmap(self.ptr.as_ptr().cast(), self.len) };
+    }
+}
+
+/// The arenas shared by a controller and subordinate, one written by each side.
+pub struct SharedArenas {
+    outbound: Mapping,
+    inbound: Mapping,
+    cursor: usize,
+}
+
+impl SharedArenas {
+    pub(crate) fn new(outbound: OwnedFd, inbound: OwnedFd) -> io::Result<Self> {
+        Ok(SharedArenas {
+            outbound: Mapping::new(outbound, false)?,
+            inbound: Mapping::new(inbound, true)?,
+            cursor: header(),
+        })
+    }
+
+    pub(crate) fn create(size: usize) -> io::Result<(OwnedFd, OwnedFd)> {
+        let create = |name: &CStr| -> io::Result<OwnedFd> {
+            let flags = MemFdCreateFlag::MFD_CLOEXEC | MemFdCreateFlag::MFD_ALLOW_SEALING;
+            let fd = memfd_create(name, flags).map_err(io::Error::from)?;
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            nix::unistd::ftruncate(fd.as_raw_fd(), (header() + size) as i64)
+                .map_err(io::Error::from)?;
+            fcntl(fd.as_raw_fd(), FcntlArg::F_ADD_SEALS(SEALS)).map_err(io::Error::from)?;
+            Ok(fd)
+        };
+        Ok((
+            create(c"ufo_ipc_arena_out")?,
+            create(c"ufo_ipc_arena_in")?,
+        ))
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.outbound.len - header()
+    }
+
+    /// Copy bytes into our arena, giving back where they went, or `None` if they don't fit.
+    pub(crate) fn place(&mut self, bytes: &[u8]) -> Option<(u64, u64)> {
+        let outstanding = self.outbound.outstanding();
+        // nothing we placed is still held by the other side, so all of it can be reused
+        if outstanding.load(Ordering::Acquire) == 0 {
+            self.cursor = header();
+        }
+        if bytes.len() > self.outbound.len - self.cursor {
+            return None;
+        }
+
+        let offset = self.cursor;
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                self.outbound.ptr.as_ptr().add(offset),
+                bytes.len(),
+            );
+        }
+        outstanding.fetch_add(1, Ordering::AcqRel);
+        self.cursor = (offset + bytes.len()).div_ceil(ALIGN) * ALIGN;
+        Some((offset as u64, bytes.len() as u64))
+    }
+
+    /// Copy bytes the other side placed in its arena onto the end of `into`, handing their
+    /// place back. The other side can still write to its arena, so nothing is read from it
+    /// in place, a misbehaving peer can only garble the copy. `len` must be a whole number of
+    /// `T`s.
+    pub(crate) fn take<T: Pod>(&self, offset: u64, len: u64, into: &mut Vec<T>) -> io::Result<()> {
+        let in_bounds = offset >= header() as u64
+            && offset
+                .checked_add(len)
+                .is_some_and(|end| end <= self.inbound.len as u64);
+        if !in_bounds {
+            return Err(ProtocolError::ArenaOutOfBounds { offset, len }.into());
+        }
+        let start = into.len();
+        into.resize(start + len as usize / std::mem::size_of::<T>(), T::zeroed());
+        let copy: &mut [u8] = bytemuck::cast_slice_mut(&mut into[start..]);
+        unsafe {
+            let from = self.inbound.ptr.as_ptr().add(offset as usize);
+            std::ptr::copy_nonoverlapping(from, copy.as_mut_ptr(), copy.len());
+        }
+        // the count was raised by the sender, taking the bytes lowers it again
+        self.inbound.outstanding().fetch_sub(1, Ordering::AcqRel);
+        Ok(())
+    }
+
+    /// Clear the count of the other side's arena. Values it placed for a message that couldn't
+    /// be read to the end were never taken, and would otherwise keep it from ever reusing the
+    /// arena.
+    pub(crate) fn release_unread(&self) {
+        self.inbound.outstanding().store(0, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn space_is_reused_once_the_reader_has_taken_everything() {
+        let (outbound, inbound) = SharedArenas::create(64).unwrap();
+        let mut controller = SharedArenas::new(
+            outbound.try_clone().unwrap(),
+            inbound.try_clone().unwrap(),
+        )
+        .unwrap();
+        let subordinate = SharedArenas::new(inbound, outbound).unwrap();
+
+        let (offset, len) = controller.place(&[7; 40]).unwrap();
+        assert!(controller.place(&[8; 40]).is_none());
+        assert!(subordinate.take(offset, 65, &mut Vec::<u8>::new()).is_err());
+        let mut taken = vec![1u8];
+        subordinate.take(offset, len, &mut taken).unwrap();
+        assert_eq!(taken[1..], [7; 40]);
+
+        let (offset, len) = controller.place(&[8; 40]).unwrap();
+        let mut taken = Vec::<u32>::new();
+        subordinate.take(offset, len, &mut taken).unwrap();
+        assert_eq!(taken, [u32::from_ne_bytes([8; 4]); 10]);
+
+        // placed for a message the subordinate never got to the end of
+        controller.place(&[9; 40]).unwrap();
+        assert!(controller.place(&[9; 40]).is_none());
+        subordinate.release_unread();
+        assert!(controller.place(&[9; 40]).is_some());
+
+        let unsealed = memfd_create(c"unsealed", MemFdCreateFlag::MFD_CLOEXEC).unwrap();
+        let unsealed = unsafe { OwnedFd::from_raw_fd(unsealed) };
+        nix::unistd::ftruncate(unsealed.as_raw_fd(), 4096).unwrap();
+        assert!(Mapping::new(unsealed, true).is_err());
+    }
+
+    #[test]
+    fn only_the_header_of_an_inbound_arena_is_writable() {
+        let (outbound, inbound) = SharedArenas::create(64).unwrap();
+        let arenas = SharedArenas::new(outbound, inbound).unwrap();
+        arenas.release_unread();
+        unsafe {
+            let child = libc::fork();
+            assert!(child >= 0);
+            if child == 0 {
+                *arenas.inbound.ptr.as_ptr().add(header()) = 1;
+                libc::_exit(0);
+            }
+            let mut status = 0;
+            assert_eq!(libc::waitpid(child, &mut status, 0), child);
+            assert!(libc::WIFSIGNALED(status) && libc::WTERMSIG(status) == libc::SIGSEGV);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use bytemuck::Pod;
+use std::{fmt, marker::PhantomData, mem::size_of};
+
+/// The borrowed form of a typed array, packed elements that may not be aligned for `T`.
+///
+/// Arrays built from a `&[T]`, and most arrays read into a `Frame`, are aligned and `as_slice`
+/// hands them back without copying. Otherwise elements are read out one at a time.
+#[derive(Copy, Clone)]
+pub struct PodSlice<'a, T> {
+    bytes: &'a [u8],
+    element: PhantomData<T>,
+}
+
+impl<'a, T: Pod> PodSlice<'a, T> {
+    // the caller checks the length is a whole number of elements
+    pub(crate) fn from_bytes(bytes: &'a [u8]) -> Self {
+        debug_assert_eq!(bytes.len() % size_of::<T>(), 0);
+        PodSlice {
+            bytes,
+            element: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len() / size_of::<T>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// `None` if the elements aren't aligned for `T`.
+    pub fn as_slice(&self) -> Option<&'a [T]> {
+        bytemuck::try_cast_slice(self.bytes).ok()
+    }
+
+    pub fn get(&self, index: usize) -> Option<T> {
+        let start = index.checked_mul(size_of::<T>())?;
+        let bytes = self.bytes.get(start..start + size_of::<T>())?;
+        Some(bytemuck::pod_read_unaligned(bytes))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = T> + 'a {
+        self.bytes
+            .chunks_exact(size_of::<T>())
+            .map(bytemuck::pod_read_unaligned)
+    }
+
+    pub fn to_vec(&self) -> Vec<T> {
+        match self.as_slice() {
+            Some(slice) => slice.to_vec(),
+            None => self.iter().collect(),
+        }
+    }
+}
+
+impl<'a, T: Pod> From<&'a [T]> for PodSlice<'a, T> {
+    fn from(slice: &'a [T]) -> Self {
+        PodSlice::from_bytes(bytemuck::cast_slice(slice))
+    }
+}
+
+impl<T: Pod + fmt::Debug> fmt::Debug for PodSlice<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unaligned_elements_are_read_one_at_a_time() {
+        let values = [1.5f64, -2.0, 3.25];
+        let mut bytes = vec![0u8];
+        bytes.extend_from_slice(bytemuck::cast_slice(&values));
+
+        let slice = PodSlice::<f64>::from_bytes(&bytes[1..]);
+        assert!(slice.as_slice().is_none());
+        assert_eq!(slice.get(1), Some(-2.0));
+        assert_eq!(slice.get(3), None);
+        assert_eq!(slice.to_vec(), values);
+    }
+}
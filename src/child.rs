@@ -1,21 +1,38 @@
-use std::io::Result;
+use std::{collections::HashMap, io::Result};
 
 use ufo_ipc::*;
 
+#[derive(FromGenerics)]
+struct PokeValue {
+    text: String,
+}
+
 fn main() -> Result<()> {
     let mut subordinate = subordinate_begin()?;
+    let mut poked: HashMap<String, String> = HashMap::new();
 
     'shutdown: loop {
         let request = subordinate.recv_command()?;
 
         match request.command {
+            // Null until something is poked into the key
             ProtocolCommand::Peek(key) => subordinate.respond_to_peek(
-                &[GenericValue::Vstring("test response")],
-                &[GenericValue::Vstring(&key)],
+                &[poked.get(&key).map(String::as_str).into()],
+                &[GenericValueRef::Vstring(&key)],
             )?,
-            ProtocolCommand::Poke { key, value } => subordinate.respond_to_poke(&[
-                GenericValue::Vstring(&key), GenericValue::Vstring(value[0].expect_string()?), 
-            ])?,
+            ProtocolCommand::Poke { key, value } => match PokeValue::from_generics(value) {
+                Ok(value) => {
+                    subordinate.respond_to_poke(&[
+                        GenericValueRef::Vstring(&key),
+                        GenericValueRef::Vstring(&value.text),
+                    ])?;
+                    poked.insert(key, value.text);
+                }
+                Err(e) => subordinate.respond_with_error(
+                    RemoteErrorType::GenericTypeError,
+                    &[e.to_string().as_str().into()],
+                )?,
+            },
 
             ProtocolCommand::Shutdown => break 'shutdown,
             _ => subordinate.respond_with_error(RemoteErrorType::ProtocolError, &[])?,
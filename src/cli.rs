@@ -0,0 +1,307 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, IsTerminal, Result, Write},
+    process::Command,
+};
+
+use ufo_ipc::*;
+
+const USAGE: &str = "\
+usage: ufo-ipc [--script FILE] [--trace FILE] [--] SUBORDINATE [ARGS..]
+       ufo-ipc conformance SUBORDINATE [ARGS..]";
+
+const HELP: &str = "\
+peek KEY                         print the values under KEY
+poke KEY VALUE..                 set the values under KEY
+define-data VALUE..              define data, printing its token
+define-function FILE [VALUE..]   define the function in FILE with associated data
+call N VALUE..                   call function N
+free N                           free function or data N
+shutdown                         say goodbye and exit
+values are written as printed: 42 -7i8 1.5f32 \"text\" b\"\\x00\" null [1, 2] (1,) {k: 1} u8s[1, 2]";
+
+// ufo-ipc starts a subordinate and sends it one command per line, from a script or typed in.
+// A script, or commands piped in, stops at the first error; typed commands carry on.
+// ufo-ipc conformance checks the subordinate against the protocol instead.
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1).peekable();
+    if args.next_if_eq("conformance").is_some() {
+        let subordinate = args.next().unwrap_or_else(|| usage());
+        let report = check_conformance(Command::new(subordinate).args(args));
+        println!("{}", report);
+        std::process::exit(if report.passed() { 0 } else { 1 });
+    }
+    let mut script = None;
+    let mut trace = None;
+    while let Some(flag) = args.next_if(|a| a.starts_with("--")) {
+        match flag.as_str() {
+            "--script" => script = Some(args.next().unwrap_or_else(|| usage())),
+            "--trace" => trace = Some(args.next().unwrap_or_else(|| usage())),
+            "--" => break,
+            _ => usage(),
+        }
+    }
+    let subordinate = args.next().unwrap_or_else(|| usage());
+
+    let mut controller = Command::new(subordinate).args(args).start_subordinate_process()?;
+    if let Some(trace) = trace {
+        controller.trace_to(trace)?;
+    }
+    let mut session = Session {
+        controller,
+        defined: HashMap::new(),
+    };
+
+    let stdin = io::stdin();
+    let (input, interactive): (Box<dyn BufRead>, bool) = match &script {
+        Some(path) => (Box::new(BufReader::new(File::open(path)?)), false),
+        None => (Box::new(stdin.lock()), stdin.is_terminal()),
+    };
+    if !session.run_lines(input.lines(), interactive)? {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2)
+}
+
+enum Defined {
+    Function(FunctionToken),
+    Data(DataToken),
+}
+
+struct Session {
+    controller: ControllerProcess,
+    // what each token number was defined as, so `free` knows which to free
+    defined: HashMap<u64, Defined>,
+}
+
+impl Session {
+    // a subordinate that has died can't be told goodbye
+    fn finish(&mut self) -> Result<()> {
+        match self.controller.is_alive() {
+            true => self.controller.shutdown(&[]),
+            false => Ok(()),
+        }
+    }
+
+    // every line until the input ends or one shuts the subordinate down, false if a script
+    // stopped at an error instead
+    fn run_lines(
+        &mut self,
+        mut lines: impl Iterator<Item = Result<String>>,
+        interactive: bool,
+    ) -> Result<bool> {
+        loop {
+            if interactive {
+                print!("> ");
+                io::stdout().flush()?;
+            }
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => break,
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match self.run(line) {
+                Ok(true) => {}
+                Ok(false) => return Ok(true),
+                Err(e) => {
+                    eprintln!("error: {}", describe(&e));
+                    if !interactive {
+                        self.finish()?;
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+        self.finish()?;
+        Ok(true)
+    }
+
+    // false once the subordinate has been shut down
+    fn run(&mut self, line: &str) -> Result<bool> {
+        let (command, rest) = word(line);
+        match command {
+            "peek" => {
+                let (key, rest) = word(rest);
+                no_values(rest)?;
+                let response = self.controller.peek(key, &[])?;
+                print_response(&response);
+                println!("{}", list(&response.value));
+            }
+            "poke" => {
+                let (key, rest) = word(rest);
+                let value = parse_generics(rest)?;
+                let response = self.controller.poke(key, &value.as_refs(), &[])?;
+                print_response(&response);
+                println!("ok");
+            }
+            "define-data" => {
+                let value = parse_generics(rest)?;
+                let response = self.controller.define_data(&value.as_refs(), &[])?;
+                print_response(&response);
+                self.defined.insert(response.value.0, Defined::Data(response.value));
+                println!("data {}", response.value.0);
+            }
+            "define-function" => {
+                let (path, rest) = word(rest);
+                let blob = std::fs::read(path)?;
+                let data = parse_generics(rest)?;
+                let response = self.controller.define_function(&blob, &data.as_refs(), &[])?;
+                print_response(&response);
+                self.defined.insert(response.value.0, Defined::Function(response.value));
+                println!("function {}", response.value.0);
+            }
+            "call" => {
+                let (n, rest) = word(rest);
+                let token = match self.defined.get(&number(n)?) {
+                    Some(Defined::Function(token)) => *token,
+                    _ => return Err(invalid(format!("no function {}", n))),
+                };
+                let args = parse_generics(rest)?;
+                let response = self.controller.call_function(&token, &args.as_refs(), &[])?;
+                print_response(&response);
+                println!("{}", list(&response.value));
+            }
+            "free" => {
+                let (n, rest) = word(rest);
+                no_values(rest)?;
+                let n = number(n)?;
+                let response = match self.defined.get(&n) {
+                    Some(Defined::Function(token)) => self.controller.free_function(token, &[])?,
+                    Some(Defined::Data(token)) => self.controller.free_data(token, &[])?,
+                    None => return Err(invalid(format!("nothing defined as {}", n))),
+                };
+                self.defined.remove(&n);
+                print_response(&response);
+                println!("ok");
+            }
+            "shutdown" | "quit" | "exit" => {
+                self.controller.shutdown(&[])?;
+                return Ok(false);
+            }
+            "help" => println!("{}", HELP),
+            _ => return Err(invalid(format!("unknown command {:?}, try help", command))),
+        }
+        Ok(true)
+    }
+}
+
+fn word(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    let (word, rest) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+    (word, rest.trim_start())
+}
+
+fn number(word: &str) -> Result<u64> {
+    word.parse()
+        .map_err(|_| invalid(format!("expected a token number, got {:?}", word)))
+}
+
+fn no_values(rest: &str) -> Result<()> {
+    match rest.is_empty() {
+        true => Ok(()),
+        false => Err(invalid(format!("unexpected {:?}", rest))),
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn print_logs(logs: &[LogEntry]) {
+    for log in logs {
+        println!("{:?}: {}", log.log_type(), log.line());
+    }
+}
+
+fn print_response<T>(response: &Response<T>) {
+    print_logs(&response.logs);
+    if !response.response_aux.is_empty() {
+        println!("aux {}", list(&response.response_aux));
+    }
+}
+
+// a refusal from the subordinate comes with logs and aux worth seeing
+fn describe(e: &io::Error) -> String {
+    match e.get_ref().and_then(|e| e.downcast_ref::<RemoteError>()) {
+        Some(e) => {
+            print_logs(&e.logs);
+            format!("{:?} aux {}", e.err_type, list(&e.aux))
+        }
+        None => e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(mock: MockSubordinate) -> Session {
+        Session {
+            controller: mock.start().unwrap(),
+            defined: HashMap::new(),
+        }
+    }
+
+    fn lines(script: &str) -> impl Iterator<Item = Result<String>> + '_ {
+        script.lines().map(|line| Ok(line.to_string()))
+    }
+
+    #[test]
+    fn tokens_are_freed_as_what_they_were_defined_as_and_only_once() {
+        let mut session = session(
+            MockSubordinate::new()
+                .expect_define_data(vec![GenericValue::Vu8(1)])
+                .expect_define_function(b"", vec![])
+                .expect_call(FunctionToken(2), vec![GenericValue::Vu8(2)])
+                .respond(vec![GenericValue::Vu8(4)])
+                .expect_free_function(FunctionToken(2))
+                .expect_free_data(DataToken(1)),
+        );
+        let ran: Vec<bool> = [
+            "define-data 1u8",
+            "define-function /dev/null",
+            // data can't be called, only the function
+            "call 1 2u8",
+            "call 2 2u8",
+            "free 2",
+            "call 2 2u8",
+            "free 1",
+            "free 1",
+            "free 3",
+        ]
+        .iter()
+        .map(|line| session.run(line).is_ok())
+        .collect();
+        assert_eq!(ran, [true, true, false, true, true, false, true, false, false]);
+        assert!(session.defined.is_empty());
+        // the mock fails the shutdown if anything else reached it
+        assert!(!session.run("shutdown").unwrap());
+    }
+
+    #[test]
+    fn scripts_stop_at_the_first_error_and_typed_commands_carry_on() {
+        let script = "# a comment\n\ndefine-data 1u8\nfree 5\ndefine-data 2u8\n";
+        let mut stopped = session(MockSubordinate::new().expect_define_data(vec![1u8.into()]));
+        assert!(!stopped.run_lines(lines(script), false).unwrap());
+
+        let mut typed = session(
+            MockSubordinate::new()
+                .expect_define_data(vec![1u8.into()])
+                .expect_define_data(vec![2u8.into()]),
+        );
+        assert!(typed.run_lines(lines(script), true).unwrap());
+
+        // nothing after a shutdown is sent
+        let mut shut = session(MockSubordinate::new());
+        assert!(shut.run_lines(lines("shutdown\ndefine-data 1u8"), false).unwrap());
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    hash::{Hash, Hasher},
+    mem,
+    os::unix::io::AsRawFd,
+};
+
+use bytemuck::Pod;
+
+use crate::{GenericValue, GenericValueBoxed, GenericValueRef, ListRef, MapRef, PodSlice};
+
+// Floats compare by their bits, so NaN equals itself and -0.0 doesn't equal 0.0. That keeps
+// equality an equivalence and in step with the hash, which is what a cache needs. Values of
+// different types are never equal, `Vu8(1)` isn't `Vu16(1)`. File descriptors are equal when they
+// are the same descriptor.
+
+// lifetimes can differ, a `GenericValueRef` can't be shortened to match another
+impl<'b> PartialEq<GenericValueRef<'b>> for GenericValueRef<'_> {
+    fn eq(&self, other: &GenericValueRef<'b>) -> bool {
+        use GenericValue::*;
+        match (*self, *other) {
+            (Vu8(a), Vu8(b)) => a == b,
+            (Vi8(a), Vi8(b)) => a == b,
+            (Vu16(a), Vu16(b)) => a == b,
+            (Vi16(a), Vi16(b)) => a == b,
+            (Vu32(a), Vu32(b)) => a == b,
+            (Vi32(a), Vi32(b)) => a == b,
+            (Vu64(a), Vu64(b)) => a == b,
+            (Vi64(a), Vi64(b)) => a == b,
+            (Vf32(a), Vf32(b)) => a.to_bits() == b.to_bits(),
+            (Vf64(a), Vf64(b)) => a.to_bits() == b.to_bits(),
+            (Vusize(a), Vusize(b)) => a == b,
+            (Visize(a), Visize(b)) => a == b,
+            (Vbool(a), Vbool(b)) => a == b,
+            (Vstring(a), Vstring(b)) => a == b,
+            (Vbytes(a), Vbytes(b)) => a == b,
+            (Token(a), Token(b)) => a == b,
+            (Fd(a), Fd(b)) => a.as_raw_fd() == b.as_raw_fd(),
+            (List(a), List(b)) | (Tuple(a), Tuple(b)) => a == b,
+            (Map(a), Map(b)) | (Struct(a), Struct(b)) => a == b,
+            (Vu8s(a), Vu8s(b)) => a == b,
+            (Vi8s(a), Vi8s(b)) => a == b,
+            (Vu16s(a), Vu16s(b)) => a == b,
+            (Vi16s(a), Vi16s(b)) => a == b,
+            (Vu32s(a), Vu32s(b)) => a == b,
+            (Vi32s(a), Vi32s(b)) => a == b,
+            (Vu64s(a), Vu64s(b)) => a == b,
+            (Vi64s(a), Vi64s(b)) => a == b,
+            (Vf32s(a), Vf32s(b)) => a == b,
+            (Vf64s(a), Vf64s(b)) => a == b,
+            (Null, Null) => true,
+            (Function(a), Function(b)) => a == b,
+            (Marker(a), Marker(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for GenericValueRef<'_> {}
+
+impl Hash for GenericValueRef<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        use GenericValue::*;
+        mem::discriminant(self).hash(state);
+        match *self {
+            Vu8(v) => v.hash(state),
+            Vi8(v) => v.hash(state),
+            Vu16(v) => v.hash(state),
+            Vi16(v) => v.hash(state),
+            Vu32(v) => v.hash(state),
+            Vi32(v) => v.hash(state),
+            Vu64(v) => v.hash(state),
+            Vi64(v) => v.hash(state),
+            Vf32(v) => v.to_bits().hash(state),
+            Vf64(v) => v.to_bits().hash(state),
+            Vusize(v) => v.hash(state),
+            Visize(v) => v.hash(state),
+            Vbool(v) => v.hash(state),
+            Vstring(v) => v.hash(state),
+            Vbytes(v) => v.hash(state),
+            Token(v) => v.hash(state),
+            Fd(v) => v.as_raw_fd().hash(state),
+            List(v) | Tuple(v) => v.hash(state),
+            Map(v) | Struct(v) => v.hash(state),
+            Vu8s(v) => v.hash(state),
+            Vi8s(v) => v.hash(state),
+            Vu16s(v) => v.hash(state),
+            Vi16s(v) => v.hash(state),
+            Vu32s(v) => v.hash(state),
+            Vi32s(v) => v.hash(state),
+            Vu64s(v) => v.hash(state),
+            Vi64s(v) => v.hash(state),
+            Vf32s(v) => v.hash(state),
+            Vf64s(v) => v.hash(state),
+            Null => {}
+            Function(v) => v.hash(state),
+            Marker(v) => v.hash(state),
+        }
+    }
+}
+
+// owned values compare and hash as their borrowed form, so the two can be mixed freely
+
+impl PartialEq for GenericValueBoxed {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_ref() == other.as_ref()
+    }
+}
+
+impl Eq for GenericValueBoxed {}
+
+impl Hash for GenericValueBoxed {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_ref().hash(state)
+    }
+}
+
+impl PartialEq<GenericValueBoxed> for GenericValueRef<'_> {
+    fn eq(&self, other: &GenericValueBoxed) -> bool {
+        *self == other.as_ref()
+    }
+}
+
+impl PartialEq<GenericValueRef<'_>> for GenericValueBoxed {
+    fn eq(&self, other: &GenericValueRef<'_>) -> bool {
+        self.as_ref() == *other
+    }
+}
+
+impl<'b> PartialEq<ListRef<'b>> for ListRef<'_> {
+    fn eq(&self, other: &ListRef<'b>) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for ListRef<'_> {}
+
+impl Hash for ListRef<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len().hash(state);
+        self.iter().for_each(|v| v.hash(state));
+    }
+}
+
+// entries in order, the same entries in another order are a different map
+impl<'b> PartialEq<MapRef<'b>> for MapRef<'_> {
+    fn eq(&self, other: &MapRef<'b>) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .zip(other.iter())
+                .all(|((k, v), (other_k, other_v))| k == other_k && v == other_v)
+    }
+}
+
+impl Eq for MapRef<'_> {}
+
+impl Hash for MapRef<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len().hash(state);
+        self.iter().for_each(|entry| entry.hash(state));
+    }
+}
+
+// comparing the bytes is comparing the elements, floats included
+impl<'b, T: Pod> PartialEq<PodSlice<'b, T>> for PodSlice<'_, T> {
+    fn eq(&self, other: &PodSlice<'b, T>) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<T: Pod> Eq for PodSlice<'_, T> {}
+
+impl<T: Pod> Hash for PodSlice<'_, T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_bytes().hash(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::*;
+
+    #[test]
+    fn borrowed_and_owned_values_agree() {
+        let owned: GenericValueBoxed = GenericValue::List(vec![
+            GenericValue::Vf64(f64::NAN),
+            GenericValue::Vstring("a".to_string()),
+            GenericValue::Vf32s(vec![0.5, -0.0]),
+        ]);
+        let items = [
+            GenericValue::Vf64(f64::NAN),
+            GenericValue::Vstring("a"),
+            GenericValue::Vf32s([0.5f32, -0.0][..].into()),
+        ];
+        let borrowed = GenericValueRef::List(ListRef::Borrowed(&items));
+
+        assert_eq!(borrowed, owned);
+        assert_eq!(borrowed.to_boxed().unwrap(), owned);
+        assert_ne!(GenericValueRef::Vu8(1), GenericValueRef::Vu16(1));
+        assert_ne!(GenericValueRef::Vf64(0.0), GenericValueRef::Vf64(-0.0));
+
+        let mut seen = HashSet::new();
+        seen.insert(owned.as_ref());
+        assert!(seen.contains(&borrowed));
+    }
+}
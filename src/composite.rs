@@ -0,0 +1,108 @@
+use std::fmt;
+
+use crate::{GenericValueBoxed, GenericValueRef, Values};
+
+/// The borrowed form of a `List` or `Tuple`, over whichever kind of values it was built from.
+#[derive(Copy, Clone)]
+pub enum ListRef<'a> {
+    Borrowed(&'a [GenericValueRef<'a>]),
+    Boxed(&'a [GenericValueBoxed]),
+    // read into a `Frame`
+    Framed(Values<'a>),
+}
+
+impl<'a> ListRef<'a> {
+    pub fn len(&self) -> usize {
+        match self {
+            ListRef::Borrowed(values) => values.len(),
+            ListRef::Boxed(values) => values.len(),
+            ListRef::Framed(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<GenericValueRef<'a>> {
+        match *self {
+            ListRef::Borrowed(values) => values.get(index).copied(),
+            ListRef::Boxed(values) => values.get(index).map(GenericValueRef::from),
+            ListRef::Framed(values) => values.get(index),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = GenericValueRef<'a>> + 'a {
+        let list = *self;
+        (0..list.len()).map(move |index| list.get(index).expect("index within the list"))
+    }
+}
+
+impl<'a> From<&'a [GenericValueRef<'a>]> for ListRef<'a> {
+    fn from(values: &'a [GenericValueRef<'a>]) -> Self {
+        ListRef::Borrowed(values)
+    }
+}
+
+impl fmt::Debug for ListRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// The borrowed form of a `Map` or `Struct`, a list of key and value pairs.
+#[derive(Copy, Clone)]
+pub enum MapRef<'a> {
+    Borrowed(&'a [(GenericValueRef<'a>, GenericValueRef<'a>)]),
+    Boxed(&'a [(GenericValueBoxed, GenericValueBoxed)]),
+    // keys and values alternate
+    Framed(Values<'a>),
+}
+
+impl<'a> MapRef<'a> {
+    pub fn len(&self) -> usize {
+        match self {
+            MapRef::Borrowed(entries) => entries.len(),
+            MapRef::Boxed(entries) => entries.len(),
+            MapRef::Framed(values) => values.len() / 2,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn entry(&self, index: usize) -> Option<(GenericValueRef<'a>, GenericValueRef<'a>)> {
+        match *self {
+            MapRef::Borrowed(entries) => entries.get(index).copied(),
+            MapRef::Boxed(entries) => entries
+                .get(index)
+                .map(|(k, v)| (GenericValueRef::from(k), GenericValueRef::from(v))),
+            MapRef::Framed(values) => Some((values.get(2 * index)?, values.get(2 * index + 1)?)),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (GenericValueRef<'a>, GenericValueRef<'a>)> + 'a {
+        let map = *self;
+        (0..map.len()).map(move |index| map.entry(index).expect("index within the map"))
+    }
+
+    /// The value under the first key equal to the string `key`.
+    pub fn get(&self, key: &str) -> Option<GenericValueRef<'a>> {
+        self.iter()
+            .find(|(k, _)| matches!(k, GenericValueRef::Vstring(k) if *k == key))
+            .map(|(_, v)| v)
+    }
+}
+
+impl<'a> From<&'a [(GenericValueRef<'a>, GenericValueRef<'a>)]> for MapRef<'a> {
+    fn from(entries: &'a [(GenericValueRef<'a>, GenericValueRef<'a>)]) -> Self {
+        MapRef::Borrowed(entries)
+    }
+}
+
+impl fmt::Debug for MapRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
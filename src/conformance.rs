@@ -0,0 +1,383 @@
+use std::{
+    fmt, io,
+    os::unix::{
+        io::{AsFd, AsRawFd},
+        net::UnixStream,
+    },
+    process::Command,
+    result::Result,
+};
+
+use nix::sys::stat::fstat;
+
+use crate::{
+    endpoint::Subordinate,
+    replay::same_values,
+    trace::{into_remote_error, list},
+    *,
+};
+
+/// Whether one conformance case passed, and what went wrong if it didn't.
+#[derive(Debug)]
+pub struct ConformanceCase {
+    pub name: String,
+    pub failure: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct ConformanceReport {
+    pub cases: Vec<ConformanceCase>,
+}
+
+impl ConformanceReport {
+    pub fn passed(&self) -> bool {
+        self.cases.iter().all(|case| case.failure.is_none())
+    }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for case in &self.cases {
+            match &case.failure {
+                None => writeln!(f, "pass  {}", case.name)?,
+                Some(failure) => writeln!(f, "FAIL  {}: {}", case.name, failure)?,
+            }
+        }
+        let passed = self.cases.iter().filter(|case| case.failure.is_none()).count();
+        write!(f, "{} of {} cases passed", passed, self.cases.len())
+    }
+}
+
+/// Start `subordinate` and check that it speaks the protocol, whatever language it is written
+/// in. It is connected by a Unix socket, so file descriptors can be sent. The subordinate has to:
+///
+/// - answer every request with the request's aux as the response aux
+/// - define any function, and answer calls to one defined as `b"echo"` with the arguments it was
+///   called with, and calls to one defined as `b"fail"` with a `UserspaceException` whose aux is
+///   the arguments
+/// - refuse calls to a function that was freed or never defined with an error of any type
+/// - define and free any data
+/// - answer a peek with the values last poked under the key, or none
+/// - exit successfully once told goodbye
+///
+/// Every `SerializedType` is sent and expected back, along with empty, large and deeply nested
+/// values. A subordinate that stops answering fails every case after it, so file descriptors and
+/// function values, which need the most of it, are checked last. One that never answers hangs
+/// the check.
+pub fn check_conformance(subordinate: &mut Command) -> ConformanceReport {
+    match subordinate.start_subordinate_process_with_fds() {
+        Ok(controller) => check_connected(controller),
+        Err(e) => ConformanceReport {
+            cases: vec![ConformanceCase {
+                name: "hello".to_string(),
+                failure: Some(e.to_string()),
+            }],
+        },
+    }
+}
+
+enum Failure {
+    Wrong(String),
+    Io(io::Error),
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Self {
+        Failure::Io(e)
+    }
+}
+
+type Outcome = Result<(), Failure>;
+
+struct Checker {
+    controller: ControllerProcess,
+    report: ConformanceReport,
+    stopped: bool,
+}
+
+impl Checker {
+    fn check<F>(&mut self, name: impl Into<String>, case: F)
+    where
+        F: FnOnce(&mut ControllerProcess) -> Outcome,
+    {
+        let failure = match self.stopped {
+            true => Some("not run, the subordinate stopped answering".to_string()),
+            false => match case(&mut self.controller) {
+                Ok(()) => None,
+                Err(Failure::Wrong(message)) => Some(message),
+                Err(Failure::Io(e)) => match into_remote_error(e) {
+                    Ok(e) => Some(format!("refused with {:?} aux {}", e.err_type, brief(&e.aux))),
+                    Err(e) => {
+                        self.stopped = true;
+                        Some(e.to_string())
+                    }
+                },
+            },
+        };
+        self.report.cases.push(ConformanceCase {
+            name: name.into(),
+            failure,
+        });
+    }
+}
+
+// values as printed, cut short so a megabyte of bytes doesn't end up in the report
+fn brief(values: &[GenericValueBoxed]) -> String {
+    let mut printed = list(values);
+    if printed.len() > 120 {
+        let end = (0..=117).rev().find(|&i| printed.is_char_boundary(i)).unwrap_or(0);
+        printed.replace_range(end.., "...");
+    }
+    printed
+}
+
+fn aux() -> Vec<GenericValueBoxed> {
+    vec![GenericValue::Vstring("aux".to_string())]
+}
+
+fn expect_same(what: &str, sent: &[GenericValueBoxed], got: &[GenericValueBoxed]) -> Outcome {
+    match same_values(sent, got) {
+        true => Ok(()),
+        false => Err(Failure::Wrong(format!(
+            "sent {} {}, got {}",
+            what,
+            brief(sent),
+            brief(got)
+        ))),
+    }
+}
+
+fn expect_refused(response: io::Result<Response<Vec<GenericValueBoxed>>>) -> Result<RemoteError, Failure> {
+    match response {
+        Ok(response) => Err(Failure::Wrong(format!(
+            "answered {} instead of refusing",
+            brief(&response.value)
+        ))),
+        Err(e) => Ok(into_remote_error(e)?),
+    }
+}
+
+fn echo(controller: &mut ControllerProcess, token: FunctionToken, args: &[GenericValueBoxed]) -> Outcome {
+    let aux = aux();
+    let response = controller.call_function(&token, &args.as_refs(), &aux.as_refs())?;
+    expect_same("values", args, &response.value)?;
+    expect_same("aux", &aux, &response.response_aux)
+}
+
+fn define(controller: &mut ControllerProcess, blob: &[u8]) -> Result<FunctionToken, Failure> {
+    let aux = aux();
+    let response = controller.define_function(blob, &[], &aux.as_refs())?;
+    expect_same("aux", &aux, &response.response_aux)?;
+    Ok(response.value)
+}
+
+// every type at an edge of its range, file descriptors are checked on their own
+fn sample(serialized: SerializedType, echo: FunctionToken) -> Option<GenericValueBoxed> {
+    use GenericValue::*;
+    Some(match serialized {
+        SerializedType::Su8 => Vu8(u8::MAX),
+        SerializedType::Si8 => Vi8(i8::MIN),
+        SerializedType::Su16 => Vu16(u16::MAX),
+        SerializedType::Si16 => Vi16(i16::MIN),
+        SerializedType::Su32 => Vu32(u32::MAX),
+        SerializedType::Si32 => Vi32(i32::MIN),
+        SerializedType::Su64 => Vu64(u64::MAX),
+        SerializedType::Si64 => Vi64(i64::MIN),
+        SerializedType::Sf32 => Vf32(f32::MIN_POSITIVE),
+        SerializedType::Sf64 => Vf64(-1.5e300),
+        SerializedType::Susize => Vusize(usize::MAX),
+        SerializedType::Sisize => Visize(isize::MIN),
+        SerializedType::Sbool => Vbool(true),
+        SerializedType::Sstring => Vstring("ünïcødé ✓".to_string()),
+        SerializedType::Sbytes => Vbytes(vec![0, 255, b'\n']),
+        SerializedType::Token => Token(DataToken(u64::MAX)),
+        SerializedType::Marker => Marker(7),
+        SerializedType::Fd => return None,
+        SerializedType::List => List(vec![Vu8(1), Vstring("a".to_string())]),
+        SerializedType::Map => Map(vec![(Vstring("k".to_string()), Vi32(-1)), (Vu8(2), Null)]),
+        SerializedType::Tuple => Tuple(vec![Vbool(false)]),
+        SerializedType::Struct => Struct(vec![(Vstring("field".to_string()), Vf32(0.5))]),
+        SerializedType::Su8s => Vu8s(vec![0, u8::MAX]),
+        SerializedType::Si8s => Vi8s(vec![i8::MIN, i8::MAX]),
+        SerializedType::Su16s => Vu16s(vec![0, u16::MAX]),
+        SerializedType::Si16s => Vi16s(vec![i16::MIN, i16::MAX]),
+        SerializedType::Su32s => Vu32s(vec![0, u32::MAX]),
+        SerializedType::Si32s => Vi32s(vec![i32::MIN, i32::MAX]),
+        SerializedType::Su64s => Vu64s(vec![0, u64::MAX]),
+        SerializedType::Si64s => Vi64s(vec![i64::MIN, i64::MAX]),
+        SerializedType::Sf32s => Vf32s(vec![-0.0, f32::MAX]),
+        SerializedType::Sf64s => Vf64s(vec![f64::MIN_POSITIVE, f64::MAX]),
+        SerializedType::Null => Null,
+        SerializedType::Function => Function(echo),
+    })
+}
+
+// the same open file, even though it arrived as another descriptor
+fn same_file<A: AsFd, B: AsFd>(a: A, b: B) -> io::Result<bool> {
+    let a = fstat(a.as_fd().as_raw_fd()).map_err(io::Error::from)?;
+    let b = fstat(b.as_fd().as_raw_fd()).map_err(io::Error::from)?;
+    Ok((a.st_dev, a.st_ino) == (b.st_dev, b.st_ino))
+}
+
+// values that need more of a subordinate than the rest, file descriptors a socket to arrive over
+// and functions a registry to be checked against. They are echoed last, so one that chokes on
+// them doesn't take the other cases down with it
+const CAPABILITIES: [SerializedType; 2] = [SerializedType::Fd, SerializedType::Function];
+
+fn check_echo(checker: &mut Checker, serialized: SerializedType, echo_token: FunctionToken) {
+    let name = format!("echo {:?}", serialized);
+    match sample(serialized, echo_token) {
+        Some(value) => checker.check(name, |c| echo(c, echo_token, &[value])),
+        None => checker.check(name, |c| {
+            let (socket, _other_end) = UnixStream::pair()?;
+            let aux = aux();
+            let sent = [GenericValueRef::Fd(socket.as_fd())];
+            let response = c.call_function(&echo_token, &sent, &aux.as_refs())?;
+            match response.value.as_slice() {
+                [GenericValue::Fd(fd)] if same_file(fd, &socket)? => {
+                    expect_same("aux", &aux, &response.response_aux)
+                }
+                got => Err(Failure::Wrong(format!("sent a socket, got {}", brief(got)))),
+            }
+        }),
+    }
+}
+
+pub(crate) fn check_connected(controller: ControllerProcess) -> ConformanceReport {
+    let mut checker = Checker {
+        controller,
+        report: ConformanceReport::default(),
+        stopped: false,
+    };
+    checker.check("hello", |_| Ok(()));
+
+    let mut echo_token = FunctionToken(0);
+    checker.check("define function", |c| {
+        echo_token = define(c, b"echo")?;
+        Ok(())
+    });
+    let echo_token = echo_token;
+
+    for serialized in (0..=u8::MAX).filter_map(|code| SerializedType::try_from(code).ok()) {
+        if !CAPABILITIES.contains(&serialized) {
+            check_echo(&mut checker, serialized, echo_token);
+        }
+    }
+
+    checker.check("echo no values", |c| echo(c, echo_token, &[]));
+    checker.check("echo empty values", |c| {
+        let empty = [
+            GenericValue::Vstring(String::new()),
+            GenericValue::Vbytes(Vec::new()),
+            GenericValue::List(Vec::new()),
+            GenericValue::Map(Vec::new()),
+            GenericValue::Tuple(Vec::new()),
+            GenericValue::Vu8s(Vec::new()),
+            GenericValue::Vf64s(Vec::new()),
+        ];
+        echo(c, echo_token, &empty)
+    });
+    checker.check("echo a megabyte of bytes", |c| {
+        echo(c, echo_token, &[GenericValue::Vbytes(vec![0xa5; 1 << 20])])
+    });
+    checker.check("echo 10000 values", |c| {
+        let many: Vec<GenericValueBoxed> = (0..10000u32).map(GenericValue::Vu32).collect();
+        echo(c, echo_token, &many)
+    });
+    checker.check(format!("echo lists nested {} deep", MAX_GENERIC_DEPTH), |c| {
+        let nested = (0..MAX_GENERIC_DEPTH).fold(GenericValue::Null, |inner, _| {
+            GenericValue::List(vec![inner])
+        });
+        echo(c, echo_token, &[nested])
+    });
+
+    checker.check("define and free data", |c| {
+        let aux = aux();
+        let response = c.define_data(&[GenericValue::Vu8(1)], &aux.as_refs())?;
+        expect_same("aux", &aux, &response.response_aux)?;
+        let response = c.free_data(&response.value, &aux.as_refs())?;
+        expect_same("aux", &aux, &response.response_aux)
+    });
+    checker.check("poke then peek", |c| {
+        let aux = aux();
+        let value = [GenericValue::Vstring("poked".to_string()), GenericValue::Vu8(2)];
+        let response = c.poke("conformance", &value.as_refs(), &aux.as_refs())?;
+        expect_same("aux", &aux, &response.response_aux)?;
+        let response = c.peek("conformance", &aux.as_refs())?;
+        expect_same("aux", &aux, &response.response_aux)?;
+        expect_same("a poke of", &value, &response.value)
+    });
+    checker.check("peek a key never poked", |c| {
+        let response = c.peek("conformance never poked", &[])?;
+        expect_same("no poke, expected", &[GenericValue::Null], &response.value)
+    });
+
+    checker.check("error from a failing function", |c| {
+        let fail = define(c, b"fail")?;
+        let args = [GenericValue::Vstring("failing".to_string())];
+        let e = expect_refused(c.call_function(&fail, &args.as_refs(), &[]))?;
+        match e.err_type {
+            RemoteErrorType::UserspaceException => expect_same("values", &args, &e.aux),
+            other => Err(Failure::Wrong(format!("refused with {:?}", other))),
+        }
+    });
+    checker.check("error calling a freed function", |c| {
+        let freed = define(c, b"echo")?;
+        let aux = aux();
+        let response = c.free_function(&freed, &aux.as_refs())?;
+        expect_same("aux", &aux, &response.response_aux)?;
+        expect_refused(c.call_function(&freed, &[], &[]))?;
+        Ok(())
+    });
+    checker.check("answers after errors", |c| echo(c, echo_token, &[GenericValue::Vu8(1)]));
+
+    for serialized in CAPABILITIES {
+        check_echo(&mut checker, serialized, echo_token);
+    }
+    checker.check("error for a function value never defined", |c| {
+        let unknown = [GenericValueRef::Function(FunctionToken(u64::MAX))];
+        let e = expect_refused(c.call_function(&echo_token, &unknown, &[]))?;
+        match e.err_type {
+            RemoteErrorType::ProtocolError => Ok(()),
+            other => Err(Failure::Wrong(format!("refused with {:?}", other))),
+        }
+    });
+
+    checker.check("shutdown", |c| {
+        c.shutdown(&aux().as_refs())?;
+        if let Subordinate::Process(child) = &mut c.subordinate {
+            let status = child.wait()?;
+            if !status.success() {
+                return Err(Failure::Wrong(format!("exited with {}", status)));
+            }
+        }
+        Ok(())
+    });
+    checker.report
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::net::UnixStream, thread};
+
+    use super::*;
+    use crate::{testing::serve, transport::SocketTransport};
+
+    #[test]
+    fn a_conforming_subordinate_passes_every_case() {
+        let (a, b) = UnixStream::pair().unwrap();
+        let served = thread::spawn(move || {
+            let mut subordinate = SubordinateProcess::new(Box::new(SocketTransport::new(b)));
+            subordinate.hello()?;
+            serve(subordinate)
+        });
+        let mut controller =
+            ControllerProcess::new(Subordinate::Connected, Box::new(SocketTransport::new(a)));
+        controller.hello().unwrap();
+
+        let report = check_connected(controller);
+        served.join().unwrap().unwrap();
+        assert!(report.passed(), "{}", report);
+        assert!(report.cases.len() > 40);
+    }
+}
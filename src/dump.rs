@@ -0,0 +1,18 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Result},
+};
+
+use ufo_ipc::dump_trace;
+
+// ufo-ipc-dump TRACE, prints a trace written by `trace_to`
+fn main() -> Result<()> {
+    let path = match std::env::args_os().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: ufo-ipc-dump TRACE");
+            std::process::exit(2);
+        }
+    };
+    dump_trace(BufReader::new(File::open(path)?), io::stdout().lock())
+}
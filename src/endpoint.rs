@@ -1,21 +1,79 @@
-use os_pipe::{PipeReader, PipeWriter};
 // use std::{process::Child, thread::Thread};
-use std::{process::Child};
+use std::{collections::HashSet, io, process::Child};
+
+#[cfg(feature = "tracing")]
+use crate::spans::Traffic;
+use crate::{
+    arena::SharedArenas, mock::MockThread, protocol::RegistryChange, stats::StatsRecorder,
+    supervisor::Supervisor, trace::Tracer, transport::Transport, FunctionToken,
+};
+
+pub(crate) enum Subordinate {
+    // a child process we started
+    Process(Child),
+    // someone else's process, reached over a socket
+    Connected,
+    // a thread answering as a test told it to
+    Mock(MockThread),
+}
+
+impl Subordinate {
+    pub(crate) fn wait(&mut self) -> io::Result<()> {
+        match self {
+            Subordinate::Process(child) => {
+                child.wait()?;
+            }
+            Subordinate::Mock(mock) => mock.finish()?,
+            Subordinate::Connected => {}
+        }
+        Ok(())
+    }
+}
+
 pub struct ControllerProcess {
-    pub(crate) subordinate: Child,
-    pub(crate) cmd_out: PipeWriter,
-    pub(crate) cmd_in: PipeReader,
+    pub(crate) subordinate: Subordinate,
+    pub(crate) transport: Box<dyn Transport>,
+    pub(crate) arenas: Option<SharedArenas>,
 
     pub(crate) id_ctr: u64,
+    pub(crate) supervisor: Option<Supervisor>,
+    pub(crate) tracer: Option<Tracer>,
+    pub(crate) recorder: StatsRecorder,
+    // the trace every request is sent as part of, rather than one each
+    #[cfg(feature = "tracing")]
+    pub(crate) trace_context: Option<u64>,
 }
 
 impl ControllerProcess {
-    pub(crate) fn new(subordinate: Child, cmd_out: PipeWriter, cmd_in: PipeReader) -> Self {
+    pub(crate) fn new(subordinate: Subordinate, transport: Box<dyn Transport>) -> Self {
         ControllerProcess {
             subordinate,
-            cmd_in,
-            cmd_out,
+            transport,
+            arenas: None,
             id_ctr: 0,
+            supervisor: None,
+            tracer: None,
+            recorder: StatsRecorder::default(),
+            #[cfg(feature = "tracing")]
+            trace_context: None,
+        }
+    }
+
+    /// A subordinate reached over a socket is alive until it closes its end, which a
+    /// subordinate that hangs without exiting never does.
+    pub fn is_alive(&mut self) -> bool {
+        match &mut self.subordinate {
+            Subordinate::Process(child) => matches!(child.try_wait(), Ok(None)),
+            Subordinate::Connected => !self.transport.hung_up(),
+            Subordinate::Mock(_) => true,
+        }
+    }
+
+    // kill and reap the subordinate, for when it can no longer be talked to
+    pub(crate) fn terminate(&mut self) {
+        if let Subordinate::Process(child) = &mut self.subordinate {
+            let _ = child.kill();
+            let _ = child.wait();
         }
     }
 }
@@ -27,47 +85,90 @@ impl ControllerProcess {
 // }
 
 pub struct SubordinateProcess {
-    pub(crate) cmd_out: PipeWriter,
-    pub(crate) cmd_in: PipeReader,
+    pub(crate) transport: Box<dyn Transport>,
+    pub(crate) arenas: Option<SharedArenas>,
+    // the functions defined and not yet freed, a `Function` value has to name one of them
+    pub(crate) functions: HashSet<FunctionToken>,
+    // what the command being answered does to `functions`, if it is answered
+    pub(crate) pending: RegistryChange,
+    // sent along with the last command read, see `trace_context`
+    pub(crate) trace_context: Option<u64>,
+    #[cfg(feature = "tracing")]
+    pub(crate) traffic: Traffic,
     // pub(crate) stdout_reader: ConsoleReaderThread,
     // pub(crate) stderr_reader: ConsoleReaderThread,
 }
 
-pub(crate) mod sealed {
-    use os_pipe::{PipeReader, PipeWriter};
-    use std::{io, io::Write};
+impl SubordinateProcess {
+    pub(crate) fn new(transport: Box<dyn Transport>) -> Self {
+        SubordinateProcess {
+            transport,
+            arenas: None,
+            functions: HashSet::new(),
+            pending: RegistryChange::None,
+            trace_context: None,
+            #[cfg(feature = "tracing")]
+            traffic: Traffic::default(),
+        }
+    }
+}
 
-    use crate::*;
+pub(crate) mod sealed {
+    use std::io;
 
-    pub struct Pipes<'a> {
-        pub reader: &'a mut PipeReader,
-        pub writer: &'a mut PipeWriter,
-    }
+    use crate::{arena::SharedArenas, transport::Transport, *};
 
     pub trait Endpoint {
-        fn pipes(&mut self) -> Pipes;
+        fn transport(&mut self) -> &mut dyn Transport;
+
+        fn arenas(&mut self) -> Option<&mut SharedArenas>;
 
         fn flush(&mut self) -> io::Result<&mut Self> {
-            self.pipes().writer.flush()?;
+            self.transport().flush()?;
             Ok(self)
         }
+
+        // bytes that went through the transport, for the controller's stats
+        fn count_traffic(&mut self, _sent: usize, _received: usize) {}
+
+        // whatever a message we failed to read had placed in the arena is handed back
+        fn read_whole<T>(&mut self, read: io::Result<T>) -> io::Result<T> {
+            if read.is_err() {
+                if let Some(arenas) = self.arenas() {
+                    arenas.release_unread();
+                }
+            }
+            read
+        }
     }
 
     impl Endpoint for ControllerProcess {
-        fn pipes(&mut self) -> Pipes {
-            Pipes {
-                reader: &mut self.cmd_in,
-                writer: &mut self.cmd_out,
-            }
+        fn transport(&mut self) -> &mut dyn Transport {
+            self.transport.as_mut()
+        }
+
+        fn arenas(&mut self) -> Option<&mut SharedArenas> {
+            self.arenas.as_mut()
+        }
+
+        fn count_traffic(&mut self, sent: usize, received: usize) {
+            self.recorder.count_traffic(sent, received);
         }
     }
 
     impl Endpoint for SubordinateProcess {
-        fn pipes(&mut self) -> Pipes {
-            Pipes {
-                reader: &mut self.cmd_in,
-                writer: &mut self.cmd_out,
-            }
+        fn transport(&mut self) -> &mut dyn Transport {
+            self.transport.as_mut()
+        }
+
+        fn arenas(&mut self) -> Option<&mut SharedArenas> {
+            self.arenas.as_mut()
+        }
+
+        #[cfg(feature = "tracing")]
+        fn count_traffic(&mut self, sent: usize, received: usize) {
+            self.traffic.sent += sent as u64;
+            self.traffic.received += received as u64;
         }
     }
 }
@@ -30,6 +30,30 @@ pub enum ProtocolError {
 
     #[error("Unknown generic type {0}")]
     UnknownGenericType(u8),
+
+    #[error("File descriptors can only be passed over a Unix socket")]
+    FdPassingUnsupported,
+
+    #[error("Expected a file descriptor, but none was received")]
+    MissingFd,
+
+    #[error("Received bytes in a shared arena, but no arena is attached")]
+    NoArena,
+
+    #[error("Shared arena of {0} bytes is too small")]
+    ArenaTooSmall(usize),
+
+    #[error("Shared arena can be resized, it has to be sealed against shrinking and growing")]
+    ArenaNotSealed,
+
+    #[error("Shared bytes at {offset} of length {len} are outside the arena")]
+    ArenaOutOfBounds { offset: u64, len: u64 },
+
+    #[error("Values nested more than {0} deep")]
+    TooDeep(usize),
+
+    #[error("Array of {bytes} bytes is not a whole number of {element} byte elements")]
+    ArrayLength { bytes: usize, element: usize },
 }
 
 impl From<ProtocolError> for io::Error {
@@ -51,7 +75,26 @@ impl From<UnexpectedGenericType> for io::Error {
     }
 }
 
-#[derive(Debug, TryFromPrimitive)]
+#[derive(Error, Debug)]
+pub enum FromGenericsError {
+    #[error("Field {field}: {source}")]
+    Field {
+        field: &'static str,
+        #[source]
+        source: UnexpectedGenericType,
+    },
+
+    #[error("Expected {expected} values, got {actual}")]
+    WrongCount { expected: usize, actual: usize },
+}
+
+impl From<FromGenericsError> for io::Error {
+    fn from(e: FromGenericsError) -> Self {
+        io::Error::new(io::ErrorKind::Other, e)
+    }
+}
+
+#[derive(Debug, TryFromPrimitive, Copy, Clone, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum RemoteErrorType {
     UserspaceException,
@@ -72,3 +115,96 @@ impl From<RemoteError> for io::Error {
         io::Error::new(io::ErrorKind::Other, e)
     }
 }
+
+#[derive(Debug, Error)]
+pub enum PoolError {
+    #[error("A subordinate pool needs at least one worker")]
+    NoWorkers,
+}
+
+impl From<PoolError> for io::Error {
+    fn from(e: PoolError) -> Self {
+        io::Error::new(io::ErrorKind::Other, e)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SupervisorError {
+    #[error("Subordinate crashed and has already been restarted {0} times")]
+    RestartLimitReached(usize),
+}
+
+impl From<SupervisorError> for io::Error {
+    fn from(e: SupervisorError) -> Self {
+        io::Error::new(io::ErrorKind::Other, e)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum MockError {
+    #[error("Mock subordinate didn't get the requests it expected: {}", .0.join("; "))]
+    Failed(Vec<String>),
+}
+
+impl From<MockError> for io::Error {
+    fn from(e: MockError) -> Self {
+        io::Error::new(io::ErrorKind::Other, e)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ParseGenericError {
+    #[error("Expected {expected} at {at}")]
+    Expected { expected: &'static str, at: usize },
+
+    #[error("Invalid literal {literal:?} at {at}")]
+    Invalid { literal: String, at: usize },
+
+    #[error("Values nested more than {depth} deep at {at}")]
+    TooDeep { depth: usize, at: usize },
+}
+
+impl From<ParseGenericError> for io::Error {
+    fn from(e: ParseGenericError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, e)
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(Debug, Error)]
+pub enum SerdeError {
+    #[error("{0}")]
+    Message(String),
+
+    #[error(transparent)]
+    UnexpectedGenericType(#[from] UnexpectedGenericType),
+}
+
+#[cfg(feature = "serde")]
+impl serde::ser::Error for SerdeError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        SerdeError::Message(msg.to_string())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::de::Error for SerdeError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        SerdeError::Message(msg.to_string())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<SerdeError> for io::Error {
+    fn from(e: SerdeError) -> Self {
+        io::Error::new(io::ErrorKind::Other, e)
+    }
+}
+
+// errors that mean the other end of the pipes has gone away
+pub(crate) fn is_disconnect(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::BrokenPipe | io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset
+    )
+}
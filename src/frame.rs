@@ -0,0 +1,183 @@
+use std::{
+    fmt,
+    os::unix::io::{AsFd, OwnedFd},
+};
+
+use bytemuck::Pod;
+
+use crate::{GenericStorage, GenericValue, GenericValueRef, ListRef, MapRef, PodSlice};
+
+// where the contents of a value ended up in the frame
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Span {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+}
+
+// a run of slots, the values of a list or the alternating keys and values of a map
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Run {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct SpanStorage;
+
+impl GenericStorage for SpanStorage {
+    type Bytes = Span;
+    type Str = Span;
+    type Fd = usize;
+    type List = Run;
+    type Map = Run;
+    type Array<T: Pod + fmt::Debug> = Span;
+}
+
+pub(crate) type Slot = GenericValue<SpanStorage>;
+
+/// A buffer that a whole message is read into, handing back `GenericValueRef`s that borrow from
+/// it. Reading the next message clears it, keeping the allocations, so a loop that reuses one
+/// `Frame` stops allocating once it has seen its largest message.
+#[derive(Default)]
+pub struct Frame {
+    pub(crate) buf: Vec<u8>,
+    pub(crate) slots: Vec<Slot>,
+    // the values of the lists still being read, innermost last
+    pub(crate) pending: Vec<Slot>,
+    fds: Vec<OwnedFd>,
+}
+
+impl Frame {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.buf.clear();
+        self.slots.clear();
+        self.pending.clear();
+        self.fds.clear();
+    }
+
+    // moves the values pending since `mark` into slots of their own, as one run
+    pub(crate) fn finish_run(&mut self, mark: usize) -> Run {
+        let start = self.slots.len();
+        self.slots.extend(self.pending.drain(mark..));
+        Run {
+            start,
+            end: self.slots.len(),
+        }
+    }
+
+    pub(crate) fn push_fd(&mut self, fd: OwnedFd) -> usize {
+        self.fds.push(fd);
+        self.fds.len() - 1
+    }
+
+    pub(crate) fn bytes(&self, span: Span) -> &[u8] {
+        &self.buf[span.start..span.end]
+    }
+
+    pub(crate) fn str(&self, span: Span) -> &str {
+        // checked as it was read, and only the frame writes to its own buffer
+        unsafe { std::str::from_utf8_unchecked(self.bytes(span)) }
+    }
+
+    fn resolve(&self, slot: Slot) -> GenericValueRef<'_> {
+        match slot {
+            GenericValue::Vu8(v) => GenericValue::Vu8(v),
+            GenericValue::Vi8(v) => GenericValue::Vi8(v),
+            GenericValue::Vu16(v) => GenericValue::Vu16(v),
+            GenericValue::Vi16(v) => GenericValue::Vi16(v),
+            GenericValue::Vu32(v) => GenericValue::Vu32(v),
+            GenericValue::Vi32(v) => GenericValue::Vi32(v),
+            GenericValue::Vu64(v) => GenericValue::Vu64(v),
+            GenericValue::Vi64(v) => GenericValue::Vi64(v),
+            GenericValue::Vf32(v) => GenericValue::Vf32(v),
+            GenericValue::Vf64(v) => GenericValue::Vf64(v),
+            GenericValue::Vusize(v) => GenericValue::Vusize(v),
+            GenericValue::Visize(v) => GenericValue::Visize(v),
+            GenericValue::Vbool(v) => GenericValue::Vbool(v),
+            GenericValue::Vstring(span) => GenericValue::Vstring(self.str(span)),
+            GenericValue::Vbytes(span) => GenericValue::Vbytes(self.bytes(span)),
+            GenericValue::Token(v) => GenericValue::Token(v),
+            GenericValue::Fd(index) => GenericValue::Fd(self.fds[index].as_fd()),
+            GenericValue::List(run) => GenericValue::List(ListRef::Framed(self.values(run))),
+            GenericValue::Map(run) => GenericValue::Map(MapRef::Framed(self.values(run))),
+            GenericValue::Tuple(run) => GenericValue::Tuple(ListRef::Framed(self.values(run))),
+            GenericValue::Struct(run) => GenericValue::Struct(MapRef::Framed(self.values(run))),
+            GenericValue::Vu8s(span) => {
+                GenericValue::Vu8s(PodSlice::from_bytes(self.bytes(span)))
+            }
+            GenericValue::Vi8s(span) => {
+                GenericValue::Vi8s(PodSlice::from_bytes(self.bytes(span)))
+            }
+            GenericValue::Vu16s(span) => {
+                GenericValue::Vu16s(PodSlice::from_bytes(self.bytes(span)))
+            }
+            GenericValue::Vi16s(span) => {
+                GenericValue::Vi16s(PodSlice::from_bytes(self.bytes(span)))
+            }
+            GenericValue::Vu32s(span) => {
+                GenericValue::Vu32s(PodSlice::from_bytes(self.bytes(span)))
+            }
+            GenericValue::Vi32s(span) => {
+                GenericValue::Vi32s(PodSlice::from_bytes(self.bytes(span)))
+            }
+            GenericValue::Vu64s(span) => {
+                GenericValue::Vu64s(PodSlice::from_bytes(self.bytes(span)))
+            }
+            GenericValue::Vi64s(span) => {
+                GenericValue::Vi64s(PodSlice::from_bytes(self.bytes(span)))
+            }
+            GenericValue::Vf32s(span) => {
+                GenericValue::Vf32s(PodSlice::from_bytes(self.bytes(span)))
+            }
+            GenericValue::Vf64s(span) => {
+                GenericValue::Vf64s(PodSlice::from_bytes(self.bytes(span)))
+            }
+            GenericValue::Null => GenericValue::Null,
+            GenericValue::Function(v) => GenericValue::Function(v),
+            GenericValue::Marker(v) => GenericValue::Marker(v),
+        }
+    }
+
+    pub(crate) fn values(&self, run: Run) -> Values<'_> {
+        Values {
+            frame: self,
+            slots: &self.slots[run.start..run.end],
+        }
+    }
+}
+
+/// A run of values read into a `Frame`.
+#[derive(Copy, Clone)]
+pub struct Values<'f> {
+    frame: &'f Frame,
+    slots: &'f [Slot],
+}
+
+impl<'f> Values<'f> {
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<GenericValueRef<'f>> {
+        self.slots.get(index).map(|slot| self.frame.resolve(*slot))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = GenericValueRef<'f>> + 'f {
+        let frame = self.frame;
+        self.slots.iter().map(move |slot| frame.resolve(*slot))
+    }
+}
+
+impl fmt::Debug for Values<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
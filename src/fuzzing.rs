@@ -0,0 +1,63 @@
+use crate::{
+    endpoint::Subordinate, serialization::sealed::SerializationEndpoint, trace::into_remote_error,
+    transport::MemoryTransport, *,
+};
+
+// Entry points for the targets in fuzz/, each feeding untrusted bytes to an endpoint over a
+// MemoryTransport. Anything the endpoint writes back is thrown away. Errors are expected, only
+// panics, hangs and runaway allocations are bugs. Values that were read are formatted, so every
+// span in a frame gets resolved. Run them with `cargo +nightly fuzz run <target>` from fuzz/.
+
+fn subordinate(bytes: &[u8]) -> SubordinateProcess {
+    SubordinateProcess::new(Box::new(MemoryTransport::new(bytes, false)))
+}
+
+/// Read `bytes` as a list of generic values, allocated and into a frame.
+pub fn read_generics(bytes: &[u8]) {
+    if let Ok(values) = subordinate(bytes).read_generic_vec() {
+        let _ = format!("{:?}", values);
+    }
+    let mut frame = Frame::new();
+    if let Ok(run) = subordinate(bytes).read_generic_vec_in(&mut frame) {
+        let _ = format!("{:?}", frame.values(run));
+    }
+}
+
+/// Read `bytes` as the commands a controller sends, allocated and into a frame, until one fails.
+pub fn recv_commands(bytes: &[u8]) {
+    let mut endpoint = subordinate(bytes);
+    while let Ok(request) = endpoint.recv_command() {
+        let _ = format!("{:?}", request);
+    }
+    let mut endpoint = subordinate(bytes);
+    let mut frame = Frame::new();
+    while let Ok(request) = endpoint.recv_command_in(&mut frame) {
+        let _ = format!("{:?}", request);
+    }
+}
+
+/// Read `bytes` as the responses a subordinate sends to calls, alternately allocated and into a
+/// frame, until one fails other than by being an error response.
+pub fn read_responses(bytes: &[u8]) {
+    let mut controller = ControllerProcess::new(
+        Subordinate::Connected,
+        Box::new(MemoryTransport::new(bytes, false)),
+    );
+    let mut frame = Frame::new();
+    let token = FunctionToken(1);
+    for framed in [false, true].into_iter().cycle() {
+        let read = match framed {
+            false => controller
+                .call_function(&token, &[], &[])
+                .map(|response| format!("{:?}", response)),
+            true => controller
+                .call_function_in(&token, &[], &[], &mut frame)
+                .map(|response| format!("{:?}", response)),
+        };
+        if let Err(e) = read {
+            if into_remote_error(e).is_err() {
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::{os::unix::io::OwnedFd, result::Result};
+
+use crate::{
+    DataToken, FromGenericsError, FunctionToken, GenericValue, GenericValueBoxed,
+    GenericValueRef, UnexpectedGenericType,
+};
+
+#[cfg(feature = "derive")]
+pub use ufo_ipc_derive::{FromGenerics, IntoGenerics};
+
+/// A type that can be sent as a run of generic values, such as the arguments of a call.
+/// `#[derive(IntoGenerics)]` spreads a struct's fields over the values in order.
+pub trait IntoGenerics {
+    fn into_generics(self) -> Vec<GenericValueBoxed>;
+
+    fn to_generics(&self) -> Vec<GenericValueRef<'_>>;
+}
+
+/// A type that can be rebuilt from a run of generic values. `#[derive(FromGenerics)]` takes a
+/// struct's fields from the values in order.
+pub trait FromGenerics: Sized {
+    fn from_generics(values: Vec<GenericValueBoxed>) -> Result<Self, FromGenericsError>;
+}
+
+/// A type that a single generic value can be taken apart into.
+pub trait FromGenericValue: Sized {
+    fn from_generic_value(value: GenericValueBoxed) -> Result<Self, UnexpectedGenericType>;
+
+    /// Like `from_generic_value`, but numbers of a narrower type are widened when std has a
+    /// lossless `From` between them, so a `Vu8` is accepted as a `u32` or an `f64`.
+    fn coerce_generic_value(value: GenericValueBoxed) -> Result<Self, UnexpectedGenericType> {
+        Self::from_generic_value(value)
+    }
+}
+
+macro_rules! from_generic_value {
+    ($t:ty, $into:ident $(; $($cons:ident),*)?) => {
+        impl FromGenericValue for $t {
+            fn from_generic_value(value: GenericValueBoxed) -> Result<Self, UnexpectedGenericType> {
+                value.$into()
+            }
+
+            $(fn coerce_generic_value(value: GenericValueBoxed) -> Result<Self, UnexpectedGenericType> {
+                match value {
+                    $(GenericValue::$cons(v) => Ok(v.into()),)*
+                    value => value.$into(),
+                }
+            })?
+        }
+    };
+}
+
+from_generic_value!(u8, expect_u8_into);
+from_generic_value!(i8, expect_i8_into);
+from_generic_value!(u16, expect_u16_into; Vu8);
+from_generic_value!(i16, expect_i16_into; Vu8, Vi8);
+from_generic_value!(u32, expect_u32_into; Vu8, Vu16);
+from_generic_value!(i32, expect_i32_into; Vu8, Vi8, Vu16, Vi16);
+from_generic_value!(u64, expect_u64_into; Vu8, Vu16, Vu32);
+from_generic_value!(i64, expect_i64_into; Vu8, Vi8, Vu16, Vi16, Vu32, Vi32);
+from_generic_value!(f32, expect_f32_into; Vu8, Vi8, Vu16, Vi16);
+from_generic_value!(f64, expect_f64_into; Vu8, Vi8, Vu16, Vi16, Vu32, Vi32, Vf32);
+from_generic_value!(usize, expect_usize_into; Vu8, Vu16);
+from_generic_value!(isize, expect_isize_into; Vu8, Vi8, Vi16);
+from_generic_value!(bool, expect_bool_into);
+from_generic_value!(String, expect_string_into);
+from_generic_value!(Vec<u8>, expect_bytes_into);
+from_generic_value!(DataToken, expect_token_into);
+from_generic_value!(FunctionToken, expect_function_into);
+from_generic_value!(OwnedFd, expect_fd_into);
+from_generic_value!(Vec<GenericValueBoxed>, expect_list_into);
+from_generic_value!(Vec<i8>, expect_i8s_into);
+from_generic_value!(Vec<u16>, expect_u16s_into);
+from_generic_value!(Vec<i16>, expect_i16s_into);
+from_generic_value!(Vec<u32>, expect_u32s_into);
+from_generic_value!(Vec<i32>, expect_i32s_into);
+from_generic_value!(Vec<u64>, expect_u64s_into);
+from_generic_value!(Vec<i64>, expect_i64s_into);
+from_generic_value!(Vec<f32>, expect_f32s_into);
+from_generic_value!(Vec<f64>, expect_f64s_into);
+
+// Null is `None`, anything else has to be a `T`
+impl<T: FromGenericValue> FromGenericValue for Option<T> {
+    fn from_generic_value(value: GenericValueBoxed) -> Result<Self, UnexpectedGenericType> {
+        match value {
+            GenericValue::Null => Ok(None),
+            value => T::from_generic_value(value).map(Some),
+        }
+    }
+
+    fn coerce_generic_value(value: GenericValueBoxed) -> Result<Self, UnexpectedGenericType> {
+        match value {
+            GenericValue::Null => Ok(None),
+            value => T::coerce_generic_value(value).map(Some),
+        }
+    }
+}
+
+impl FromGenericValue for GenericValueBoxed {
+    fn from_generic_value(value: GenericValueBoxed) -> Result<Self, UnexpectedGenericType> {
+        Ok(value)
+    }
+}
+
+/// Takes a number with `FromGenericValue::coerce_generic_value`, as a tuple element or a field.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Lossless<T>(pub T);
+
+impl<T: FromGenericValue> FromGenericValue for Lossless<T> {
+    fn from_generic_value(value: GenericValueBoxed) -> Result<Self, UnexpectedGenericType> {
+        T::coerce_generic_value(value).map(Lossless)
+    }
+}
+
+macro_rules! try_from_generic_value {
+    ($($t:ty),*) => {
+        $(impl TryFrom<GenericValueBoxed> for $t {
+            type Error = UnexpectedGenericType;
+
+            fn try_from(value: GenericValueBoxed) -> Result<Self, Self::Error> {
+                FromGenericValue::from_generic_value(value)
+            }
+        })*
+    };
+}
+
+try_from_generic_value!(
+    u8, i8, u16, i16, u32, i32, u64, i64, f32, f64, usize, isize, bool, String, Vec<u8>,
+    DataToken, FunctionToken
+);
+
+macro_rules! from_generics_tuple {
+    ($count:literal; $($t:ident),*) => {
+        impl<$($t: FromGenericValue),*> FromGenerics for ($($t,)*) {
+            fn from_generics(values: Vec<GenericValueBoxed>) -> Result<Self, FromGenericsError> {
+                const FIELDS: [&str; 8] = ["0", "1", "2", "3", "4", "5", "6", "7"];
+                if values.len() != $count {
+                    return Err(FromGenericsError::WrongCount {
+                        expected: $count,
+                        actual: values.len(),
+                    });
+                }
+                let mut values = values.into_iter().zip(FIELDS);
+                Ok(($({
+                    let (value, field) = values.next().expect("counted above");
+                    $t::from_generic_value(value)
+                        .map_err(|source| FromGenericsError::Field { field, source })?
+                },)*))
+            }
+        }
+    };
+}
+
+from_generics_tuple!(1; A);
+from_generics_tuple!(2; A, B);
+from_generics_tuple!(3; A, B, C);
+from_generics_tuple!(4; A, B, C, D);
+from_generics_tuple!(5; A, B, C, D, E);
+from_generics_tuple!(6; A, B, C, D, E, F);
+from_generics_tuple!(7; A, B, C, D, E, F, G);
+from_generics_tuple!(8; A, B, C, D, E, F, G, H);
+
+/// `values.try_into_generics()` for any `FromGenerics`, so the type can come from the binding,
+/// as in `let (a, b): (u64, String) = response.value.try_into_generics()?`.
+pub trait TryIntoGenerics {
+    fn try_into_generics<T: FromGenerics>(self) -> Result<T, FromGenericsError>;
+}
+
+impl TryIntoGenerics for Vec<GenericValueBoxed> {
+    fn try_into_generics<T: FromGenerics>(self) -> Result<T, FromGenericsError> {
+        T::from_generics(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tuples_take_values_in_order_and_coerce_when_asked() {
+        let values = vec![GenericValue::Vu64(7), GenericValue::Vstring("seven".to_string())];
+        let (a, b): (u64, String) = values.try_into_generics().unwrap();
+        assert_eq!((a, b.as_str()), (7, "seven"));
+
+        let narrow = || vec![GenericValue::Vu8(200), GenericValue::Vf32(0.5)];
+        assert!(narrow().try_into_generics::<(u32, f64)>().is_err());
+        let (Lossless(a), Lossless(b)): (Lossless<u32>, Lossless<f64>) =
+            narrow().try_into_generics().unwrap();
+        assert_eq!((a, b), (200, 0.5));
+
+        // widening only, a negative number never becomes unsigned
+        assert!(Lossless::<u64>::from_generic_value(GenericValue::Vi8(-1)).is_err());
+        let (a, b): (Option<String>, Option<u8>) =
+            vec![GenericValue::Null, GenericValue::from(Some(3u8))].try_into_generics().unwrap();
+        assert_eq!((a, b), (None, Some(3)));
+
+        assert!(matches!(
+            vec![GenericValue::Vu8(1)].try_into_generics::<(u8, u8)>(),
+            Err(FromGenericsError::WrongCount { expected: 2, actual: 1 })
+        ));
+    }
+}
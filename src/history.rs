@@ -0,0 +1,140 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+};
+
+use crate::*;
+
+enum Definition {
+    Function {
+        token: FunctionToken,
+        function_blob: Vec<u8>,
+        associated_data: Vec<GenericValueBoxed>,
+        aux: Vec<GenericValueBoxed>,
+    },
+    Data {
+        token: DataToken,
+        value: Vec<GenericValueBoxed>,
+        aux: Vec<GenericValueBoxed>,
+    },
+    Poke {
+        key: String,
+        value: Vec<GenericValueBoxed>,
+        aux: Vec<GenericValueBoxed>,
+    },
+}
+
+/// Everything a subordinate has been told to define and not yet told to free, along with the last
+/// value poked into each key, so the same state can be rebuilt in a fresh subordinate.
+#[derive(Default)]
+pub(crate) struct SubordinateState {
+    // replayed in the order they were first sent, data can refer to earlier data through a Token
+    // value and a poke can change how later definitions are handled
+    sequence: u64,
+    definitions: BTreeMap<u64, Definition>,
+    // from function and data tokens, and poked keys, to where they sit in the sequence
+    tokens: HashMap<u64, u64>,
+    pokes: HashMap<String, u64>,
+}
+
+impl SubordinateState {
+    fn push(&mut self, definition: Definition) -> u64 {
+        self.sequence += 1;
+        self.definitions.insert(self.sequence, definition);
+        self.sequence
+    }
+
+    fn remove_token(&mut self, token: u64) {
+        if let Some(sequence) = self.tokens.remove(&token) {
+            self.definitions.remove(&sequence);
+        }
+    }
+
+    pub fn define_function(
+        &mut self,
+        token: FunctionToken,
+        function_blob: &[u8],
+        associated_data: &[GenericValueRef],
+        aux: &[GenericValueRef],
+    ) -> io::Result<()> {
+        let sequence = self.push(Definition::Function {
+            token,
+            function_blob: function_blob.to_vec(),
+            associated_data: associated_data.to_boxed()?,
+            aux: aux.to_boxed()?,
+        });
+        self.tokens.insert(token.0, sequence);
+        Ok(())
+    }
+
+    pub fn define_data(
+        &mut self,
+        token: DataToken,
+        value: &[GenericValueRef],
+        aux: &[GenericValueRef],
+    ) -> io::Result<()> {
+        let sequence = self.push(Definition::Data {
+            token,
+            value: value.to_boxed()?,
+            aux: aux.to_boxed()?,
+        });
+        self.tokens.insert(token.0, sequence);
+        Ok(())
+    }
+
+    pub fn free_function(&mut self, token: &FunctionToken) {
+        self.remove_token(token.0);
+    }
+
+    pub fn free_data(&mut self, token: &DataToken) {
+        self.remove_token(token.0);
+    }
+
+    // only the latest poke to a key is kept
+    pub fn poke(
+        &mut self,
+        key: &str,
+        value: &[GenericValueRef],
+        aux: &[GenericValueRef],
+    ) -> io::Result<()> {
+        let value = value.to_boxed()?;
+        let aux = aux.to_boxed()?;
+        if let Some(sequence) = self.pokes.remove(key) {
+            self.definitions.remove(&sequence);
+        }
+        let sequence = self.push(Definition::Poke {
+            key: key.to_string(),
+            value,
+            aux,
+        });
+        self.pokes.insert(key.to_string(), sequence);
+        Ok(())
+    }
+
+    pub fn replay(&self, controller: &mut ControllerProcess) -> io::Result<()> {
+        for definition in self.definitions.values() {
+            match definition {
+                Definition::Function {
+                    token,
+                    function_blob,
+                    associated_data,
+                    aux,
+                } => {
+                    controller.define_function_as(
+                        *token,
+                        function_blob,
+                        &associated_data.as_refs(),
+                        &aux.as_refs(),
+                    )?;
+                }
+                Definition::Data { token, value, aux } => {
+                    controller.define_data_as(*token, &value.as_refs(), &aux.as_refs())?;
+                }
+                Definition::Poke { key, value, aux } => {
+                    controller.poke(key, &value.as_refs(), &aux.as_refs())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,14 @@
+// errors are wrapped with `io::Error::new(ErrorKind::Other, ..)` throughout
+#![allow(clippy::io_other_error)]
+
 // use libc::{dup2, STDERR_FILENO, STDOUT_FILENO};
 use os_pipe::{PipeReader, PipeWriter};
 use std::{
     io::Result,
-    os::unix::prelude::{AsRawFd, FromRawFd},
+    os::unix::{
+        net::UnixStream,
+        prelude::{AsRawFd, FromRawFd, OwnedFd},
+    },
     process::{Command, Stdio},
     str::FromStr,
 };
@@ -10,9 +16,16 @@ use std::{
 mod pipe;
 use pipe::*;
 
+mod transport;
+pub use transport::Transport;
+use transport::{inheritable_copy, is_socket, PipeTransport, SocketTransport};
+
 mod err;
 pub use err::*;
 
+mod arena;
+pub use arena::ARENA_MIN_PAYLOAD;
+
 mod endpoint;
 pub use endpoint::{sealed::*, *};
 
@@ -22,8 +35,87 @@ pub use protocol::*;
 mod serialization;
 pub use serialization::*;
 
+mod frame;
+pub use frame::{Frame, Values};
+
+mod composite;
+pub use composite::*;
+
+mod array;
+pub use array::PodSlice;
+
+mod generics;
+pub use generics::*;
+
+mod compare;
+
+mod parse;
+pub use parse::parse_generics;
+
+#[cfg(feature = "serde")]
+mod serde_support;
+#[cfg(feature = "serde")]
+pub use serde_support::{from_generic, to_generic};
+
+mod trace;
+pub use trace::{dump_trace, list, Exchange, Message, RecordedSession, Recording, Reply};
+
+mod replay;
+pub use replay::Divergence;
+
+mod mock;
+pub use mock::MockSubordinate;
+
+mod conformance;
+pub use conformance::*;
+
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
+
+mod stats;
+pub use stats::{Histogram, Stats};
+
+#[cfg(feature = "tracing")]
+mod spans;
+
+mod history;
+
+mod pool;
+pub use pool::*;
+
+mod supervisor;
+pub use supervisor::RestartPolicy;
+
+// seccomp filters are written for the syscall tables of these two
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod sandbox;
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub use sandbox::*;
+
+mod listener;
+pub use listener::*;
+
+#[cfg(test)]
+mod testing;
+
 pub trait StartSubordinateProcess {
     fn start_subordinate_process(&mut self) -> Result<ControllerProcess>;
+
+    /// Like `start_subordinate_process`, but connected by a Unix socket rather than a pair of
+    /// pipes, so `GenericValue::Fd` values and shared arenas can be passed.
+    fn start_subordinate_process_with_fds(&mut self) -> Result<ControllerProcess> {
+        Err(ProtocolError::FdPassingUnsupported.into())
+    }
+}
+
+pub trait StartSupervisedSubordinateProcess {
+    /// Start a subordinate that is restarted if it crashes, with every function, data and poke
+    /// it held replayed into the new one so existing tokens keep working.
+    fn start_supervised_subordinate_process(
+        self,
+        policy: RestartPolicy,
+    ) -> Result<ControllerProcess>;
 }
 
 const SUB_IN_ENV: &str = "UFO_SUBORDINATE_PIPEFD_IN";
@@ -45,8 +137,10 @@ impl StartSubordinateProcess for Command {
             .env(SUB_OUT_ENV, child_pipefd_out.as_raw_fd().to_string())
             .spawn()?;
 
-        let mut controller =
-            ControllerProcess::new(subordinate, parent_to_child.1, child_to_parent.0);
+        let mut controller = ControllerProcess::new(
+            Subordinate::Process(subordinate),
+            Box::new(PipeTransport::new(child_to_parent.0, parent_to_child.1)),
+        );
 
         controller.hello()?;
 
@@ -56,6 +150,47 @@ impl StartSubordinateProcess for Command {
 
         Ok(controller)
     }
+
+    fn start_subordinate_process_with_fds(&mut self) -> Result<ControllerProcess> {
+        // the subordinate still gets an in and an out descriptor, both are its end of the socket
+        let (controller_end, subordinate_end) = UnixStream::pair()?;
+
+        let child_pipefd_in = inheritable_copy(&subordinate_end)?;
+        let child_pipefd_out = inheritable_copy(&subordinate_end)?;
+
+        let subordinate = self
+            .stderr(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stdin(Stdio::null())
+            .env(SUB_IN_ENV, child_pipefd_in.as_raw_fd().to_string())
+            .env(SUB_OUT_ENV, child_pipefd_out.as_raw_fd().to_string())
+            .spawn()?;
+
+        let mut controller = ControllerProcess::new(
+            Subordinate::Process(subordinate),
+            Box::new(SocketTransport::new(controller_end)),
+        );
+
+        controller.hello()?;
+
+        // child process good and started, drop our copies of their side of the socket
+        std::mem::drop(child_pipefd_in);
+        std::mem::drop(child_pipefd_out);
+        std::mem::drop(subordinate_end);
+
+        Ok(controller)
+    }
+}
+
+impl StartSupervisedSubordinateProcess for Command {
+    fn start_supervised_subordinate_process(
+        mut self,
+        policy: RestartPolicy,
+    ) -> Result<ControllerProcess> {
+        let mut controller = self.start_subordinate_process()?;
+        controller.supervisor = Some(supervisor::Supervisor::new(self, policy));
+        Ok(controller)
+    }
 }
 
 pub fn subordinate_begin() -> Result<SubordinateProcess> {
@@ -69,8 +204,16 @@ pub fn subordinate_begin() -> Result<SubordinateProcess> {
     let pipe_out =
         i32::from_str(&pipe_out).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
 
-    let cmd_in = unsafe { PipeReader::from_raw_fd(pipe_in) };
-    let cmd_out = unsafe { PipeWriter::from_raw_fd(pipe_out) };
+    let transport: Box<dyn Transport> = if is_socket(pipe_in) {
+        if pipe_out != pipe_in {
+            std::mem::drop(unsafe { OwnedFd::from_raw_fd(pipe_out) });
+        }
+        Box::new(SocketTransport::new(unsafe { UnixStream::from_raw_fd(pipe_in) }))
+    } else {
+        let cmd_in = unsafe { PipeReader::from_raw_fd(pipe_in) };
+        let cmd_out = unsafe { PipeWriter::from_raw_fd(pipe_out) };
+        Box::new(PipeTransport::new(cmd_in, cmd_out))
+    };
 
     // let (stdout_r, stdout_w) = pipe2_nocloexec()?;
     // let (stderr_r, stderr_w) = pipe2_nocloexec()?;
@@ -80,7 +223,7 @@ pub fn subordinate_begin() -> Result<SubordinateProcess> {
     //     dup2(stderr_w.as_raw_fd(), STDERR_FILENO);
     // }
 
-    let mut sub = SubordinateProcess { cmd_in, cmd_out };
+    let mut sub = SubordinateProcess::new(transport);
 
     sub.hello()?;
 
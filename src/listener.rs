@@ -0,0 +1,130 @@
+use std::{
+    fs, io,
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+};
+
+use crate::{endpoint::Subordinate, transport::SocketTransport, *};
+
+/// A subordinate that is already running and waits for controllers on a Unix socket.
+///
+/// Each accepted connection is a `SubordinateProcess` of its own, served until the controller says
+/// goodbye or goes away, so an expensive to start subordinate can outlive its controllers.
+pub struct SubordinateListener {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl SubordinateListener {
+    /// A socket file left behind by a listener that is no longer running is replaced.
+    pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let listener = match UnixListener::bind(path) {
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse && is_stale_socket(path) => {
+                fs::remove_file(path)?;
+                UnixListener::bind(path)?
+            }
+            result => result?,
+        };
+
+        Ok(SubordinateListener {
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Wait for the next controller and greet it.
+    pub fn accept(&self) -> io::Result<SubordinateProcess> {
+        let (stream, _) = self.listener.accept()?;
+        let mut sub = SubordinateProcess::new(Box::new(SocketTransport::new(stream)));
+        sub.hello()?;
+        Ok(sub)
+    }
+
+    pub fn incoming(&self) -> impl Iterator<Item = io::Result<SubordinateProcess>> + '_ {
+        std::iter::repeat_with(move || self.accept())
+    }
+}
+
+impl Drop for SubordinateListener {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn is_stale_socket(path: &Path) -> bool {
+    let is_socket = fs::symlink_metadata(path)
+        .map(|m| m.file_type().is_socket())
+        .unwrap_or(false);
+    is_socket
+        && matches!(
+            UnixStream::connect(path),
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused
+        )
+}
+
+impl ControllerProcess {
+    /// Connect to a subordinate served by a `SubordinateListener`.
+    ///
+    /// Shutting the controller down only ends this connection, the subordinate keeps running.
+    pub fn connect<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let stream = UnixStream::connect(path)?;
+        let mut controller = ControllerProcess::new(
+            Subordinate::Connected,
+            Box::new(SocketTransport::new(stream)),
+        );
+        controller.hello()?;
+        Ok(controller)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::testing::serve;
+
+    #[test]
+    fn controllers_come_and_go_while_the_subordinate_stays() {
+        let path = std::env::temp_dir().join(format!("ufo_ipc_listener_{}", std::process::id()));
+        // what a listener that was killed leaves behind
+        drop(UnixListener::bind(&path).unwrap());
+        let listener = SubordinateListener::bind(&path).unwrap();
+        assert_eq!(listener.path(), path);
+
+        thread::scope(|scope| {
+            let served = scope.spawn(|| -> io::Result<()> {
+                for subordinate in listener.incoming().take(2) {
+                    serve(subordinate?)?;
+                }
+                // the third is let go without an answer
+                drop(listener.accept()?);
+                Ok(())
+            });
+
+            for i in 0..2u8 {
+                let mut controller = ControllerProcess::connect(&path).unwrap();
+                let echo = controller.define_function(b"echo", &[], &[]).unwrap().value;
+                let value = controller.call_function(&echo, &[i.into()], &[]).unwrap().value;
+                assert_eq!(value, [GenericValueBoxed::Vu8(i)]);
+                assert!(controller.is_alive());
+                controller.shutdown(&[]).unwrap();
+            }
+
+            let mut controller = ControllerProcess::connect(&path).unwrap();
+            served.join().unwrap().unwrap();
+            assert!(!controller.is_alive());
+        });
+
+        drop(listener);
+        assert!(!path.exists());
+    }
+}
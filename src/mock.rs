@@ -0,0 +1,255 @@
+use std::{
+    collections::VecDeque,
+    io,
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    panic,
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    endpoint::Subordinate, replay::same_command, transport::SocketTransport, AsRefGenerics,
+    ControllerProcess, DataToken, FunctionToken, GenericValueBoxed, MockError, ProtocolCommand,
+    RemoteErrorType, SubordinateProcess,
+};
+
+enum MockReply {
+    Values(Vec<GenericValueBoxed>),
+    Error(RemoteErrorType),
+}
+
+struct Expectation {
+    command: ProtocolCommand,
+    reply: MockReply,
+}
+
+/// A subordinate for unit testing controller code, answering from a list of expected requests
+/// rather than running a child process.
+///
+/// Requests have to arrive in the order they are expected, each is answered with what was given
+/// to `respond` or `fail` after it, or with no values. Anything else is refused with a
+/// `ProtocolError`. Functions and data get tokens counting up from 1 in the order they are
+/// defined, as with a real subordinate, and the `expect_define_*` methods hand out the same ones.
+/// Aux values aren't checked.
+///
+/// An unexpected request, or an expected one that never arrived, fails `shutdown` with a
+/// `MockError`. A controller dropped without shutting down panics instead.
+#[derive(Default)]
+pub struct MockSubordinate {
+    expectations: Vec<Expectation>,
+    id_ctr: u64,
+}
+
+impl MockSubordinate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn expect(mut self, command: ProtocolCommand) -> Self {
+        self.expectations.push(Expectation {
+            command,
+            reply: MockReply::Values(Vec::new()),
+        });
+        self
+    }
+
+    pub fn expect_define_function(
+        mut self,
+        function_blob: &[u8],
+        associated_data: Vec<GenericValueBoxed>,
+    ) -> Self {
+        self.id_ctr += 1;
+        let token = FunctionToken(self.id_ctr);
+        self.expect(ProtocolCommand::DefineFunction {
+            token,
+            function_blob: function_blob.to_vec(),
+            associated_data,
+        })
+    }
+
+    pub fn expect_define_data(mut self, value: Vec<GenericValueBoxed>) -> Self {
+        self.id_ctr += 1;
+        let token = DataToken(self.id_ctr);
+        self.expect(ProtocolCommand::DefineData { token, value })
+    }
+
+    pub fn expect_call(self, token: FunctionToken, args: Vec<GenericValueBoxed>) -> Self {
+        self.expect(ProtocolCommand::Call { token, args })
+    }
+
+    pub fn expect_free_function(self, token: FunctionToken) -> Self {
+        self.expect(ProtocolCommand::FreeFunction(token))
+    }
+
+    pub fn expect_free_data(self, token: DataToken) -> Self {
+        self.expect(ProtocolCommand::FreeData(token))
+    }
+
+    pub fn expect_peek(self, key: &str) -> Self {
+        self.expect(ProtocolCommand::Peek(key.to_string()))
+    }
+
+    pub fn expect_poke(self, key: &str, value: Vec<GenericValueBoxed>) -> Self {
+        self.expect(ProtocolCommand::Poke {
+            key: key.to_string(),
+            value,
+        })
+    }
+
+    fn reply(mut self, reply: MockReply) -> Self {
+        self.expectations
+            .last_mut()
+            .expect("a reply follows the request it answers")
+            .reply = reply;
+        self
+    }
+
+    /// Answer the last expected request with `values`, which only calls and peeks return.
+    pub fn respond(self, values: Vec<GenericValueBoxed>) -> Self {
+        self.reply(MockReply::Values(values))
+    }
+
+    /// Refuse the last expected request with an error of `error_type`.
+    pub fn fail(self, error_type: RemoteErrorType) -> Self {
+        self.reply(MockReply::Error(error_type))
+    }
+
+    /// Start answering, on a thread of its own, and connect a controller to it.
+    pub fn start(self) -> io::Result<ControllerProcess> {
+        let (controller_end, subordinate_end) = UnixStream::pair()?;
+        let stream = controller_end.try_clone()?;
+        let expectations = self.expectations.into();
+        let thread = thread::spawn(move || {
+            let mut subordinate =
+                SubordinateProcess::new(Box::new(SocketTransport::new(subordinate_end)));
+            match subordinate.hello() {
+                Ok(()) => serve(subordinate, expectations),
+                Err(e) => vec![format!("no hello: {}", e)],
+            }
+        });
+
+        let mock = MockThread {
+            stream,
+            thread: Some(thread),
+        };
+        let mut controller = ControllerProcess::new(
+            Subordinate::Mock(mock),
+            Box::new(SocketTransport::new(controller_end)),
+        );
+        controller.hello()?;
+        Ok(controller)
+    }
+}
+
+// answers until the controller says goodbye or goes away, and returns what went wrong
+fn serve(mut subordinate: SubordinateProcess, mut expected: VecDeque<Expectation>) -> Vec<String> {
+    let mut failures = Vec::new();
+    while let Ok(request) = subordinate.recv_command() {
+        if let ProtocolCommand::Shutdown = request.command {
+            break;
+        }
+        let expectation = match expected.front() {
+            Some(e) if same_command(&e.command, &request.command) => expected.pop_front().unwrap(),
+            next => {
+                let failure = match next {
+                    Some(e) => format!("expected {:?}, got {:?}", e.command, request.command),
+                    None => format!("got {:?} after everything expected", request.command),
+                };
+                let refused = subordinate
+                    .respond_with_error(RemoteErrorType::ProtocolError, &[failure.as_str().into()]);
+                failures.push(failure);
+                match refused {
+                    Ok(()) => continue,
+                    Err(_) => break,
+                }
+            }
+        };
+
+        let answered = match (expectation.reply, &request.command) {
+            (MockReply::Error(error_type), _) => subordinate.respond_with_error(error_type, &[]),
+            (MockReply::Values(values), ProtocolCommand::Call { .. } | ProtocolCommand::Peek(_)) => {
+                subordinate.respond_to_call(&values.as_refs(), &[])
+            }
+            (MockReply::Values(_), _) => subordinate.respond_to_define(&[]),
+        };
+        if answered.is_err() {
+            break;
+        }
+    }
+    failures.extend(
+        expected
+            .into_iter()
+            .map(|e| format!("expected {:?}, never got it", e.command)),
+    );
+    failures
+}
+
+pub(crate) struct MockThread {
+    // the controller's end, to hang up on the thread when it hasn't been told goodbye
+    stream: UnixStream,
+    thread: Option<JoinHandle<Vec<String>>>,
+}
+
+impl MockThread {
+    pub(crate) fn finish(&mut self) -> io::Result<()> {
+        let thread = match self.thread.take() {
+            Some(thread) => thread,
+            None => return Ok(()),
+        };
+        let _ = self.stream.shutdown(Shutdown::Write);
+        let failures = thread.join().unwrap_or_else(|panic| panic::resume_unwind(panic));
+        match failures.is_empty() {
+            true => Ok(()),
+            false => Err(MockError::Failed(failures).into()),
+        }
+    }
+}
+
+impl Drop for MockThread {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            if !thread::panicking() {
+                panic!("{}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn mocks_answer_what_they_expect_and_report_the_rest() {
+        let mut controller = MockSubordinate::new()
+            .expect_define_function(b"f", vec![])
+            .expect_call(FunctionToken(1), vec![GenericValue::Vu8(2)])
+            .respond(vec![GenericValue::Vu8(4)])
+            .expect_peek("missing")
+            .fail(RemoteErrorType::UserspaceException)
+            .start()
+            .unwrap();
+
+        let token = controller.define_function(b"f", &[], &[]).unwrap().value;
+        let value = controller.call_function(&token, &[2u8.into()], &[]).unwrap().value;
+        assert_eq!(value, [GenericValueBoxed::Vu8(4)]);
+        let e = controller.peek("missing", &[]).unwrap_err();
+        let e = e.get_ref().unwrap().downcast_ref::<RemoteError>().unwrap();
+        assert_eq!(e.err_type, RemoteErrorType::UserspaceException);
+        controller.shutdown(&[]).unwrap();
+
+        let mut controller = MockSubordinate::new().expect_peek("a").start().unwrap();
+        assert!(controller.peek("b", &[]).is_err());
+        let e = controller.shutdown(&[]).unwrap_err();
+        let failures = match e.get_ref().unwrap().downcast_ref::<MockError>().unwrap() {
+            MockError::Failed(failures) => failures.clone(),
+        };
+        assert_eq!(
+            failures,
+            [
+                "expected Peek(\"a\"), got Peek(\"b\")",
+                "expected Peek(\"a\"), never got it",
+            ]
+        );
+    }
+}
@@ -0,0 +1,414 @@
+use std::{result::Result, str::FromStr};
+
+use crate::{
+    DataToken, FunctionToken, GenericValue, GenericValueBoxed, ParseGenericError,
+    MAX_GENERIC_DEPTH,
+};
+
+// Generic values written out by hand, mostly as `Display` prints them:
+//
+//   42  -7i8  300u16  2usize        integers are i64 unless suffixed with their type
+//   1.5  0.25f32                     floats are f64 unless suffixed with f32
+//   true  false  null  "text"  b"bytes\x00"
+//   token 3  function 1
+//   [1, 2]  (1, "a")  (1,)           lists and tuples
+//   {"a": 1}  {name: "x", size: 2}  maps, or structs when the keys are bare names
+//   u8s[1, 2]  f64s[0.5]             packed arrays, one for each numeric type
+//
+// Strings and byte strings take the escapes \\ \" \n \r \t \0, byte strings also \xNN.
+
+const SUFFIXES: [&str; 12] = [
+    "u8", "i8", "u16", "i16", "u32", "i32", "u64", "i64", "f32", "f64", "usize", "isize",
+];
+
+impl FromStr for GenericValueBoxed {
+    type Err = ParseGenericError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { s, at: 0, depth: 0 };
+        let value = parser.value()?;
+        parser.end()?;
+        Ok(value)
+    }
+}
+
+/// Parse values separated by whitespace or commas, see `GenericValueBoxed::from_str` for how
+/// each is written.
+pub fn parse_generics(s: &str) -> Result<Vec<GenericValueBoxed>, ParseGenericError> {
+    let mut parser = Parser { s, at: 0, depth: 0 };
+    let mut values = Vec::new();
+    loop {
+        parser.skip_space();
+        parser.eat(',');
+        parser.skip_space();
+        if parser.rest().is_empty() {
+            return Ok(values);
+        }
+        values.push(parser.value()?);
+    }
+}
+
+struct Parser<'s> {
+    s: &'s str,
+    at: usize,
+    // how many lists, tuples and maps the value being parsed is in
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.s[self.at..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.at += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.at += c.len_utf8();
+        }
+        found
+    }
+
+    fn skip_space(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    fn expected(&self, expected: &'static str) -> ParseGenericError {
+        ParseGenericError::Expected {
+            expected,
+            at: self.at,
+        }
+    }
+
+    fn expect(&mut self, c: char, expected: &'static str) -> Result<(), ParseGenericError> {
+        self.skip_space();
+        match self.eat(c) {
+            true => Ok(()),
+            false => Err(self.expected(expected)),
+        }
+    }
+
+    fn end(&mut self) -> Result<(), ParseGenericError> {
+        self.skip_space();
+        match self.rest().is_empty() {
+            true => Ok(()),
+            false => Err(self.expected("the end")),
+        }
+    }
+
+    // the longest run of characters that could make up a name or a number
+    fn word(&mut self) -> &str {
+        let start = self.at;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-' | '+'))
+        {
+            self.bump();
+        }
+        &self.s[start..self.at]
+    }
+
+    // no deeper than a peer would read, which also keeps the recursion off the end of the stack
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseGenericError>,
+    ) -> Result<T, ParseGenericError> {
+        if self.depth == MAX_GENERIC_DEPTH {
+            return Err(ParseGenericError::TooDeep {
+                depth: MAX_GENERIC_DEPTH,
+                at: self.at,
+            });
+        }
+        self.depth += 1;
+        let parsed = parse(self);
+        self.depth -= 1;
+        parsed
+    }
+
+    fn value(&mut self) -> Result<GenericValueBoxed, ParseGenericError> {
+        self.skip_space();
+        let start = self.at;
+        match self.peek() {
+            Some('"') => return self.string().map(GenericValue::Vstring),
+            Some('[') => {
+                return self.nested(|p| p.items('[', ']', Self::value)).map(GenericValue::List)
+            }
+            Some('(') => {
+                return self.nested(|p| p.items('(', ')', Self::value)).map(GenericValue::Tuple)
+            }
+            Some('{') => return self.nested(Self::map),
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '+' => return self.number(),
+            _ => {}
+        }
+        if self.rest().starts_with("b\"") {
+            self.bump();
+            return self.bytes().map(GenericValue::Vbytes);
+        }
+
+        let word = self.word().to_string();
+        match word.as_str() {
+            "true" => Ok(GenericValue::Vbool(true)),
+            "false" => Ok(GenericValue::Vbool(false)),
+            "null" => Ok(GenericValue::Null),
+            "token" => Ok(GenericValue::Token(DataToken(self.index()?))),
+            "function" => Ok(GenericValue::Function(FunctionToken(self.index()?))),
+            array if keyword(array) => {
+                self.array(&array[..array.len() - 1], start)
+            }
+            _ => {
+                self.at = start;
+                Err(self.expected("a value"))
+            }
+        }
+    }
+
+    fn index(&mut self) -> Result<u64, ParseGenericError> {
+        self.skip_space();
+        let start = self.at;
+        let word = self.word();
+        word.parse().map_err(|_| ParseGenericError::Invalid {
+            literal: word.to_string(),
+            at: start,
+        })
+    }
+
+    fn items(
+        &mut self,
+        open: char,
+        close: char,
+        item: fn(&mut Self) -> Result<GenericValueBoxed, ParseGenericError>,
+    ) -> Result<Vec<GenericValueBoxed>, ParseGenericError> {
+        self.eat(open);
+        let mut items = Vec::new();
+        loop {
+            self.skip_space();
+            if self.eat(close) {
+                return Ok(items);
+            }
+            items.push(item(self)?);
+            self.skip_space();
+            if !self.eat(',') {
+                self.expect(close, "a comma or the end of the list")?;
+                return Ok(items);
+            }
+        }
+    }
+
+    // packed arrays hold nothing but numbers
+    fn number(&mut self) -> Result<GenericValueBoxed, ParseGenericError> {
+        self.skip_space();
+        let start = self.at;
+        let word = self.word().to_string();
+        match word.is_empty() {
+            true => Err(self.expected("a number")),
+            false => number(&word).ok_or(ParseGenericError::Invalid { literal: word, at: start }),
+        }
+    }
+
+    fn array(&mut self, element: &str, start: usize) -> Result<GenericValueBoxed, ParseGenericError> {
+        self.skip_space();
+        if self.peek() != Some('[') {
+            return Err(self.expected("["));
+        }
+        let items = self.items('[', ']', Self::number)?;
+        // every item as the element type, however it was written
+        let literals: Vec<String> = items
+            .iter()
+            .map(|item| match item {
+                GenericValue::Vi64(v) => Some(v.to_string()),
+                GenericValue::Vf64(v) => Some(format!("{:?}", v)),
+                _ => None,
+            })
+            .collect::<Option<_>>()
+            .ok_or_else(|| ParseGenericError::Invalid {
+                literal: self.s[start..self.at].to_string(),
+                at: start,
+            })?;
+        let invalid = |literal: &String| ParseGenericError::Invalid {
+            literal: literal.clone(),
+            at: start,
+        };
+        macro_rules! elements {
+            ($t:ty) => {
+                literals
+                    .iter()
+                    .map(|l| l.parse::<$t>().map_err(|_| invalid(l)))
+                    .collect::<Result<Vec<$t>, _>>()?
+            };
+        }
+        Ok(match element {
+            "u8" => GenericValue::Vu8s(elements!(u8)),
+            "i8" => GenericValue::Vi8s(elements!(i8)),
+            "u16" => GenericValue::Vu16s(elements!(u16)),
+            "i16" => GenericValue::Vi16s(elements!(i16)),
+            "u32" => GenericValue::Vu32s(elements!(u32)),
+            "i32" => GenericValue::Vi32s(elements!(i32)),
+            "u64" => GenericValue::Vu64s(elements!(u64)),
+            "i64" => GenericValue::Vi64s(elements!(i64)),
+            "f32" => GenericValue::Vf32s(elements!(f32)),
+            "f64" => GenericValue::Vf64s(elements!(f64)),
+            _ => return Err(ParseGenericError::Invalid {
+                literal: format!("{}s", element),
+                at: start,
+            }),
+        })
+    }
+
+    fn map(&mut self) -> Result<GenericValueBoxed, ParseGenericError> {
+        self.eat('{');
+        let mut entries = Vec::new();
+        let mut fields = None;
+        loop {
+            self.skip_space();
+            if self.eat('}') {
+                break;
+            }
+            let start = self.at;
+            let word = self.word().to_string();
+            let field = word.starts_with(|c: char| c.is_alphabetic() || c == '_')
+                && self.peek() != Some('"')
+                && !keyword(&word);
+            if *fields.get_or_insert(field) != field {
+                return Err(self.expected("keys that are all names or all values"));
+            }
+            let key = match field {
+                true => GenericValue::Vstring(word),
+                false => {
+                    self.at = start;
+                    self.value()?
+                }
+            };
+            self.expect(':', ":")?;
+            entries.push((key, self.value()?));
+            self.skip_space();
+            if !self.eat(',') {
+                self.expect('}', "a comma or the end of the map")?;
+                break;
+            }
+        }
+        Ok(match fields {
+            Some(true) => GenericValue::Struct(entries),
+            _ => GenericValue::Map(entries),
+        })
+    }
+
+    fn string(&mut self) -> Result<String, ParseGenericError> {
+        let bytes = self.bytes()?;
+        String::from_utf8(bytes).map_err(|_| self.expected("a string without \\x escapes"))
+    }
+
+    // the quoted part of a string or a byte string
+    fn bytes(&mut self) -> Result<Vec<u8>, ParseGenericError> {
+        self.eat('"');
+        let mut bytes = Vec::new();
+        loop {
+            let c = self.bump().ok_or_else(|| self.expected("a closing quote"))?;
+            let escaped = match c {
+                '"' => return Ok(bytes),
+                '\\' => match self.bump() {
+                    Some('\\') => b'\\',
+                    Some('"') => b'"',
+                    Some('n') => b'\n',
+                    Some('r') => b'\r',
+                    Some('t') => b'\t',
+                    Some('0') => 0,
+                    Some('x') => {
+                        let hex = self.rest().get(..2).unwrap_or_default();
+                        let byte = u8::from_str_radix(hex, 16)
+                            .map_err(|_| self.expected("two hex digits"))?;
+                        self.at += 2;
+                        byte
+                    }
+                    _ => return Err(self.expected("an escape")),
+                },
+                c => {
+                    let mut utf8 = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+                    continue;
+                }
+            };
+            bytes.push(escaped);
+        }
+    }
+}
+
+fn number(word: &str) -> Option<GenericValueBoxed> {
+    let (digits, suffix) = SUFFIXES
+        .iter()
+        .find_map(|suffix| Some((word.strip_suffix(suffix)?, *suffix)))
+        .unwrap_or((word, if word.contains(['.', 'e', 'E']) { "f64" } else { "i64" }));
+    Some(match suffix {
+        "u8" => GenericValue::Vu8(digits.parse().ok()?),
+        "i8" => GenericValue::Vi8(digits.parse().ok()?),
+        "u16" => GenericValue::Vu16(digits.parse().ok()?),
+        "i16" => GenericValue::Vi16(digits.parse().ok()?),
+        "u32" => GenericValue::Vu32(digits.parse().ok()?),
+        "i32" => GenericValue::Vi32(digits.parse().ok()?),
+        "u64" => GenericValue::Vu64(digits.parse().ok()?),
+        "i64" => GenericValue::Vi64(digits.parse().ok()?),
+        "f32" => GenericValue::Vf32(digits.parse().ok()?),
+        "f64" => GenericValue::Vf64(digits.parse().ok()?),
+        "usize" => GenericValue::Vusize(digits.parse().ok()?),
+        "isize" => GenericValue::Visize(digits.parse().ok()?),
+        _ => return None,
+    })
+}
+
+// names that start a value rather than being a struct field
+fn keyword(word: &str) -> bool {
+    matches!(word, "true" | "false" | "null" | "token" | "function")
+        || word.strip_suffix('s').is_some_and(|t| SUFFIXES.contains(&t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_parse_back_from_how_they_print() {
+        let written = r#"-7i8 300u16 1.5 [1, "a\n", null] (true,) {"k": b"\x00"} {name: function 2} f32s[1, 0.5]"#;
+        let values = parse_generics(written).unwrap();
+        let printed: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        assert_eq!(
+            printed,
+            [
+                "-7",
+                "300",
+                "1.5",
+                "[1, \"a\\n\", null]",
+                "(true,)",
+                "{\"k\": b\"\\x00\"}",
+                "{name: function 2}",
+                "[1.0, 0.5]"
+            ]
+        );
+        assert!(matches!(values[1], GenericValue::Vu16(300)));
+        assert!("256u8".parse::<GenericValueBoxed>().is_err());
+        assert!("[1, 2".parse::<GenericValueBoxed>().is_err());
+    }
+
+    #[test]
+    fn values_nest_only_as_deep_as_they_can_be_read() {
+        let nested = |depth| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(nested(MAX_GENERIC_DEPTH).parse::<GenericValueBoxed>().is_ok());
+        assert!(matches!(
+            nested(MAX_GENERIC_DEPTH + 1).parse::<GenericValueBoxed>(),
+            Err(ParseGenericError::TooDeep { at: MAX_GENERIC_DEPTH, .. })
+        ));
+        // refused long before the stack runs out
+        assert!(parse_generics(&"[".repeat(1_000_000)).is_err());
+        assert!(parse_generics(&"u8s[".repeat(1_000_000)).is_err());
+        assert!(parse_generics(&"{(".repeat(1_000_000)).is_err());
+    }
+}
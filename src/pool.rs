@@ -0,0 +1,303 @@
+use std::{
+    io,
+    process::Command,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, MutexGuard, PoisonError, TryLockError,
+    },
+};
+
+use crate::{history::SubordinateState, *};
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+struct PoolState {
+    id_ctr: u64,
+    history: SubordinateState,
+}
+
+/// A fixed number of subordinates started from the same `Command`.
+///
+/// Functions and data are defined on every worker under the same token, so a call can go to
+/// whichever worker is idle. Workers found dead are restarted and have the pool's definitions
+/// replayed into them.
+pub struct SubordinatePool {
+    command: Mutex<Command>,
+    workers: Vec<Mutex<ControllerProcess>>,
+    // lock order is state, then workers
+    state: Mutex<PoolState>,
+    next: AtomicUsize,
+}
+
+impl SubordinatePool {
+    pub fn new(mut command: Command, size: usize) -> io::Result<Self> {
+        if size == 0 {
+            return Err(PoolError::NoWorkers.into());
+        }
+
+        let mut workers = Vec::with_capacity(size);
+        for _ in 0..size {
+            workers.push(Mutex::new(command.start_subordinate_process()?));
+        }
+
+        Ok(SubordinatePool {
+            command: Mutex::new(command),
+            workers,
+            state: Mutex::new(PoolState {
+                id_ctr: 0,
+                history: SubordinateState::default(),
+            }),
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+
+    fn respawn(
+        &self,
+        history: &SubordinateState,
+        worker: &mut ControllerProcess,
+    ) -> io::Result<()> {
+        let mut fresh = lock(&self.command).start_subordinate_process()?;
+        std::mem::swap(&mut fresh.recorder, &mut worker.recorder);
+        let caught_up = history.replay(&mut fresh);
+        std::mem::swap(&mut fresh.recorder, &mut worker.recorder);
+        caught_up?;
+        fresh.recorder = std::mem::take(&mut worker.recorder);
+        std::mem::replace(worker, fresh).terminate();
+        Ok(())
+    }
+
+    // the first worker nobody is using, or if they are all busy, the next one in line
+    fn idle_worker(&self) -> (usize, MutexGuard<'_, ControllerProcess>) {
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % self.workers.len();
+        for offset in 0..self.workers.len() {
+            let index = (start + offset) % self.workers.len();
+            match self.workers[index].try_lock() {
+                Ok(worker) => return (index, worker),
+                Err(TryLockError::Poisoned(poisoned)) => return (index, poisoned.into_inner()),
+                Err(TryLockError::WouldBlock) => continue,
+            }
+        }
+        (start, lock(&self.workers[start]))
+    }
+
+    fn live_worker(&self, index: usize) -> io::Result<MutexGuard<'_, ControllerProcess>> {
+        let state = lock(&self.state);
+        let mut worker = lock(&self.workers[index]);
+        if !worker.is_alive() {
+            self.respawn(&state.history, &mut worker)?;
+        }
+        Ok(worker)
+    }
+
+    // run a request on every worker, giving back the first worker's response
+    fn broadcast<F, V>(&self, history: &SubordinateState, request: F) -> io::Result<Response<V>>
+    where
+        F: Fn(&mut ControllerProcess) -> io::Result<Response<V>>,
+    {
+        let mut first = None;
+        for worker in &self.workers {
+            let mut worker = lock(worker);
+            if !worker.is_alive() {
+                self.respawn(history, &mut worker)?;
+            }
+            let response = request(&mut worker)?;
+            first.get_or_insert(response);
+        }
+        Ok(first.expect("pools are never empty"))
+    }
+
+    pub fn define_function(
+        &self,
+        function_blob: &[u8],
+        associated_data: &[GenericValueRef],
+        aux: &[GenericValueRef],
+    ) -> io::Result<Response<FunctionToken>> {
+        let mut state = lock(&self.state);
+        state.id_ctr += 1;
+        let token = FunctionToken(state.id_ctr);
+
+        let response = self.broadcast(&state.history, |w| {
+            w.define_function_as(token, function_blob, associated_data, aux)
+        });
+        let recorded = response.and_then(|response| {
+            state
+                .history
+                .define_function(token, function_blob, associated_data, aux)?;
+            Ok(response)
+        });
+        if recorded.is_err() {
+            // don't leave the function behind on the workers that did take it
+            self.free_everywhere(|w| w.free_function(&token, &[]));
+        }
+        recorded
+    }
+
+    pub fn define_data(
+        &self,
+        value: &[GenericValueRef],
+        aux: &[GenericValueRef],
+    ) -> io::Result<Response<DataToken>> {
+        let mut state = lock(&self.state);
+        state.id_ctr += 1;
+        let token = DataToken(state.id_ctr);
+
+        let response = self.broadcast(&state.history, |w| w.define_data_as(token, value, aux));
+        let recorded = response.and_then(|response| {
+            state.history.define_data(token, value, aux)?;
+            Ok(response)
+        });
+        if recorded.is_err() {
+            self.free_everywhere(|w| w.free_data(&token, &[]));
+        }
+        recorded
+    }
+
+    fn free_everywhere<F>(&self, free: F)
+    where
+        F: Fn(&mut ControllerProcess) -> io::Result<Response<()>>,
+    {
+        for worker in &self.workers {
+            let mut worker = lock(worker);
+            if worker.is_alive() {
+                let _ = free(&mut worker);
+            }
+        }
+    }
+
+    pub fn free_function(
+        &self,
+        token: &FunctionToken,
+        aux: &[GenericValueRef],
+    ) -> io::Result<Response<()>> {
+        let mut state = lock(&self.state);
+        state.history.free_function(token);
+        self.broadcast(&state.history, |w| w.free_function(token, aux))
+    }
+
+    pub fn free_data(
+        &self,
+        token: &DataToken,
+        aux: &[GenericValueRef],
+    ) -> io::Result<Response<()>> {
+        let mut state = lock(&self.state);
+        state.history.free_data(token);
+        self.broadcast(&state.history, |w| w.free_data(token, aux))
+    }
+
+    pub fn call_function(
+        &self,
+        token: &FunctionToken,
+        args: &[GenericValueRef],
+        aux: &[GenericValueRef],
+    ) -> io::Result<Response<Vec<GenericValueBoxed>>> {
+        let (index, mut worker) = self.idle_worker();
+        if !worker.is_alive() {
+            // restarting needs the state lock, which must not be taken while holding a worker
+            drop(worker);
+            worker = self.live_worker(index)?;
+        }
+
+        let response = worker.call_function(token, args, aux);
+        if let Err(e) = &response {
+            if is_disconnect(e) || !worker.is_alive() {
+                // the call is not retried, it may well be what brought the worker down. If the
+                // restart fails, the next call to land on this worker tries again
+                worker.terminate();
+                drop(worker);
+                drop(self.live_worker(index));
+            }
+        }
+        response
+    }
+
+    /// The stats of each worker, in order. A worker's carry on when it is restarted.
+    pub fn stats(&self) -> Vec<Stats> {
+        self.workers.iter().map(|worker| lock(worker).stats()).collect()
+    }
+
+    /// Export every worker's requests to the `metrics` crate, labelled `name/0`, `name/1`..
+    #[cfg(feature = "metrics")]
+    pub fn export_metrics(&self, name: &str) {
+        for (index, worker) in self.workers.iter().enumerate() {
+            lock(worker).export_metrics(&format!("{}/{}", name, index));
+        }
+    }
+
+    pub fn shutdown(self, aux: &[GenericValueRef]) -> io::Result<()> {
+        let mut result = Ok(());
+        for worker in self.workers {
+            let mut worker = worker.into_inner().unwrap_or_else(PoisonError::into_inner);
+            if !worker.is_alive() {
+                worker.terminate();
+                continue;
+            }
+            let shutdown = worker.shutdown(aux);
+            if result.is_ok() {
+                result = shutdown;
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::subordinate_command;
+
+    fn calls(pool: &SubordinatePool) -> Vec<u64> {
+        let calls = |stats: Stats| stats.requests.get(&ProtocolConstant::Call).copied();
+        pool.stats().into_iter().map(|s| calls(s).unwrap_or(0)).collect()
+    }
+
+    #[test]
+    fn calls_spread_over_workers_that_all_hold_every_definition() {
+        assert!(SubordinatePool::new(subordinate_command(), 0).is_err());
+
+        let pool = SubordinatePool::new(subordinate_command(), 2).unwrap();
+        let echo = pool.define_function(b"echo", &[], &[]).unwrap().value;
+        pool.define_data(&[1u8.into()], &[]).unwrap();
+        for stats in pool.stats() {
+            assert_eq!((stats.live_functions, stats.live_data), (1, 1));
+        }
+
+        for i in 0..4u8 {
+            let value = pool.call_function(&echo, &[i.into()], &[]).unwrap().value;
+            assert_eq!(value, [GenericValueBoxed::Vu8(i)]);
+        }
+        assert_eq!(calls(&pool), [2, 2]);
+
+        pool.free_function(&echo, &[]).unwrap();
+        for _ in 0..2 {
+            assert!(pool.call_function(&echo, &[], &[]).is_err());
+        }
+        pool.shutdown(&[]).unwrap();
+    }
+
+    #[test]
+    fn a_dead_worker_is_restarted_with_the_pools_definitions() {
+        let pool = SubordinatePool::new(subordinate_command(), 2).unwrap();
+        let echo = pool.define_function(b"echo", &[], &[]).unwrap().value;
+
+        lock(&pool.workers[0]).terminate();
+        for i in 0..2u8 {
+            let value = pool.call_function(&echo, &[i.into()], &[]).unwrap().value;
+            assert_eq!(value, [GenericValueBoxed::Vu8(i)]);
+        }
+        assert!(lock(&pool.workers[0]).is_alive());
+        // the restarted worker's stats carry on from the dead one's
+        assert_eq!(calls(&pool), [1, 1]);
+        assert_eq!(pool.stats()[0].live_functions, 1);
+        pool.shutdown(&[]).unwrap();
+    }
+}
@@ -1,12 +1,16 @@
-use crate::serialization::{sealed::SerializationEndpoint};
+use crate::serialization::sealed::{SerializationEndpoint, PREALLOCATE};
 use crate::serialization::{GenericValueBoxed, GenericValueRef};
-use crate::*;
+use crate::{
+    arena::SharedArenas,
+    frame::{Run, Span},
+    *,
+};
 use derive_try_from_primitive::TryFromPrimitive;
-use std::{io, result::Result};
+use std::{collections::HashSet, io, os::unix::io::AsFd, result::Result};
 
 #[repr(u8)]
-#[derive(TryFromPrimitive, Copy, Clone, Debug, PartialEq, Eq)]
-/// Public only for the sake of errors
+#[derive(TryFromPrimitive, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+/// Public only for the sake of errors and stats
 pub enum ProtocolConstant {
     Hello = 0x00,
 
@@ -22,12 +26,17 @@ pub enum ProtocolConstant {
     DefineData,
     Call,
     Result = 0xc5,
+    // the error type, then the logs as a count and entries like a Result's, then the aux
     Erroneous = 0x5c,
     FreeFunction,
     FreeData,
     Peek,
     Poke,
     Log,
+    // hand the subordinate a pair of shared arenas for large byte strings
+    Arena,
+    // the trace the next command belongs to, ahead of it and not answered
+    Context,
 
     // also a version for writeback
 
@@ -112,7 +121,152 @@ pub enum ProtocolCommand {
     },
 }
 
-#[derive(Debug, TryFromPrimitive)]
+/// A request read into a `Frame`, see `SubordinateProcess::recv_command_in`.
+#[derive(Debug)]
+pub struct RequestRef<'f> {
+    pub command: ProtocolCommandRef<'f>,
+    pub aux: Values<'f>,
+}
+
+#[derive(Debug)]
+#[must_use]
+pub enum ProtocolCommandRef<'f> {
+    Shutdown,
+    DefineFunction {
+        token: FunctionToken,
+        function_blob: &'f [u8],
+        associated_data: Values<'f>,
+    },
+    DefineData {
+        token: DataToken,
+        value: Values<'f>,
+    },
+    Call {
+        token: FunctionToken,
+        args: Values<'f>,
+    },
+    FreeFunction(FunctionToken),
+    FreeData(DataToken),
+    Peek(&'f str),
+    Poke {
+        key: &'f str,
+        value: Values<'f>,
+    },
+}
+
+// a command still being read into a frame, which may move its contents until it is done
+enum FramedCommand {
+    Shutdown,
+    DefineFunction {
+        token: FunctionToken,
+        function_blob: Span,
+        associated_data: Run,
+    },
+    DefineData {
+        token: DataToken,
+        value: Run,
+    },
+    Call {
+        token: FunctionToken,
+        args: Run,
+    },
+    FreeFunction(FunctionToken),
+    FreeData(DataToken),
+    Peek(Span),
+    Poke {
+        key: Span,
+        value: Run,
+    },
+}
+
+// what a command does to the subordinate's function registry, once it has been answered
+#[derive(Clone, Copy)]
+pub(crate) enum RegistryChange {
+    Define(FunctionToken),
+    Free(FunctionToken),
+    None,
+}
+
+impl ProtocolCommand {
+    fn registry_change(&self) -> RegistryChange {
+        match *self {
+            ProtocolCommand::DefineFunction { token, .. } => RegistryChange::Define(token),
+            ProtocolCommand::FreeFunction(token) => RegistryChange::Free(token),
+            _ => RegistryChange::None,
+        }
+    }
+
+    fn values(&self) -> impl Iterator<Item = &GenericValueBoxed> {
+        let values: &[GenericValueBoxed] = match self {
+            ProtocolCommand::DefineFunction {
+                associated_data, ..
+            } => associated_data,
+            ProtocolCommand::DefineData { value, .. } => value,
+            ProtocolCommand::Call { args, .. } => args,
+            ProtocolCommand::Poke { value, .. } => value,
+            _ => &[],
+        };
+        values.iter()
+    }
+}
+
+fn unknown_function(
+    functions: &HashSet<FunctionToken>,
+    value: GenericValueRef,
+) -> Option<FunctionToken> {
+    match value {
+        GenericValue::Function(token) if !functions.contains(&token) => Some(token),
+        GenericValue::List(list) | GenericValue::Tuple(list) => {
+            list.iter().find_map(|v| unknown_function(functions, v))
+        }
+        GenericValue::Map(map) | GenericValue::Struct(map) => map.iter().find_map(|(k, v)| {
+            unknown_function(functions, k).or_else(|| unknown_function(functions, v))
+        }),
+        _ => None,
+    }
+}
+
+impl FramedCommand {
+    fn registry_change(&self) -> RegistryChange {
+        match *self {
+            FramedCommand::DefineFunction { token, .. } => RegistryChange::Define(token),
+            FramedCommand::FreeFunction(token) => RegistryChange::Free(token),
+            _ => RegistryChange::None,
+        }
+    }
+
+    fn resolve(self, frame: &Frame) -> ProtocolCommandRef<'_> {
+        match self {
+            FramedCommand::Shutdown => ProtocolCommandRef::Shutdown,
+            FramedCommand::DefineFunction {
+                token,
+                function_blob,
+                associated_data,
+            } => ProtocolCommandRef::DefineFunction {
+                token,
+                function_blob: frame.bytes(function_blob),
+                associated_data: frame.values(associated_data),
+            },
+            FramedCommand::DefineData { token, value } => ProtocolCommandRef::DefineData {
+                token,
+                value: frame.values(value),
+            },
+            FramedCommand::Call { token, args } => ProtocolCommandRef::Call {
+                token,
+                args: frame.values(args),
+            },
+            FramedCommand::FreeFunction(token) => ProtocolCommandRef::FreeFunction(token),
+            FramedCommand::FreeData(token) => ProtocolCommandRef::FreeData(token),
+            FramedCommand::Peek(key) => ProtocolCommandRef::Peek(frame.str(key)),
+            FramedCommand::Poke { key, value } => ProtocolCommandRef::Poke {
+                key: frame.str(key),
+                value: frame.values(value),
+            },
+        }
+    }
+}
+
+#[derive(Debug, TryFromPrimitive, Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 pub enum LogType {
     Stdout,
@@ -121,8 +275,18 @@ pub enum LogType {
 
 #[derive(Debug)]
 pub struct LogEntry {
-    _log_type: LogType,
-    _line: String,
+    log_type: LogType,
+    line: String,
+}
+
+impl LogEntry {
+    pub fn log_type(&self) -> LogType {
+        self.log_type
+    }
+
+    pub fn line(&self) -> &str {
+        &self.line
+    }
 }
 
 #[derive(Debug)]
@@ -146,10 +310,39 @@ impl ControllerProcess {
         Ok(())
     }
 
+    /// Share two arenas of `size` bytes with the subordinate, one for each direction. Byte strings
+    /// of at least `ARENA_MIN_PAYLOAD` are then placed there instead of being copied through the
+    /// transport, and are copied out of it as they are read. An arena is reused once the other
+    /// side has read everything placed in it, until then anything that doesn't fit is sent inline.
+    ///
+    /// The arenas are passed as file descriptors, so the subordinate has to have been started
+    /// with `start_subordinate_process_with_fds` or connected over a Unix socket.
+    pub fn attach_arena(&mut self, size: usize) -> io::Result<()> {
+        if !self.transport.passes_fds() {
+            return Err(ProtocolError::FdPassingUnsupported.into());
+        }
+        let (outbound, inbound) = SharedArenas::create(size)?;
+        self.measured(ProtocolConstant::Arena, None, |s| {
+            s.write_protocol(ProtocolConstant::Arena)?
+                .write_fd(outbound.as_fd())?
+                .write_fd(inbound.as_fd())?
+                .read_response(|_| Ok(()))
+        })?;
+        self.arenas = Some(SharedArenas::new(outbound, inbound)?);
+        Ok(())
+    }
+
+    pub(crate) fn arena_size(&self) -> Option<usize> {
+        self.arenas.as_ref().map(SharedArenas::size)
+    }
+
     pub fn shutdown(&mut self, aux: &[GenericValueRef]) -> io::Result<()> {
-        self.write_protocol(ProtocolConstant::Goodbye)?
-            .write_generic_vec(aux)?
-            .flush()?;
+        self.measured(ProtocolConstant::Goodbye, None, |s| {
+            s.write_protocol(ProtocolConstant::Goodbye)?
+                .write_generic_vec(aux)?
+                .flush()?;
+            Ok(())
+        })?;
         self.subordinate.wait()?;
         Ok(())
     }
@@ -163,41 +356,28 @@ impl ControllerProcess {
 
     fn read_logs(&mut self) -> io::Result<Vec<LogEntry>> {
         let log_ct = self.read_usize()?;
-        let mut logs = Vec::with_capacity(log_ct);
+        let mut logs = Vec::with_capacity(log_ct.min(PREALLOCATE));
         for _ in 0..log_ct {
             let log_type = self.read_log_type()?;
             let line = self.read_string()?;
-            logs.push(LogEntry { _log_type: log_type, _line: line });
+            logs.push(LogEntry { log_type, line });
         }
         Ok(logs)
     }
 
-    fn read_response<F, V>(&mut self, get_v: F) -> io::Result<Response<V>>
+    pub(crate) fn read_response<F, V>(&mut self, get_v: F) -> io::Result<Response<V>>
     where
         F: FnOnce(&mut Self) -> io::Result<V>,
     {
         let p = self.flush()?.read_protocol()?;
         match p {
             ProtocolConstant::Result => {
-                let logs = self.read_logs()?;
-                let response_aux = self.read_generic_vec()?;
-                let value = get_v(self)?;
-                Ok(Response {
-                    logs,
-                    response_aux,
-                    value,
-                })
+                let read = self.read_result(get_v);
+                self.read_whole(read)
             }
             ProtocolConstant::Erroneous => {
-                let err_type = self.read_err_type()?;
-                let logs = self.read_logs()?;
-                let aux = self.read_generic_vec()?;
-                Err(RemoteError {
-                    err_type,
-                    logs,
-                    aux,
-                }
-                .into())
+                let read = self.read_refusal();
+                Err(self.read_whole(read)?.into())
             }
             err => Err(ProtocolError::UnexpectedProtocolConstant {
                 expected: ProtocolConstant::Result,
@@ -207,6 +387,31 @@ impl ControllerProcess {
         }
     }
 
+    fn read_result<F, V>(&mut self, get_v: F) -> io::Result<Response<V>>
+    where
+        F: FnOnce(&mut Self) -> io::Result<V>,
+    {
+        let logs = self.read_logs()?;
+        let response_aux = self.read_generic_vec()?;
+        let value = get_v(self)?;
+        Ok(Response {
+            logs,
+            response_aux,
+            value,
+        })
+    }
+
+    fn read_refusal(&mut self) -> io::Result<RemoteError> {
+        let err_type = self.read_err_type()?;
+        let logs = self.read_logs()?;
+        let aux = self.read_generic_vec()?;
+        Ok(RemoteError {
+            err_type,
+            logs,
+            aux,
+        })
+    }
+
     pub fn define_function(
         &mut self,
         function_blob: &[u8],
@@ -214,14 +419,33 @@ impl ControllerProcess {
         aux: &[GenericValueRef],
     ) -> io::Result<Response<FunctionToken>> {
         self.id_ctr += 1;
-        let token = self.id_ctr;
+        let token = FunctionToken(self.id_ctr);
 
-        self.write_protocol(ProtocolConstant::DefineFunction)?
-            .write_u64(token)?
-            .write_bytes(function_blob)?
-            .write_generic_vec(associated_data)?
-            .write_generic_vec(aux)?
-            .read_response(|_| Ok(FunctionToken(token)))
+        let response = self
+            .supervised(|s| s.define_function_as(token, function_blob, associated_data, aux))?;
+        self.record(|h| h.define_function(token, function_blob, associated_data, aux))?;
+        Ok(response)
+    }
+
+    // define under a token chosen by the caller, used when several subordinates must agree on tokens
+    pub(crate) fn define_function_as(
+        &mut self,
+        token: FunctionToken,
+        function_blob: &[u8],
+        associated_data: &[GenericValueRef],
+        aux: &[GenericValueRef],
+    ) -> io::Result<Response<FunctionToken>> {
+        self.measured(ProtocolConstant::DefineFunction, Some(token.0), |s| {
+            let response = s
+                .write_protocol(ProtocolConstant::DefineFunction)?
+                .write_u64(token.0)?
+                .write_bytes(function_blob)?
+                .write_generic_vec(associated_data)?
+                .write_generic_vec(aux)?
+                .read_response(|_| Ok(token))?;
+            s.recorder.functions.insert(token);
+            Ok(response)
+        })
     }
 
     pub fn call_function(
@@ -230,11 +454,42 @@ impl ControllerProcess {
         args: &[GenericValueRef],
         aux: &[GenericValueRef],
     ) -> io::Result<Response<Vec<GenericValueBoxed>>> {
-        self.write_protocol(ProtocolConstant::Call)?
-            .write_u64(token.0)?
-            .write_generic_vec(args)?
-            .write_generic_vec(aux)?
-            .read_response(|s| s.read_generic_vec())
+        self.supervised_call(|s| {
+            s.measured(ProtocolConstant::Call, Some(token.0), |s| {
+                s.write_protocol(ProtocolConstant::Call)?
+                    .write_u64(token.0)?
+                    .write_generic_vec(args)?
+                    .write_generic_vec(aux)?
+                    .read_response(|s| s.read_generic_vec())
+            })
+        })
+    }
+
+    /// Like `call_function`, but the results are read into `frame` rather than allocated one by
+    /// one.
+    pub fn call_function_in<'f>(
+        &mut self,
+        token: &FunctionToken,
+        args: &[GenericValueRef],
+        aux: &[GenericValueRef],
+        frame: &'f mut Frame,
+    ) -> io::Result<Response<Values<'f>>> {
+        let response = self.supervised_call(|s| {
+            frame.clear();
+            s.measured(ProtocolConstant::Call, Some(token.0), |s| {
+                s.write_protocol(ProtocolConstant::Call)?
+                    .write_u64(token.0)?
+                    .write_generic_vec(args)?
+                    .write_generic_vec(aux)?
+                    .read_response(|s| s.read_generic_vec_in(frame))
+            })
+        })?;
+        let frame = &*frame;
+        Ok(Response {
+            logs: response.logs,
+            response_aux: response.response_aux,
+            value: frame.values(response.value),
+        })
     }
 
     pub fn free_function(
@@ -242,10 +497,22 @@ impl ControllerProcess {
         token: &FunctionToken,
         aux: &[GenericValueRef],
     ) -> io::Result<Response<()>> {
-        self.write_protocol(ProtocolConstant::FreeFunction)?
-            .write_u64(token.0)?
-            .write_generic_vec(aux)?
-            .read_response(|_| Ok(()))
+        let response = self.supervised(|s| {
+            s.measured(ProtocolConstant::FreeFunction, Some(token.0), |s| {
+                let response = s
+                    .write_protocol(ProtocolConstant::FreeFunction)?
+                    .write_u64(token.0)?
+                    .write_generic_vec(aux)?
+                    .read_response(|_| Ok(()))?;
+                s.recorder.functions.remove(token);
+                Ok(response)
+            })
+        })?;
+        self.record(|h| {
+            h.free_function(token);
+            Ok(())
+        })?;
+        Ok(response)
     }
 
     pub fn define_data(
@@ -254,13 +521,29 @@ impl ControllerProcess {
         aux: &[GenericValueRef]
     ) -> io::Result<Response<DataToken>> {
         self.id_ctr += 1;
-        let token = self.id_ctr;
+        let token = DataToken(self.id_ctr);
 
-        self.write_protocol(ProtocolConstant::DefineData)?
-            .write_u64(token)?
-            .write_generic_vec(value)?            
-            .write_generic_vec(aux)?
-            .read_response(|_| Ok(DataToken(token)))
+        let response = self.supervised(|s| s.define_data_as(token, value, aux))?;
+        self.record(|h| h.define_data(token, value, aux))?;
+        Ok(response)
+    }
+
+    pub(crate) fn define_data_as(
+        &mut self,
+        token: DataToken,
+        value: &[GenericValueRef],
+        aux: &[GenericValueRef],
+    ) -> io::Result<Response<DataToken>> {
+        self.measured(ProtocolConstant::DefineData, Some(token.0), |s| {
+            let response = s
+                .write_protocol(ProtocolConstant::DefineData)?
+                .write_u64(token.0)?
+                .write_generic_vec(value)?
+                .write_generic_vec(aux)?
+                .read_response(|_| Ok(token))?;
+            s.recorder.data.insert(token);
+            Ok(response)
+        })
     }
 
 
@@ -269,10 +552,22 @@ impl ControllerProcess {
         token: &DataToken,
         aux: &[GenericValueRef],
     ) -> io::Result<Response<()>> {
-        self.write_protocol(ProtocolConstant::FreeData)?
-            .write_u64(token.0)?
-            .write_generic_vec(aux)?
-            .read_response(|_| Ok(()))
+        let response = self.supervised(|s| {
+            s.measured(ProtocolConstant::FreeData, Some(token.0), |s| {
+                let response = s
+                    .write_protocol(ProtocolConstant::FreeData)?
+                    .write_u64(token.0)?
+                    .write_generic_vec(aux)?
+                    .read_response(|_| Ok(()))?;
+                s.recorder.data.remove(token);
+                Ok(response)
+            })
+        })?;
+        self.record(|h| {
+            h.free_data(token);
+            Ok(())
+        })?;
+        Ok(response)
     }
 
     pub fn peek(
@@ -280,10 +575,14 @@ impl ControllerProcess {
         key: &str,
         aux: &[GenericValueRef],
     ) -> io::Result<Response<Vec<GenericValueBoxed>>> {
-        self.write_protocol(ProtocolConstant::Peek)?
-            .write_string(key)?
-            .write_generic_vec(aux)?
-            .read_response(|s| s.read_generic_vec())
+        self.supervised(|s| {
+            s.measured(ProtocolConstant::Peek, None, |s| {
+                s.write_protocol(ProtocolConstant::Peek)?
+                    .write_string(key)?
+                    .write_generic_vec(aux)?
+                    .read_response(|s| s.read_generic_vec())
+            })
+        })
     }
 
     pub fn poke(
@@ -292,11 +591,17 @@ impl ControllerProcess {
         value: &[GenericValueRef],
         aux: &[GenericValueRef],
     ) -> io::Result<Response<()>> {
-        self.write_protocol(ProtocolConstant::Poke)?
-            .write_string(key)?
-            .write_generic_vec(value)?
-            .write_generic_vec(aux)?
-            .read_response(|_| Ok(()))
+        let response = self.supervised(|s| {
+            s.measured(ProtocolConstant::Poke, None, |s| {
+                s.write_protocol(ProtocolConstant::Poke)?
+                    .write_string(key)?
+                    .write_generic_vec(value)?
+                    .write_generic_vec(aux)?
+                    .read_response(|_| Ok(()))
+            })
+        })?;
+        self.record(|h| h.poke(key, value, aux))?;
+        Ok(response)
     }
 }
 
@@ -359,8 +664,97 @@ impl SubordinateProcess {
         Ok(ProtocolCommand::Poke { key, value })
     }
 
+    // the controller's outbound arena is our inbound one
+    fn recv_arena(&mut self) -> io::Result<()> {
+        let inbound = self.read_fd()?;
+        let outbound = self.read_fd()?;
+        match SharedArenas::new(outbound, inbound) {
+            Ok(arenas) => {
+                self.respond(&[], |s| Ok(s))?;
+                self.arenas = Some(arenas);
+                Ok(())
+            }
+            Err(_) => self.respond_with_error(RemoteErrorType::ProtocolError, &[]),
+        }
+    }
+
+    // arenas are set up and trace contexts noted without bothering the caller
+    fn read_command_protocol(&mut self) -> io::Result<ProtocolConstant> {
+        self.trace_context = None;
+        loop {
+            match self.read_protocol()? {
+                ProtocolConstant::Arena => self.recv_arena()?,
+                ProtocolConstant::Context => self.trace_context = Some(self.read_u64()?),
+                protocol => return Ok(protocol),
+            }
+        }
+    }
+
+    /// The trace context id the controller sent along with the last command read, see
+    /// `ControllerProcess::set_trace_context`.
+    pub fn trace_context(&self) -> Option<u64> {
+        self.trace_context
+    }
+
+    // a function goes into the registry once its definition has been answered, and out of it
+    // once its free has been. A refused command changes nothing
+    fn register(&mut self, command: RegistryChange) {
+        match command {
+            RegistryChange::Define(token) => {
+                self.functions.insert(token);
+            }
+            RegistryChange::Free(token) => {
+                self.functions.remove(&token);
+            }
+            RegistryChange::None => {}
+        }
+    }
+
+    /// Read the next command. A command holding a `Function` value for a function that isn't
+    /// defined is answered with an error here, with the token as aux, and never returned. A
+    /// shutdown is always returned, as the controller doesn't wait for an answer to one.
     pub fn recv_command(&mut self) -> io::Result<Request> {
-        let command = match self.read_protocol()? {
+        #[cfg(feature = "tracing")]
+        let span = crate::spans::CommandSpan::enter(self);
+        let request = self.read_known_command();
+        #[cfg(feature = "tracing")]
+        span.exit(self, request.as_ref().map(|r| crate::spans::describe(&r.command)));
+        request
+    }
+
+    fn read_known_command(&mut self) -> io::Result<Request> {
+        loop {
+            let request = self.read_command();
+            let request = self.read_whole(request)?;
+            let unknown = match request.command {
+                ProtocolCommand::Shutdown => None,
+                _ => request
+                    .command
+                    .values()
+                    .chain(&request.aux)
+                    .find_map(|v| unknown_function(&self.functions, v.as_ref())),
+            };
+            match unknown {
+                None => {
+                    self.pending = request.command.registry_change();
+                    return Ok(request);
+                }
+                Some(token) => self.respond_with_error(
+                    RemoteErrorType::ProtocolError,
+                    &[GenericValueRef::Function(token)],
+                )?,
+            }
+        }
+    }
+
+    fn read_command(&mut self) -> io::Result<Request> {
+        let protocol = self.read_command_protocol()?;
+        self.read_command_body(protocol)
+    }
+
+    // everything after the protocol constant
+    pub(crate) fn read_command_body(&mut self, protocol: ProtocolConstant) -> io::Result<Request> {
+        let command = match protocol {
             ProtocolConstant::DefineFunction => self.recv_define_function(),
             ProtocolConstant::Call => self.recv_call(),
             ProtocolConstant::FreeFunction => self.recv_free_function(),
@@ -380,18 +774,117 @@ impl SubordinateProcess {
         Ok(Request { command, aux })
     }
 
+    /// Like `recv_command`, but the request is read into `frame` and borrows from it rather than
+    /// allocating each value.
+    pub fn recv_command_in<'f>(&mut self, frame: &'f mut Frame) -> io::Result<RequestRef<'f>> {
+        #[cfg(feature = "tracing")]
+        let span = crate::spans::CommandSpan::enter(self);
+        let request = self.read_known_command_in(frame);
+        #[cfg(feature = "tracing")]
+        span.exit(self, request.as_ref().map(|r| crate::spans::describe_ref(&r.command)));
+        request
+    }
+
+    fn read_known_command_in<'f>(&mut self, frame: &'f mut Frame) -> io::Result<RequestRef<'f>> {
+        let (command, aux) = loop {
+            let read = self.read_command_in(frame);
+            let (command, aux) = self.read_whole(read)?;
+            // every value read, however deeply nested, has a slot of its own
+            let unknown = match command {
+                FramedCommand::Shutdown => None,
+                _ => frame.slots.iter().find_map(|slot| match slot {
+                    GenericValue::Function(token) if !self.functions.contains(token) => {
+                        Some(*token)
+                    }
+                    _ => None,
+                }),
+            };
+            match unknown {
+                None => break (command, aux),
+                Some(token) => self.respond_with_error(
+                    RemoteErrorType::ProtocolError,
+                    &[GenericValueRef::Function(token)],
+                )?,
+            }
+        };
+        self.pending = command.registry_change();
+
+        let frame = &*frame;
+        Ok(RequestRef {
+            command: command.resolve(frame),
+            aux: frame.values(aux),
+        })
+    }
+
+    fn read_command_in(&mut self, frame: &mut Frame) -> io::Result<(FramedCommand, Run)> {
+        frame.clear();
+        let command = match self.read_command_protocol()? {
+            ProtocolConstant::DefineFunction => FramedCommand::DefineFunction {
+                token: FunctionToken(self.read_u64()?),
+                function_blob: self.read_bytes_in(frame)?,
+                associated_data: self.read_generic_vec_in(frame)?,
+            },
+            ProtocolConstant::Call => FramedCommand::Call {
+                token: FunctionToken(self.read_u64()?),
+                args: self.read_generic_vec_in(frame)?,
+            },
+            ProtocolConstant::FreeFunction => {
+                FramedCommand::FreeFunction(FunctionToken(self.read_u64()?))
+            }
+
+            ProtocolConstant::DefineData => FramedCommand::DefineData {
+                token: DataToken(self.read_u64()?),
+                value: self.read_generic_vec_in(frame)?,
+            },
+            ProtocolConstant::FreeData => FramedCommand::FreeData(DataToken(self.read_u64()?)),
+
+            ProtocolConstant::Peek => FramedCommand::Peek(self.read_string_in(frame)?),
+            ProtocolConstant::Poke => FramedCommand::Poke {
+                key: self.read_string_in(frame)?,
+                value: self.read_generic_vec_in(frame)?,
+            },
+
+            ProtocolConstant::Goodbye => FramedCommand::Shutdown,
+            err => return Err(ProtocolError::InappropriateProtocolConstant(err).into()),
+        };
+
+        let aux = self.read_generic_vec_in(frame)?;
+        Ok((command, aux))
+    }
+
+    // with the tracing feature, every response is written in a span of its own
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn responding<F>(&mut self, refusal: Option<RemoteErrorType>, write: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut Self) -> io::Result<()>,
+    {
+        #[cfg(feature = "tracing")]
+        let span = crate::spans::ResponseSpan::enter(self, refusal);
+        let result = write(self);
+        #[cfg(feature = "tracing")]
+        span.exit(self, &result);
+        result
+    }
+
     fn respond<F>(&mut self, aux: &[GenericValueRef], value_writer: F) -> io::Result<()>
     where
         F: FnOnce(&mut Self) -> io::Result<&mut Self>,
     {
-        self.write_protocol(ProtocolConstant::Result)?;
+        let answered = self.responding(None, |s| {
+            s.write_protocol(ProtocolConstant::Result)?;
 
-        //TODO: we'll need to read logs, for now zero logs
-        self.write_usize(0)?;
+            //TODO: we'll need to read logs, for now zero logs
+            s.write_usize(0)?;
 
-        self.write_generic_vec(aux)?;
-        value_writer(self)?.flush()?;
-        Ok(())
+            s.write_generic_vec(aux)?;
+            value_writer(s)?.flush()?;
+            Ok(())
+        });
+        let pending = std::mem::replace(&mut self.pending, RegistryChange::None);
+        if answered.is_ok() {
+            self.register(pending);
+        }
+        answered
     }
 
     pub fn respond_to_define(&mut self, aux: &[GenericValueRef]) -> io::Result<()> {
@@ -410,6 +903,8 @@ impl SubordinateProcess {
         self.respond(aux, |s| Ok(s))
     }
 
+    /// Answer a peek with the values under its key, or with a lone `Null` when nothing has been
+    /// poked there.
     pub fn respond_to_peek(
         &mut self,
         peek_value: &[GenericValueRef],
@@ -427,10 +922,130 @@ impl SubordinateProcess {
         error_type: RemoteErrorType,
         aux: &[GenericValueRef],
     ) -> io::Result<()> {
-        self.write_protocol(ProtocolConstant::Erroneous)?
-            .write_err_type(error_type)?
-            .write_generic_vec(aux)?
-            .flush()?;
-        Ok(())
+        self.pending = RegistryChange::None;
+        self.responding(Some(error_type), |s| {
+            // no logs, read_response expects them ahead of the aux
+            s.write_protocol(ProtocolConstant::Erroneous)?
+                .write_err_type(error_type)?
+                .write_usize(0)?
+                .write_generic_vec(aux)?
+                .flush()?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::net::UnixStream, thread};
+
+    use crate::{endpoint::Subordinate, transport::SocketTransport, *};
+
+    #[test]
+    fn commands_naming_undefined_functions_are_refused_before_they_are_returned() {
+        let (a, b) = UnixStream::pair().unwrap();
+        let subordinate = thread::spawn(move || {
+            let mut subordinate = SubordinateProcess::new(Box::new(SocketTransport::new(b)));
+            let mut calls = 0;
+            loop {
+                match subordinate.recv_command().unwrap().command {
+                    ProtocolCommand::DefineFunction { function_blob, .. } => {
+                        match function_blob.as_slice() {
+                            b"no" => subordinate
+                                .respond_with_error(RemoteErrorType::UserspaceException, &[]),
+                            _ => subordinate.respond_to_define(&[]),
+                        }
+                    }
+                    ProtocolCommand::Call { args, .. } => {
+                        calls += 1;
+                        subordinate.respond_to_call(&args.as_refs(), &[])
+                    }
+                    ProtocolCommand::Shutdown => return calls,
+                    _ => unreachable!(),
+                }
+                .unwrap();
+            }
+        });
+        let mut controller =
+            ControllerProcess::new(Subordinate::Connected, Box::new(SocketTransport::new(a)));
+        let refused = FunctionToken(controller.id_ctr + 1);
+        assert!(controller.define_function(b"no", &[], &[]).is_err());
+        let defined = controller.define_function(b"yes", &[], &[]).unwrap().value;
+
+        // a refused definition doesn't define the function
+        let e = controller.call_function(&defined, &[refused.into()], &[]).unwrap_err();
+        let e = e.get_ref().unwrap().downcast_ref::<RemoteError>().unwrap();
+        assert_eq!(e.err_type, RemoteErrorType::ProtocolError);
+        assert_eq!(e.aux, [GenericValueBoxed::Function(refused)]);
+
+        let value = controller.call_function(&defined, &[defined.into()], &[]).unwrap().value;
+        assert_eq!(value, [GenericValueBoxed::Function(defined)]);
+        controller.shutdown(&[]).unwrap();
+        assert_eq!(subordinate.join().unwrap(), 1);
+    }
+
+    #[test]
+    fn large_values_go_through_the_arena_and_come_back_whole() {
+        let (a, b) = UnixStream::pair().unwrap();
+        let subordinate = thread::spawn(move || {
+            crate::testing::serve(SubordinateProcess::new(Box::new(SocketTransport::new(b))))
+        });
+        let mut controller =
+            ControllerProcess::new(Subordinate::Connected, Box::new(SocketTransport::new(a)));
+        controller.attach_arena(8 * ARENA_MIN_PAYLOAD).unwrap();
+        let echo = controller.define_function(b"echo", &[], &[]).unwrap().value;
+        let bytes: Vec<u8> = (0..ARENA_MIN_PAYLOAD * 2).map(|i| i as u8).collect();
+        let words: Vec<u32> = (0..ARENA_MIN_PAYLOAD as u32).collect();
+        let args = [GenericValueRef::Vbytes(&bytes), GenericValueRef::from(&words)];
+
+        // the second round only fits if the first one's space was handed back
+        let mut frame = Frame::new();
+        for _ in 0..2 {
+            let value = controller.call_function(&echo, &args, &[]).unwrap().value;
+            assert_eq!(value.as_refs(), args);
+            let value = controller.call_function_in(&echo, &args, &[], &mut frame).unwrap().value;
+            assert_eq!(value.iter().collect::<Vec<_>>(), args);
+        }
+        controller.shutdown(&[]).unwrap();
+        subordinate.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn a_shutdown_naming_a_freed_function_still_shuts_down() {
+        for framed in [false, true] {
+            let (a, b) = UnixStream::pair().unwrap();
+            let subordinate = thread::spawn(move || {
+                let mut subordinate = SubordinateProcess::new(Box::new(SocketTransport::new(b)));
+                let mut frame = Frame::new();
+                loop {
+                    let (shutdown, aux) = match framed {
+                        false => {
+                            let request = subordinate.recv_command().unwrap();
+                            (matches!(request.command, ProtocolCommand::Shutdown), request.aux)
+                        }
+                        true => {
+                            let request = subordinate.recv_command_in(&mut frame).unwrap();
+                            let shutdown =
+                                matches!(request.command, ProtocolCommandRef::Shutdown);
+                            let aux = request.aux.iter().map(|v| v.to_boxed().unwrap());
+                            (shutdown, aux.collect())
+                        }
+                    };
+                    if shutdown {
+                        return aux;
+                    }
+                    // a define or a free, either is answered the same way
+                    subordinate.respond_to_define(&[]).unwrap();
+                }
+            });
+            let mut controller =
+                ControllerProcess::new(Subordinate::Connected, Box::new(SocketTransport::new(a)));
+            let freed = controller.define_function(b"f", &[], &[]).unwrap().value;
+            controller.free_function(&freed, &[]).unwrap();
+            controller.shutdown(&[GenericValueRef::Function(freed)]).unwrap();
+            // a subordinate that refused the shutdown then fails rather than waiting on
+            drop(controller);
+            assert_eq!(subordinate.join().unwrap(), [GenericValueBoxed::Function(freed)]);
+        }
     }
 }
@@ -0,0 +1,282 @@
+use std::io;
+
+use crate::{trace::into_remote_error, *};
+
+// Replaying a recorded session against one side, with the other side played from the trace.
+// Values are compared as with `==`, except that any file descriptor matches any other: the trace
+// only records that one was passed, not what it was. Logs aren't compared.
+
+fn same_value(a: GenericValueRef<'_>, b: GenericValueRef<'_>) -> bool {
+    use GenericValue::*;
+    match (a, b) {
+        (Fd(_), Fd(_)) => true,
+        (List(a), List(b)) | (Tuple(a), Tuple(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| same_value(a, b))
+        }
+        (Map(a), Map(b)) | (Struct(a), Struct(b)) => {
+            a.len() == b.len()
+                && a.iter().zip(b.iter()).all(|((a_key, a_value), (b_key, b_value))| {
+                    same_value(a_key, b_key) && same_value(a_value, b_value)
+                })
+        }
+        (a, b) => a == b,
+    }
+}
+
+pub(crate) fn same_values(a: &[GenericValueBoxed], b: &[GenericValueBoxed]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_value(a.as_ref(), b.as_ref()))
+}
+
+pub(crate) fn same_command(a: &ProtocolCommand, b: &ProtocolCommand) -> bool {
+    use ProtocolCommand::*;
+    match (a, b) {
+        (Shutdown, Shutdown) => true,
+        (
+            DefineFunction {
+                token,
+                function_blob,
+                associated_data,
+            },
+            DefineFunction {
+                token: other_token,
+                function_blob: other_blob,
+                associated_data: other_data,
+            },
+        ) => {
+            token == other_token
+                && function_blob == other_blob
+                && same_values(associated_data, other_data)
+        }
+        (
+            DefineData { token, value },
+            DefineData {
+                token: other_token,
+                value: other_value,
+            },
+        ) => token == other_token && same_values(value, other_value),
+        (
+            Call { token, args },
+            Call {
+                token: other_token,
+                args: other_args,
+            },
+        ) => token == other_token && same_values(args, other_args),
+        (FreeFunction(a), FreeFunction(b)) => a == b,
+        (FreeData(a), FreeData(b)) => a == b,
+        (Peek(a), Peek(b)) => a == b,
+        (
+            Poke { key, value },
+            Poke {
+                key: other_key,
+                value: other_value,
+            },
+        ) => key == other_key && same_values(value, other_value),
+        _ => false,
+    }
+}
+
+fn same_reply(a: &Reply, b: &Reply) -> bool {
+    match (a, b) {
+        (Reply::Hello, Reply::Hello) => true,
+        (Reply::Result(a), Reply::Result(b)) => {
+            same_values(&a.value, &b.value) && same_values(&a.response_aux, &b.response_aux)
+        }
+        (Reply::Error(a), Reply::Error(b)) => a.err_type == b.err_type && same_values(&a.aux, &b.aux),
+        _ => false,
+    }
+}
+
+fn without_value<T>(
+    response: io::Result<Response<T>>,
+) -> io::Result<Response<Vec<GenericValueBoxed>>> {
+    response.map(|response| Response {
+        logs: response.logs,
+        response_aux: response.response_aux,
+        value: Vec::new(),
+    })
+}
+
+/// A reply that went differently when a session was replayed, the one to
+/// `session.exchanges[index]`. `replayed` is an error when the subordinate didn't answer at all.
+#[derive(Debug)]
+pub struct Divergence {
+    pub index: usize,
+    pub replayed: io::Result<Reply>,
+}
+
+impl ControllerProcess {
+    /// Resend the commands of a recorded session to this subordinate, which should be freshly
+    /// started, and return every reply that differs from the recorded one. Functions and data
+    /// are defined under their recorded tokens, arenas aren't set up again. A command the
+    /// recorded subordinate never answered is expected to go unanswered again. Stops at the
+    /// recorded shutdown, or once the subordinate stops answering.
+    pub fn replay(&mut self, session: &RecordedSession) -> io::Result<Vec<Divergence>> {
+        let mut divergences = Vec::new();
+        for (index, exchange) in session.exchanges.iter().enumerate() {
+            let request = match &exchange.message {
+                Message::Command(request) => request,
+                // done when the subordinate was started
+                Message::Hello | Message::Arena => continue,
+            };
+            if let ProtocolCommand::Shutdown = request.command {
+                self.shutdown(&request.aux.as_refs())?;
+                break;
+            }
+
+            let recorded = exchange.reply.as_ref().map(|(_, reply)| reply);
+            match (recorded, self.resend(request)) {
+                (Some(recorded), Ok(replayed)) if same_reply(recorded, &replayed) => {}
+                // failed just like it did when it was recorded
+                (None, Err(_)) => break,
+                (_, replayed) => {
+                    let answered = replayed.is_ok();
+                    divergences.push(Divergence { index, replayed });
+                    if !answered {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(divergences)
+    }
+
+    fn resend(&mut self, request: &Request) -> io::Result<Reply> {
+        let aux = request.aux.as_refs();
+        let response = match &request.command {
+            ProtocolCommand::DefineFunction {
+                token,
+                function_blob,
+                associated_data,
+            } => {
+                self.id_ctr = self.id_ctr.max(token.0);
+                without_value(self.define_function_as(
+                    *token,
+                    function_blob,
+                    &associated_data.as_refs(),
+                    &aux,
+                ))
+            }
+            ProtocolCommand::DefineData { token, value } => {
+                self.id_ctr = self.id_ctr.max(token.0);
+                without_value(self.define_data_as(*token, &value.as_refs(), &aux))
+            }
+            ProtocolCommand::Call { token, args } => self.call_function(token, &args.as_refs(), &aux),
+            ProtocolCommand::FreeFunction(token) => without_value(self.free_function(token, &aux)),
+            ProtocolCommand::FreeData(token) => without_value(self.free_data(token, &aux)),
+            ProtocolCommand::Peek(key) => self.peek(key, &aux),
+            ProtocolCommand::Poke { key, value } => {
+                without_value(self.poke(key, &value.as_refs(), &aux))
+            }
+            ProtocolCommand::Shutdown => unreachable!("a shutdown isn't answered"),
+        };
+        match response {
+            Ok(response) => Ok(Reply::Result(response)),
+            Err(e) => into_remote_error(e).map(Reply::Error),
+        }
+    }
+}
+
+impl SubordinateProcess {
+    /// Answer the controller with the recorded replies of a session, so a controller can be
+    /// debugged without its subordinate. Each command has to be the one recorded next; the first
+    /// that isn't is refused with a `ProtocolError` whose aux says what was expected, and the
+    /// replay fails. Returns after the recorded shutdown, or where the recorded subordinate
+    /// stopped answering.
+    pub fn serve(&mut self, session: &RecordedSession) -> io::Result<()> {
+        for (index, exchange) in session.exchanges.iter().enumerate() {
+            let expected = match &exchange.message {
+                Message::Command(request) => request,
+                // the hello is done by `subordinate_begin`, arenas by `recv_command`
+                Message::Hello | Message::Arena => continue,
+            };
+            let request = self.recv_command()?;
+            if !same_command(&expected.command, &request.command)
+                || !same_values(&expected.aux, &request.aux)
+            {
+                let expected = format!("expected {}", exchange.message);
+                self.respond_with_error(RemoteErrorType::ProtocolError, &[expected.as_str().into()])?;
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("command {} isn't the recorded {}", index, exchange.message),
+                ));
+            }
+
+            match &exchange.reply {
+                None => return Ok(()),
+                Some((_, Reply::Result(response))) => match request.command {
+                    ProtocolCommand::Call { .. } | ProtocolCommand::Peek(_) => self
+                        .respond_to_call(&response.value.as_refs(), &response.response_aux.as_refs())?,
+                    _ => self.respond_to_define(&response.response_aux.as_refs())?,
+                },
+                Some((_, Reply::Error(e))) => self.respond_with_error(e.err_type, &e.aux.as_refs())?,
+                Some((_, Reply::Hello)) => unreachable!("only a hello is answered with a hello"),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, os::unix::net::UnixStream, thread};
+
+    use super::*;
+    use crate::{endpoint::Subordinate, transport::SocketTransport};
+
+    // answers every peek with the key and `suffix`
+    fn connect(suffix: &'static str) -> (ControllerProcess, thread::JoinHandle<()>) {
+        let (a, b) = UnixStream::pair().unwrap();
+        let subordinate = thread::spawn(move || {
+            let mut subordinate = SubordinateProcess::new(Box::new(SocketTransport::new(b)));
+            subordinate.hello().unwrap();
+            while let Request {
+                command: ProtocolCommand::Peek(key),
+                ..
+            } = subordinate.recv_command().unwrap()
+            {
+                let value = format!("{}{}", key, suffix);
+                subordinate.respond_to_peek(&[value.as_str().into()], &[]).unwrap();
+            }
+        });
+        let mut controller =
+            ControllerProcess::new(Subordinate::Connected, Box::new(SocketTransport::new(a)));
+        controller.hello().unwrap();
+        (controller, subordinate)
+    }
+
+    #[test]
+    fn replays_diff_a_subordinate_and_serve_a_controller() {
+        let path = std::env::temp_dir().join(format!("ufo_ipc_replay_{}", std::process::id()));
+        let (mut controller, subordinate) = connect("");
+        controller.trace_to(&path).unwrap();
+        controller.peek("a", &[]).unwrap();
+        controller.peek("b", &[]).unwrap();
+        controller.shutdown(&[]).unwrap();
+        subordinate.join().unwrap();
+        let recording = Recording::read(File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let session = &recording.sessions()[0];
+
+        let (mut controller, subordinate) = connect("");
+        assert!(controller.replay(session).unwrap().is_empty());
+        subordinate.join().unwrap();
+
+        let (mut controller, subordinate) = connect("!");
+        let divergences = controller.replay(session).unwrap();
+        subordinate.join().unwrap();
+        assert_eq!(divergences.iter().map(|d| d.index).collect::<Vec<_>>(), [0, 1]);
+
+        let (a, b) = UnixStream::pair().unwrap();
+        thread::scope(|scope| {
+            let served = scope.spawn(|| {
+                SubordinateProcess::new(Box::new(SocketTransport::new(b))).serve(session)
+            });
+            let mut controller =
+                ControllerProcess::new(Subordinate::Connected, Box::new(SocketTransport::new(a)));
+            let value = controller.peek("a", &[]).unwrap().value;
+            assert_eq!(value, [GenericValueBoxed::Vstring("a".to_string())]);
+            assert!(controller.peek("c", &[]).is_err());
+            assert!(served.join().unwrap().is_err());
+        });
+    }
+}
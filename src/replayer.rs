@@ -0,0 +1,69 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Result},
+    process::Command,
+};
+
+use ufo_ipc::*;
+
+// ufo-ipc-replay TRACE SUBORDINATE [ARGS..], resends the recorded commands to a fresh subordinate
+// and prints every reply that differs. ufo-ipc-replay --serve TRACE, started as a subordinate,
+// answers the controller with the recorded replies.
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["--serve", trace] => serve(trace),
+        [trace, subordinate, ref args @ ..] => replay(trace, subordinate, args),
+        _ => {
+            eprintln!("usage: ufo-ipc-replay TRACE SUBORDINATE [ARGS..]");
+            eprintln!("       ufo-ipc-replay --serve TRACE");
+            std::process::exit(2);
+        }
+    }
+}
+
+fn read(trace: &str) -> Result<Recording> {
+    Recording::read(BufReader::new(File::open(trace)?))
+}
+
+fn replay(trace: &str, subordinate: &str, args: &[&str]) -> Result<()> {
+    let recording = read(trace)?;
+    let mut diverged = false;
+    for (i, session) in recording.sessions().iter().enumerate() {
+        if i > 0 {
+            println!("-- subordinate restarted --");
+        }
+        let mut controller =
+            Command::new(subordinate).args(args).start_subordinate_process_with_fds()?;
+        for divergence in controller.replay(session)? {
+            let exchange = &session.exchanges[divergence.index];
+            println!("-> {}", exchange.message);
+            match &exchange.reply {
+                Some((_, reply)) => println!("   recorded <- {}", reply),
+                None => println!("   recorded <- (no response)"),
+            }
+            match &divergence.replayed {
+                Ok(reply) => println!("   replayed <- {}", reply),
+                Err(e) => println!("   replayed !! {}", e),
+            }
+            diverged = true;
+        }
+        if let Some(e) = &session.error {
+            println!("!! replayed up to where the trace stops decoding: {}", e);
+        }
+    }
+    if diverged {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// only the first session, a restarted subordinate starts over
+fn serve(trace: &str) -> Result<()> {
+    let recording = read(trace)?;
+    let session = recording
+        .sessions()
+        .first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "empty trace"))?;
+    subordinate_begin()?.serve(session)
+}
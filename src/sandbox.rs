@@ -0,0 +1,415 @@
+use std::{
+    ffi::{CString, OsString},
+    io,
+    os::unix::process::CommandExt,
+    path::PathBuf,
+    process::Command,
+};
+
+use libc::{c_int, c_ulong, rlimit, sock_filter, sock_fprog};
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+// x32 syscalls share the x86_64 audit arch, and are told apart by this bit in their number
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+// offsets into struct seccomp_data
+const SECCOMP_DATA_NR: u32 = 0;
+const SECCOMP_DATA_ARCH: u32 = 4;
+
+/// Restrictions placed on a subordinate between `fork` and `exec`.
+///
+/// Start from `SubordinateSandbox::new()`, pick the restrictions, then `apply_to` the `Command`
+/// before starting the subordinate with it. A sandboxed `Command` can be handed to a
+/// `SubordinatePool` or a supervised subordinate, every restart goes through the same sandbox.
+#[derive(Debug, Clone, Default)]
+pub struct SubordinateSandbox {
+    rlimits: Vec<(c_int, u64)>,
+    syscalls: Option<Vec<i64>>,
+    no_new_privs: bool,
+    namespaces: c_int,
+    working_directory: Option<PathBuf>,
+    environment: Option<Vec<OsString>>,
+}
+
+impl SubordinateSandbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn rlimit(mut self, resource: c_int, limit: u64) -> Self {
+        self.rlimits.retain(|(r, _)| *r != resource);
+        self.rlimits.push((resource, limit));
+        self
+    }
+
+    pub fn address_space_limit(self, bytes: u64) -> Self {
+        self.rlimit(libc::RLIMIT_AS as c_int, bytes)
+    }
+
+    pub fn cpu_time_limit(self, seconds: u64) -> Self {
+        self.rlimit(libc::RLIMIT_CPU as c_int, seconds)
+    }
+
+    pub fn open_files_limit(self, count: u64) -> Self {
+        self.rlimit(libc::RLIMIT_NOFILE as c_int, count)
+    }
+
+    /// Only let the subordinate make these syscalls (`libc::SYS_*` numbers), any other fails with
+    /// `EPERM`. The filter is installed before `exec`, so `execve` is always allowed. Implies
+    /// `no_new_privs`. A syscall made through another ABI, such as x32, kills the subordinate.
+    pub fn allow_syscalls(mut self, syscalls: &[i64]) -> Self {
+        let allowed = self.syscalls.get_or_insert_with(|| vec![libc::SYS_execve]);
+        allowed.extend_from_slice(syscalls);
+        self.no_new_privs = true;
+        self
+    }
+
+    pub fn no_new_privs(mut self) -> Self {
+        self.no_new_privs = true;
+        self
+    }
+
+    /// The subordinate's own uid and gid are mapped to themselves inside the namespace.
+    pub fn user_namespace(mut self) -> Self {
+        self.namespaces |= libc::CLONE_NEWUSER;
+        self
+    }
+
+    pub fn mount_namespace(mut self) -> Self {
+        self.namespaces |= libc::CLONE_NEWNS;
+        self
+    }
+
+    /// Leaves the subordinate with nothing but a loopback interface, which is down.
+    pub fn network_namespace(mut self) -> Self {
+        self.namespaces |= libc::CLONE_NEWNET;
+        self
+    }
+
+    pub fn working_directory<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.working_directory = Some(path.into());
+        self
+    }
+
+    /// Once anything is allowed, the subordinate only sees the allowed variables from the
+    /// controller's environment, plus whatever is set on the `Command` after `apply_to`.
+    pub fn allow_env<K: Into<OsString>>(mut self, key: K) -> Self {
+        self.environment
+            .get_or_insert_with(Vec::new)
+            .push(key.into());
+        self
+    }
+
+    pub fn apply_to<'c>(&self, command: &'c mut Command) -> &'c mut Command {
+        if let Some(path) = &self.working_directory {
+            command.current_dir(path);
+        }
+
+        if let Some(keys) = &self.environment {
+            command.env_clear();
+            for key in keys {
+                if let Some(value) = std::env::var_os(key) {
+                    command.env(key, value);
+                }
+            }
+        }
+
+        let confinement = self.confinement();
+        unsafe { command.pre_exec(move || confinement.enter()) }
+    }
+
+    // everything the child needs is built here, after the fork it may not allocate
+    fn confinement(&self) -> Confinement {
+        Confinement {
+            rlimits: self.rlimits.clone(),
+            filter: self.syscalls.as_deref().map(seccomp_filter),
+            no_new_privs: self.no_new_privs,
+            namespaces: self.namespaces,
+            id_maps: IdMaps::new(),
+        }
+    }
+}
+
+fn statement(code: u32, k: u32) -> sock_filter {
+    sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn jump_if_equal(k: u32, jt: u8, jf: u8) -> sock_filter {
+    sock_filter {
+        code: (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+fn seccomp_filter(syscalls: &[i64]) -> Vec<sock_filter> {
+    let deny = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+    let allow = libc::SECCOMP_RET_ALLOW;
+    let load = libc::BPF_LD | libc::BPF_W | libc::BPF_ABS;
+    let ret = libc::BPF_RET | libc::BPF_K;
+
+    let mut filter = vec![
+        // a syscall from another architecture's table would not mean what the numbers say
+        statement(load, SECCOMP_DATA_ARCH),
+        jump_if_equal(AUDIT_ARCH, 1, 0),
+        statement(ret, libc::SECCOMP_RET_KILL_PROCESS),
+        statement(load, SECCOMP_DATA_NR),
+    ];
+    #[cfg(target_arch = "x86_64")]
+    filter.extend([
+        sock_filter {
+            code: (libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K) as u16,
+            jt: 0,
+            jf: 1,
+            k: X32_SYSCALL_BIT,
+        },
+        statement(ret, libc::SECCOMP_RET_KILL_PROCESS),
+    ]);
+    for syscall in syscalls {
+        filter.push(jump_if_equal(*syscall as u32, 0, 1));
+        filter.push(statement(ret, allow));
+    }
+    filter.push(statement(ret, deny));
+    filter
+}
+
+struct IdMaps {
+    uid_map: (CString, String),
+    gid_map: (CString, String),
+    setgroups: (CString, String),
+}
+
+impl IdMaps {
+    fn new() -> Self {
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let file = |path: &str, contents: String| (CString::new(path).unwrap(), contents);
+        IdMaps {
+            uid_map: file("/proc/self/uid_map", format!("{uid} {uid} 1\n")),
+            gid_map: file("/proc/self/gid_map", format!("{gid} {gid} 1\n")),
+            // an unprivileged process has to give up setgroups before it may write gid_map
+            setgroups: file("/proc/self/setgroups", "deny\n".to_string()),
+        }
+    }
+
+    fn write((path, contents): &(CString, String)) -> io::Result<()> {
+        unsafe {
+            let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let written = libc::write(fd, contents.as_ptr().cast(), contents.len());
+            let result = if written < 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(())
+            };
+            libc::close(fd);
+            result
+        }
+    }
+
+    fn enter(&self) -> io::Result<()> {
+        Self::write(&self.setgroups)?;
+        Self::write(&self.uid_map)?;
+        Self::write(&self.gid_map)
+    }
+}
+
+struct Confinement {
+    rlimits: Vec<(c_int, u64)>,
+    filter: Option<Vec<sock_filter>>,
+    no_new_privs: bool,
+    namespaces: c_int,
+    id_maps: IdMaps,
+}
+
+fn check(result: c_int) -> io::Result<()> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+impl Confinement {
+    // runs in the child between fork and exec
+    fn enter(&self) -> io::Result<()> {
+        unsafe {
+            if self.namespaces != 0 {
+                check(libc::unshare(self.namespaces))?;
+                if self.namespaces & libc::CLONE_NEWUSER != 0 {
+                    self.id_maps.enter()?;
+                }
+            }
+
+            for (resource, limit) in &self.rlimits {
+                let limit = rlimit {
+                    rlim_cur: *limit,
+                    rlim_max: *limit,
+                };
+                // glibc and musl disagree on the type of the resource
+                check(libc::setrlimit(*resource as _, &limit))?;
+            }
+
+            if self.no_new_privs {
+                check(libc::prctl(
+                    libc::PR_SET_NO_NEW_PRIVS,
+                    1 as c_ulong,
+                    0,
+                    0,
+                    0,
+                ))?;
+            }
+
+            // last, so nothing above has to be on the allow-list
+            if let Some(filter) = &self.filter {
+                let program = sock_fprog {
+                    len: filter.len() as u16,
+                    filter: filter.as_ptr() as *mut sock_filter,
+                };
+                check(libc::prctl(
+                    libc::PR_SET_SECCOMP,
+                    libc::SECCOMP_MODE_FILTER as c_ulong,
+                    &program as *const sock_fprog as c_ulong,
+                    0,
+                    0,
+                ))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, mem};
+
+    use super::*;
+
+    // what a child that couldn't enter the confinement for lack of privilege exits with
+    const NOT_PERMITTED: c_int = 77;
+
+    // enter the confinement in a forked child and run `check` there, which like everything after
+    // the fork may only do what is async-signal-safe. Gives the child's exit status
+    fn in_child<F: Fn() -> bool>(confinement: Confinement, check: F) -> c_int {
+        unsafe {
+            let child = libc::fork();
+            assert!(child >= 0);
+            if child == 0 {
+                let status = match confinement.enter() {
+                    Ok(()) if check() => 0,
+                    Ok(()) => 1,
+                    Err(e) if e.raw_os_error() == Some(libc::EPERM) => NOT_PERMITTED,
+                    Err(_) => 2,
+                };
+                libc::_exit(status);
+            }
+            let mut status = 0;
+            assert_eq!(libc::waitpid(child, &mut status, 0), child);
+            assert!(libc::WIFEXITED(status), "killed with status {}", status);
+            libc::WEXITSTATUS(status)
+        }
+    }
+
+    #[test]
+    fn syscalls_off_the_list_are_refused() {
+        let confinement = SubordinateSandbox::new()
+            .allow_syscalls(&[libc::SYS_getpid, libc::SYS_exit_group])
+            .confinement();
+        let status = in_child(confinement, || unsafe {
+            libc::syscall(libc::SYS_getpid) > 0
+                && libc::syscall(libc::SYS_getppid) < 0
+                && *libc::__errno_location() == libc::EPERM
+        });
+        assert_eq!(status, 0);
+    }
+
+    #[test]
+    fn limits_and_no_new_privs_hold_after_entering() {
+        let sandbox = SubordinateSandbox::new()
+            .address_space_limit(1 << 40)
+            .cpu_time_limit(100)
+            .open_files_limit(64);
+        let limits = [
+            (libc::RLIMIT_AS, 1 << 40),
+            (libc::RLIMIT_CPU, 100),
+            (libc::RLIMIT_NOFILE, 64),
+        ];
+        let status = in_child(sandbox.confinement(), || unsafe {
+            limits.iter().all(|(resource, expected)| {
+                let mut limit = mem::zeroed::<rlimit>();
+                libc::getrlimit(*resource, &mut limit) == 0
+                    && limit.rlim_cur == *expected
+                    && limit.rlim_max == *expected
+            })
+        });
+        assert_eq!(status, 0);
+
+        let no_new_privs = || unsafe { libc::prctl(libc::PR_GET_NO_NEW_PRIVS, 0, 0, 0, 0) };
+        assert_eq!(in_child(SubordinateSandbox::new().confinement(), || no_new_privs() == 0), 0);
+        let confinement = SubordinateSandbox::new().no_new_privs().confinement();
+        assert_eq!(in_child(confinement, || no_new_privs() == 1), 0);
+    }
+
+    #[test]
+    fn namespaces_are_new_with_the_same_ids() {
+        let namespaces: Vec<CString> = ["user", "mnt", "net"]
+            .iter()
+            .map(|ns| CString::new(format!("/proc/self/ns/{}", ns)).unwrap())
+            .collect();
+        let inode = |path: &CString| unsafe {
+            let mut stat = mem::zeroed::<libc::stat>();
+            libc::stat(path.as_ptr(), &mut stat);
+            stat.st_ino
+        };
+        let outside: Vec<u64> = namespaces.iter().map(inode).collect();
+        let ids = unsafe { (libc::getuid(), libc::getgid()) };
+
+        let confinement = SubordinateSandbox::new()
+            .user_namespace()
+            .mount_namespace()
+            .network_namespace()
+            .confinement();
+        let status = in_child(confinement, || unsafe {
+            namespaces.iter().zip(&outside).all(|(ns, outside)| inode(ns) != *outside)
+                && (libc::getuid(), libc::getgid()) == ids
+        });
+        if status == NOT_PERMITTED {
+            eprintln!("skipped, namespaces can't be created here");
+            return;
+        }
+        assert_eq!(status, 0);
+    }
+
+    fn output(command: &mut Command) -> String {
+        let output = command.output().unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn commands_start_where_and_with_what_they_are_given() {
+        let sandbox = SubordinateSandbox::new().working_directory("/");
+        assert_eq!(output(sandbox.apply_to(&mut Command::new("pwd"))), "/\n");
+
+        let sandbox = SubordinateSandbox::new()
+            .allow_env("PATH")
+            .allow_env("UFO_IPC_TEST_NEVER_SET");
+        let mut command = Command::new("env");
+        sandbox.apply_to(&mut command).env("UFO_IPC_TEST_ADDED", "1");
+        let env = output(&mut command);
+        let keys: BTreeSet<&str> = env.lines().filter_map(|l| Some(l.split_once('=')?.0)).collect();
+        assert_eq!(keys, BTreeSet::from(["PATH", "UFO_IPC_TEST_ADDED"]));
+    }
+}
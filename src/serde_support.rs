@@ -0,0 +1,675 @@
+use serde::{
+    de::{self, DeserializeOwned, IntoDeserializer, Visitor},
+    ser::{self, Serialize},
+    Deserialize,
+};
+use std::{io, result::Result};
+
+use crate::*;
+
+// Rust values map onto generic values like this:
+//  • sequences are a List, tuples and tuple structs a Tuple
+//  • maps are a Map, structs a Struct
+//  • None is Null, Some(v) is just v
+//  • unit and unit structs are an empty Tuple
+//  • a unit variant is its name, any other variant a one entry Map from its name to its contents
+// so Some(None) and None can't be told apart once they are sent
+
+/// Serialize any `Serialize` value as a `GenericValue`.
+pub fn to_generic<T: Serialize + ?Sized>(value: &T) -> Result<GenericValueBoxed, SerdeError> {
+    value.serialize(GenericSerializer)
+}
+
+/// Deserialize a value out of a `GenericValue`, borrowing strings and bytes from it where the type
+/// allows.
+pub fn from_generic<'de, T: Deserialize<'de>>(value: GenericValueRef<'de>) -> Result<T, SerdeError> {
+    T::deserialize(value)
+}
+
+fn empty() -> GenericValueBoxed {
+    GenericValue::Tuple(Vec::new())
+}
+
+fn variant(name: &str, value: GenericValueBoxed) -> GenericValueBoxed {
+    GenericValue::Map(vec![(GenericValue::Vstring(name.to_string()), value)])
+}
+
+struct GenericSerializer;
+
+impl ser::Serializer for GenericSerializer {
+    type Ok = GenericValueBoxed;
+    type Error = SerdeError;
+
+    type SerializeSeq = SerializeList;
+    type SerializeTuple = SerializeList;
+    type SerializeTupleStruct = SerializeList;
+    type SerializeTupleVariant = SerializeList;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeMap;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(GenericValue::Vbool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        Ok(GenericValue::Vi8(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        Ok(GenericValue::Vi16(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        Ok(GenericValue::Vi32(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(GenericValue::Vi64(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        Ok(GenericValue::Vu8(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        Ok(GenericValue::Vu16(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        Ok(GenericValue::Vu32(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        Ok(GenericValue::Vu64(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        Ok(GenericValue::Vf32(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        Ok(GenericValue::Vf64(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Ok(GenericValue::Vstring(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        Ok(GenericValue::Vstring(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_vec().into())
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Ok(GenericValue::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Ok(empty())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Ok(empty())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(GenericValue::Vstring(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(variant(name, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Ok(SerializeList::new(ListKind::List, len))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Ok(SerializeList::new(ListKind::Tuple, Some(len)))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Ok(SerializeList::new(ListKind::Tuple, Some(len)))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Ok(SerializeList::new(ListKind::Variant(name), Some(len)))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(SerializeMap::new(MapKind::Map, len))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Ok(SerializeMap::new(MapKind::Struct, Some(len)))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Ok(SerializeMap::new(MapKind::Variant(name), Some(len)))
+    }
+}
+
+enum ListKind {
+    List,
+    Tuple,
+    Variant(&'static str),
+}
+
+struct SerializeList {
+    kind: ListKind,
+    values: Vec<GenericValueBoxed>,
+}
+
+impl SerializeList {
+    fn new(kind: ListKind, len: Option<usize>) -> Self {
+        SerializeList {
+            kind,
+            values: Vec::with_capacity(len.unwrap_or(0)),
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.values.push(to_generic(value)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<GenericValueBoxed, SerdeError> {
+        Ok(match self.kind {
+            ListKind::List => GenericValue::List(self.values),
+            ListKind::Tuple => GenericValue::Tuple(self.values),
+            ListKind::Variant(name) => variant(name, GenericValue::Tuple(self.values)),
+        })
+    }
+}
+
+impl ser::SerializeSeq for SerializeList {
+    type Ok = GenericValueBoxed;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SerializeList {
+    type Ok = GenericValueBoxed;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+    type Ok = GenericValueBoxed;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeList {
+    type Ok = GenericValueBoxed;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+enum MapKind {
+    Map,
+    Struct,
+    Variant(&'static str),
+}
+
+struct SerializeMap {
+    kind: MapKind,
+    entries: Vec<(GenericValueBoxed, GenericValueBoxed)>,
+    key: Option<GenericValueBoxed>,
+}
+
+impl SerializeMap {
+    fn new(kind: MapKind, len: Option<usize>) -> Self {
+        SerializeMap {
+            kind,
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        }
+    }
+
+    fn field<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), SerdeError> {
+        self.entries
+            .push((GenericValue::Vstring(key.to_string()), to_generic(value)?));
+        Ok(())
+    }
+
+    fn finish(self) -> Result<GenericValueBoxed, SerdeError> {
+        Ok(match self.kind {
+            MapKind::Map => GenericValue::Map(self.entries),
+            MapKind::Struct => GenericValue::Struct(self.entries),
+            MapKind::Variant(name) => variant(name, GenericValue::Struct(self.entries)),
+        })
+    }
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = GenericValueBoxed;
+    type Error = SerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerdeError> {
+        self.key = Some(to_generic(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| SerdeError::Message("map value without a key".to_string()))?;
+        self.entries.push((key, to_generic(value)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+    type Ok = GenericValueBoxed;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeMap {
+    type Ok = GenericValueBoxed;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl<'de> de::Deserializer<'de> for GenericValueRef<'de> {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self {
+            GenericValue::Vu8(v) => visitor.visit_u8(v),
+            GenericValue::Vi8(v) => visitor.visit_i8(v),
+            GenericValue::Vu16(v) => visitor.visit_u16(v),
+            GenericValue::Vi16(v) => visitor.visit_i16(v),
+            GenericValue::Vu32(v) => visitor.visit_u32(v),
+            GenericValue::Vi32(v) => visitor.visit_i32(v),
+            GenericValue::Vu64(v) => visitor.visit_u64(v),
+            GenericValue::Vi64(v) => visitor.visit_i64(v),
+            GenericValue::Vf32(v) => visitor.visit_f32(v),
+            GenericValue::Vf64(v) => visitor.visit_f64(v),
+            GenericValue::Vusize(v) => visitor.visit_u64(v as u64),
+            GenericValue::Visize(v) => visitor.visit_i64(v as i64),
+            GenericValue::Vbool(v) => visitor.visit_bool(v),
+            GenericValue::Vstring(v) => visitor.visit_borrowed_str(v),
+            GenericValue::Vbytes(v) => visitor.visit_borrowed_bytes(v),
+            GenericValue::Token(v) => visitor.visit_u64(v.0),
+            GenericValue::Null => visitor.visit_none(),
+            GenericValue::Function(v) => visitor.visit_u64(v.0),
+            GenericValue::Marker(v) => visitor.visit_u8(v),
+            GenericValue::Fd(_) => Err(de::Error::custom("file descriptors can't be deserialized")),
+            GenericValue::List(v) | GenericValue::Tuple(v) => {
+                visitor.visit_seq(de::value::SeqDeserializer::new(v.iter()))
+            }
+            GenericValue::Map(v) | GenericValue::Struct(v) => {
+                visitor.visit_map(de::value::MapDeserializer::new(v.iter()))
+            }
+            GenericValue::Vu8s(v) => visitor.visit_seq(de::value::SeqDeserializer::new(v.iter())),
+            GenericValue::Vi8s(v) => visitor.visit_seq(de::value::SeqDeserializer::new(v.iter())),
+            GenericValue::Vu16s(v) => visitor.visit_seq(de::value::SeqDeserializer::new(v.iter())),
+            GenericValue::Vi16s(v) => visitor.visit_seq(de::value::SeqDeserializer::new(v.iter())),
+            GenericValue::Vu32s(v) => visitor.visit_seq(de::value::SeqDeserializer::new(v.iter())),
+            GenericValue::Vi32s(v) => visitor.visit_seq(de::value::SeqDeserializer::new(v.iter())),
+            GenericValue::Vu64s(v) => visitor.visit_seq(de::value::SeqDeserializer::new(v.iter())),
+            GenericValue::Vi64s(v) => visitor.visit_seq(de::value::SeqDeserializer::new(v.iter())),
+            GenericValue::Vf32s(v) => visitor.visit_seq(de::value::SeqDeserializer::new(v.iter())),
+            GenericValue::Vf64s(v) => visitor.visit_seq(de::value::SeqDeserializer::new(v.iter())),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self {
+            GenericValue::Null => visitor.visit_none(),
+            v => visitor.visit_some(v),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self {
+            GenericValue::Null => visitor.visit_unit(),
+            GenericValue::Tuple(v) if v.is_empty() => visitor.visit_unit(),
+            v => v.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        match self {
+            GenericValue::Vstring(name) => visitor.visit_enum(name.into_deserializer()),
+            GenericValue::Map(entries) if entries.len() == 1 => {
+                let (name, value) = entries.entry(0).expect("map has one entry");
+                visitor.visit_enum(EnumAccess { name, value })
+            }
+            v => Err(de::Error::invalid_type(
+                unexpected(v),
+                &"a variant name or a one entry map",
+            )),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, SerdeError> for GenericValueRef<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+fn unexpected(value: GenericValueRef) -> de::Unexpected {
+    match value {
+        GenericValue::Vbool(v) => de::Unexpected::Bool(v),
+        GenericValue::Vstring(v) => de::Unexpected::Str(v),
+        GenericValue::Vbytes(v) => de::Unexpected::Bytes(v),
+        GenericValue::List(_) | GenericValue::Tuple(_) => de::Unexpected::Seq,
+        GenericValue::Map(_) | GenericValue::Struct(_) => de::Unexpected::Map,
+        _ => de::Unexpected::Other("a generic value"),
+    }
+}
+
+struct EnumAccess<'de> {
+    name: GenericValueRef<'de>,
+    value: GenericValueRef<'de>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess<'de> {
+    type Error = SerdeError;
+    type Variant = GenericValueRef<'de>;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), SerdeError> {
+        Ok((seed.deserialize(self.name)?, self.value))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for GenericValueRef<'de> {
+    type Error = SerdeError;
+
+    fn unit_variant(self) -> Result<(), SerdeError> {
+        Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, SerdeError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, SerdeError> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+}
+
+// a single value is taken on its own, several as a tuple
+fn from_results<R: DeserializeOwned>(mut values: Vec<GenericValueBoxed>) -> io::Result<R> {
+    let value = match values.len() {
+        1 => values.pop().expect("one value"),
+        _ => GenericValue::Tuple(values),
+    };
+    Ok(from_generic(GenericValueRef::from(&value))?)
+}
+
+// a tuple is spread over the arguments, anything else is the only argument
+fn to_args<A: Serialize + ?Sized>(args: &A) -> io::Result<Vec<GenericValueBoxed>> {
+    Ok(match to_generic(args)? {
+        GenericValue::Tuple(values) => values,
+        value => vec![value],
+    })
+}
+
+impl ControllerProcess {
+    /// `call_function` with the arguments and result converted through serde. A tuple of
+    /// arguments is passed as separate arguments, and a function returning several values is
+    /// read back as a tuple.
+    pub fn call_function_typed<A, R>(
+        &mut self,
+        token: &FunctionToken,
+        args: &A,
+        aux: &[GenericValueRef],
+    ) -> io::Result<Response<R>>
+    where
+        A: Serialize + ?Sized,
+        R: DeserializeOwned,
+    {
+        let args = to_args(args)?;
+        let args: Vec<GenericValueRef> = args.iter().map(GenericValueRef::from).collect();
+        let response = self.call_function(token, &args, aux)?;
+        Ok(Response {
+            logs: response.logs,
+            response_aux: response.response_aux,
+            value: from_results(response.value)?,
+        })
+    }
+
+    pub fn peek_typed<T: DeserializeOwned>(
+        &mut self,
+        key: &str,
+        aux: &[GenericValueRef],
+    ) -> io::Result<Response<T>> {
+        let response = self.peek(key, aux)?;
+        Ok(Response {
+            logs: response.logs,
+            response_aux: response.response_aux,
+            value: from_results(response.value)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+    use std::{collections::BTreeMap, os::unix::net::UnixStream, thread};
+
+    use super::*;
+    use crate::{testing::serve, transport::SocketTransport};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Dot,
+        Circle(f64),
+        Rect { w: u32, h: u32 },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Drawing {
+        name: String,
+        caption: Option<String>,
+        layers: BTreeMap<String, u8>,
+        shapes: Vec<Shape>,
+    }
+
+    fn drawing() -> Drawing {
+        Drawing {
+            name: "sketch".to_string(),
+            caption: None,
+            layers: [("ink".to_string(), 1), ("paper".to_string(), 0)].into_iter().collect(),
+            shapes: vec![Shape::Dot, Shape::Circle(1.5), Shape::Rect { w: 3, h: 4 }],
+        }
+    }
+
+    #[test]
+    fn values_come_back_from_generic_as_they_went_in() {
+        let generic = to_generic(&drawing()).unwrap();
+        assert_eq!(from_generic::<Drawing>(GenericValueRef::from(&generic)).unwrap(), drawing());
+
+        let captioned = Drawing {
+            caption: Some("first".to_string()),
+            ..drawing()
+        };
+        let generic = to_generic(&captioned).unwrap();
+        assert_eq!(from_generic::<Drawing>(GenericValueRef::from(&generic)).unwrap(), captioned);
+
+        // Some(None) is sent as None, see above
+        let generic = to_generic(&Some(None::<u8>)).unwrap();
+        let read: Option<Option<u8>> = from_generic(GenericValueRef::from(&generic)).unwrap();
+        assert_eq!(read, None);
+    }
+
+    #[test]
+    fn typed_calls_and_peeks_go_through_serde() {
+        let (a, b) = UnixStream::pair().unwrap();
+        let served = thread::spawn(move || {
+            let mut subordinate = SubordinateProcess::new(Box::new(SocketTransport::new(b)));
+            subordinate.hello()?;
+            serve(subordinate)
+        });
+        let mut controller =
+            ControllerProcess::new(Subordinate::Connected, Box::new(SocketTransport::new(a)));
+        controller.hello().unwrap();
+
+        // the subordinate's functions answer with their arguments
+        let echo = controller.define_function(b"echo", &[], &[]).unwrap().value;
+        let response = controller.call_function_typed::<_, Drawing>(&echo, &drawing(), &[]);
+        assert_eq!(response.unwrap().value, drawing());
+        let response = controller.call_function_typed::<_, (u8, String)>(&echo, &(7, "seven"), &[]);
+        assert_eq!(response.unwrap().value, (7, "seven".to_string()));
+
+        let poked = to_generic(&drawing()).unwrap();
+        controller.poke("drawing", &[GenericValueRef::from(&poked)], &[]).unwrap();
+        let response = controller.peek_typed::<Drawing>("drawing", &[]).unwrap();
+        assert_eq!(response.value, drawing());
+        // nothing poked is a lone null
+        let response = controller.peek_typed::<Option<Drawing>>("nothing", &[]).unwrap();
+        assert_eq!(response.value, None);
+
+        controller.shutdown(&[]).unwrap();
+        served.join().unwrap().unwrap();
+    }
+}
@@ -1,9 +1,53 @@
 use derive_try_from_primitive::TryFromPrimitive;
+use std::{
+    fmt, io,
+    marker::PhantomData,
+    os::unix::io::{AsFd, AsRawFd, BorrowedFd, OwnedFd},
+};
 
-use crate::{err::UnexpectedGenericType, DataToken};
+use bytemuck::Pod;
+
+use crate::{
+    err::UnexpectedGenericType, DataToken, FunctionToken, ListRef, MapRef, PodSlice,
+};
+
+/// The types a `GenericValue` keeps its non-scalar contents in, one set borrowed and one owned.
+pub trait GenericStorage {
+    type Bytes: fmt::Debug;
+    type Str: fmt::Debug;
+    type Fd: fmt::Debug;
+    type List: fmt::Debug;
+    type Map: fmt::Debug;
+    type Array<T: Pod + fmt::Debug>: fmt::Debug;
+}
 
 #[derive(Copy, Clone, Debug)]
-pub enum GenericValue<ArrType, StrType> {
+pub struct RefStorage<'a>(PhantomData<&'a ()>);
+
+impl<'a> GenericStorage for RefStorage<'a> {
+    type Bytes = &'a [u8];
+    type Str = &'a str;
+    type Fd = BorrowedFd<'a>;
+    type List = ListRef<'a>;
+    type Map = MapRef<'a>;
+    type Array<T: Pod + fmt::Debug> = PodSlice<'a, T>;
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct BoxedStorage;
+
+impl GenericStorage for BoxedStorage {
+    type Bytes = Vec<u8>;
+    type Str = String;
+    type Fd = OwnedFd;
+    type List = Vec<GenericValueBoxed>;
+    type Map = Vec<(GenericValueBoxed, GenericValueBoxed)>;
+    type Array<T: Pod + fmt::Debug> = Vec<T>;
+}
+
+// Debug is written out, deriving it would need `Vec<GenericValueBoxed>: Debug` to prove itself
+#[derive(Copy, Clone)]
+pub enum GenericValue<S: GenericStorage> {
     Vu8(u8),
     Vi8(i8),
     Vu16(u16),
@@ -17,13 +61,156 @@ pub enum GenericValue<ArrType, StrType> {
     Vusize(usize),
     Visize(isize),
     Vbool(bool),
-    Vstring(StrType),
-    Vbytes(ArrType),
+    Vstring(S::Str),
+    Vbytes(S::Bytes),
     Token(DataToken),
+    // only over transports that can pass file descriptors, see `Transport::send_fd`
+    Fd(S::Fd),
+    List(S::List),
+    // keys can be any value, look them up with `MapRef::get` when they are strings
+    Map(S::Map),
+    Tuple(S::List),
+    // keys are the field names, always strings
+    Struct(S::Map),
+    // packed arrays of one element type
+    Vu8s(S::Array<u8>),
+    Vi8s(S::Array<i8>),
+    Vu16s(S::Array<u16>),
+    Vi16s(S::Array<i16>),
+    Vu32s(S::Array<u32>),
+    Vi32s(S::Array<i32>),
+    Vu64s(S::Array<u64>),
+    Vi64s(S::Array<i64>),
+    Vf32s(S::Array<f32>),
+    Vf64s(S::Array<f64>),
+    // no value, a peek of a missing key or a `None`
+    Null,
+    // a function defined in the subordinate, to pass to or return from another
+    Function(FunctionToken),
 
     Marker(u8),
 }
 
+impl<S: GenericStorage> fmt::Debug for GenericValue<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenericValue::Vu8(v) => f.debug_tuple("Vu8").field(v).finish(),
+            GenericValue::Vi8(v) => f.debug_tuple("Vi8").field(v).finish(),
+            GenericValue::Vu16(v) => f.debug_tuple("Vu16").field(v).finish(),
+            GenericValue::Vi16(v) => f.debug_tuple("Vi16").field(v).finish(),
+            GenericValue::Vu32(v) => f.debug_tuple("Vu32").field(v).finish(),
+            GenericValue::Vi32(v) => f.debug_tuple("Vi32").field(v).finish(),
+            GenericValue::Vu64(v) => f.debug_tuple("Vu64").field(v).finish(),
+            GenericValue::Vi64(v) => f.debug_tuple("Vi64").field(v).finish(),
+            GenericValue::Vf32(v) => f.debug_tuple("Vf32").field(v).finish(),
+            GenericValue::Vf64(v) => f.debug_tuple("Vf64").field(v).finish(),
+            GenericValue::Vusize(v) => f.debug_tuple("Vusize").field(v).finish(),
+            GenericValue::Visize(v) => f.debug_tuple("Visize").field(v).finish(),
+            GenericValue::Vbool(v) => f.debug_tuple("Vbool").field(v).finish(),
+            GenericValue::Vstring(v) => f.debug_tuple("Vstring").field(v).finish(),
+            GenericValue::Vbytes(v) => f.debug_tuple("Vbytes").field(v).finish(),
+            GenericValue::Token(v) => f.debug_tuple("Token").field(v).finish(),
+            GenericValue::Fd(v) => f.debug_tuple("Fd").field(v).finish(),
+            GenericValue::List(v) => f.debug_tuple("List").field(v).finish(),
+            GenericValue::Map(v) => f.debug_tuple("Map").field(v).finish(),
+            GenericValue::Tuple(v) => f.debug_tuple("Tuple").field(v).finish(),
+            GenericValue::Struct(v) => f.debug_tuple("Struct").field(v).finish(),
+            GenericValue::Vu8s(v) => f.debug_tuple("Vu8s").field(v).finish(),
+            GenericValue::Vi8s(v) => f.debug_tuple("Vi8s").field(v).finish(),
+            GenericValue::Vu16s(v) => f.debug_tuple("Vu16s").field(v).finish(),
+            GenericValue::Vi16s(v) => f.debug_tuple("Vi16s").field(v).finish(),
+            GenericValue::Vu32s(v) => f.debug_tuple("Vu32s").field(v).finish(),
+            GenericValue::Vi32s(v) => f.debug_tuple("Vi32s").field(v).finish(),
+            GenericValue::Vu64s(v) => f.debug_tuple("Vu64s").field(v).finish(),
+            GenericValue::Vi64s(v) => f.debug_tuple("Vi64s").field(v).finish(),
+            GenericValue::Vf32s(v) => f.debug_tuple("Vf32s").field(v).finish(),
+            GenericValue::Vf64s(v) => f.debug_tuple("Vf64s").field(v).finish(),
+            GenericValue::Null => f.write_str("Null"),
+            GenericValue::Function(v) => f.debug_tuple("Function").field(v).finish(),
+            GenericValue::Marker(v) => f.debug_tuple("Marker").field(v).finish(),
+        }
+    }
+}
+
+// written for people rather than for parsing, a string is quoted and a struct's field names
+// aren't
+impl fmt::Display for GenericValueRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list<T: fmt::Display>(
+            f: &mut fmt::Formatter<'_>,
+            open: &str,
+            close: &str,
+            items: impl Iterator<Item = T>,
+        ) -> fmt::Result {
+            f.write_str(open)?;
+            for (i, item) in items.enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{}", item)?;
+            }
+            f.write_str(close)
+        }
+
+        match *self {
+            GenericValue::Vu8(v) => write!(f, "{}", v),
+            GenericValue::Vi8(v) => write!(f, "{}", v),
+            GenericValue::Vu16(v) => write!(f, "{}", v),
+            GenericValue::Vi16(v) => write!(f, "{}", v),
+            GenericValue::Vu32(v) => write!(f, "{}", v),
+            GenericValue::Vi32(v) => write!(f, "{}", v),
+            GenericValue::Vu64(v) => write!(f, "{}", v),
+            GenericValue::Vi64(v) => write!(f, "{}", v),
+            // Debug keeps the point, so 1.0 doesn't read as an integer
+            GenericValue::Vf32(v) => write!(f, "{:?}", v),
+            GenericValue::Vf64(v) => write!(f, "{:?}", v),
+            GenericValue::Vusize(v) => write!(f, "{}", v),
+            GenericValue::Visize(v) => write!(f, "{}", v),
+            GenericValue::Vbool(v) => write!(f, "{}", v),
+            GenericValue::Vstring(v) => write!(f, "{:?}", v),
+            GenericValue::Vbytes(v) => write!(f, "b\"{}\"", v.escape_ascii()),
+            GenericValue::Token(v) => write!(f, "token {}", v.0),
+            GenericValue::Fd(v) => write!(f, "fd {}", v.as_raw_fd()),
+            GenericValue::List(v) => list(f, "[", "]", v.iter()),
+            GenericValue::Tuple(v) if v.len() == 1 => list(f, "(", ",)", v.iter()),
+            GenericValue::Tuple(v) => list(f, "(", ")", v.iter()),
+            GenericValue::Map(v) => list(f, "{", "}", v.iter().map(|(k, v)| Entry(k, v, false))),
+            GenericValue::Struct(v) => list(f, "{", "}", v.iter().map(|(k, v)| Entry(k, v, true))),
+            GenericValue::Vu8s(v) => list(f, "[", "]", v.iter()),
+            GenericValue::Vi8s(v) => list(f, "[", "]", v.iter()),
+            GenericValue::Vu16s(v) => list(f, "[", "]", v.iter()),
+            GenericValue::Vi16s(v) => list(f, "[", "]", v.iter()),
+            GenericValue::Vu32s(v) => list(f, "[", "]", v.iter()),
+            GenericValue::Vi32s(v) => list(f, "[", "]", v.iter()),
+            GenericValue::Vu64s(v) => list(f, "[", "]", v.iter()),
+            GenericValue::Vi64s(v) => list(f, "[", "]", v.iter()),
+            GenericValue::Vf32s(v) => list(f, "[", "]", v.iter().map(GenericValueRef::Vf32)),
+            GenericValue::Vf64s(v) => list(f, "[", "]", v.iter().map(GenericValueRef::Vf64)),
+            GenericValue::Null => f.write_str("null"),
+            GenericValue::Function(v) => write!(f, "function {}", v.0),
+            GenericValue::Marker(v) => write!(f, "marker {}", v),
+        }
+    }
+}
+
+// a map entry, with a string key left bare for a struct field
+struct Entry<'a>(GenericValueRef<'a>, GenericValueRef<'a>, bool);
+
+impl fmt::Display for Entry<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Entry(GenericValue::Vstring(key), value, true) => write!(f, "{}: {}", key, value),
+            Entry(key, value, _) => write!(f, "{}: {}", key, value),
+        }
+    }
+}
+
+impl fmt::Display for GenericValueBoxed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_ref().fmt(f)
+    }
+}
+
 impl<'a> From<&'a GenericValueBoxed> for GenericValueRef<'a> {
     fn from(boxed: &'a GenericValueBoxed) -> Self {
         match boxed {
@@ -43,21 +230,150 @@ impl<'a> From<&'a GenericValueBoxed> for GenericValueRef<'a> {
             GenericValueBoxed::Vstring(v) => GenericValueRef::Vstring(v.as_str()),
             GenericValueBoxed::Vbytes(v) => GenericValueRef::Vbytes(v.as_slice()),
             GenericValueBoxed::Token(v) => GenericValueRef::Token(*v),
+            GenericValueBoxed::Fd(v) => GenericValueRef::Fd(v.as_fd()),
+            GenericValueBoxed::List(v) => GenericValueRef::List(ListRef::Boxed(v)),
+            GenericValueBoxed::Map(v) => GenericValueRef::Map(MapRef::Boxed(v)),
+            GenericValueBoxed::Tuple(v) => GenericValueRef::Tuple(ListRef::Boxed(v)),
+            GenericValueBoxed::Struct(v) => GenericValueRef::Struct(MapRef::Boxed(v)),
+            GenericValueBoxed::Vu8s(v) => GenericValueRef::Vu8s(v.as_slice().into()),
+            GenericValueBoxed::Vi8s(v) => GenericValueRef::Vi8s(v.as_slice().into()),
+            GenericValueBoxed::Vu16s(v) => GenericValueRef::Vu16s(v.as_slice().into()),
+            GenericValueBoxed::Vi16s(v) => GenericValueRef::Vi16s(v.as_slice().into()),
+            GenericValueBoxed::Vu32s(v) => GenericValueRef::Vu32s(v.as_slice().into()),
+            GenericValueBoxed::Vi32s(v) => GenericValueRef::Vi32s(v.as_slice().into()),
+            GenericValueBoxed::Vu64s(v) => GenericValueRef::Vu64s(v.as_slice().into()),
+            GenericValueBoxed::Vi64s(v) => GenericValueRef::Vi64s(v.as_slice().into()),
+            GenericValueBoxed::Vf32s(v) => GenericValueRef::Vf32s(v.as_slice().into()),
+            GenericValueBoxed::Vf64s(v) => GenericValueRef::Vf64s(v.as_slice().into()),
+            GenericValueBoxed::Null => GenericValueRef::Null,
+            GenericValueBoxed::Function(v) => GenericValueRef::Function(*v),
             GenericValueBoxed::Marker(v) => GenericValueRef::Marker(*v),
         }
     }
 }
 
-pub type GenericValueRef<'a> = GenericValue<&'a [u8], &'a str>;
-pub type GenericValueBoxed = GenericValue<Vec<u8>, String>;
+impl GenericValueBoxed {
+    pub fn as_ref(&self) -> GenericValueRef<'_> {
+        GenericValueRef::from(self)
+    }
+
+    /// A copy, standing in for `Clone`, which a value holding a file descriptor can't be:
+    /// descriptors are duplicated, which can fail.
+    pub fn try_clone(&self) -> io::Result<GenericValueBoxed> {
+        self.as_ref().to_boxed()
+    }
+
+    pub fn expect_bytes_into(self) -> Result<Vec<u8>, UnexpectedGenericType> {
+        match self {
+            GenericValue::Vbytes(v) => Ok(v),
+            g => Err(UnexpectedGenericType {
+                expected_type: SerializedType::Sbytes,
+                actual_type: g.type_of(),
+            }),
+        }
+    }
+}
+
+impl<'a> GenericValueRef<'a> {
+    pub fn expect_bytes_into(self) -> Result<&'a [u8], UnexpectedGenericType> {
+        match self {
+            GenericValue::Vbytes(v) => Ok(v),
+            g => Err(UnexpectedGenericType {
+                expected_type: SerializedType::Sbytes,
+                actual_type: g.type_of(),
+            }),
+        }
+    }
+
+    /// An owned copy, to keep past the buffer it was read from. File descriptors are duplicated,
+    /// which can fail.
+    pub fn to_boxed(self) -> io::Result<GenericValueBoxed> {
+        Ok(match self {
+            GenericValueRef::Vu8(v) => GenericValueBoxed::Vu8(v),
+            GenericValueRef::Vi8(v) => GenericValueBoxed::Vi8(v),
+            GenericValueRef::Vu16(v) => GenericValueBoxed::Vu16(v),
+            GenericValueRef::Vi16(v) => GenericValueBoxed::Vi16(v),
+            GenericValueRef::Vu32(v) => GenericValueBoxed::Vu32(v),
+            GenericValueRef::Vi32(v) => GenericValueBoxed::Vi32(v),
+            GenericValueRef::Vu64(v) => GenericValueBoxed::Vu64(v),
+            GenericValueRef::Vi64(v) => GenericValueBoxed::Vi64(v),
+            GenericValueRef::Vf32(v) => GenericValueBoxed::Vf32(v),
+            GenericValueRef::Vf64(v) => GenericValueBoxed::Vf64(v),
+            GenericValueRef::Vusize(v) => GenericValueBoxed::Vusize(v),
+            GenericValueRef::Visize(v) => GenericValueBoxed::Visize(v),
+            GenericValueRef::Vbool(v) => GenericValueBoxed::Vbool(v),
+            GenericValueRef::Vstring(v) => GenericValueBoxed::Vstring(v.to_string()),
+            GenericValueRef::Vbytes(v) => GenericValueBoxed::Vbytes(v.to_vec()),
+            GenericValueRef::Token(v) => GenericValueBoxed::Token(v),
+            GenericValueRef::Fd(v) => GenericValueBoxed::Fd(v.try_clone_to_owned()?),
+            GenericValueRef::List(v) => GenericValueBoxed::List(boxed_list(v)?),
+            GenericValueRef::Map(v) => GenericValueBoxed::Map(boxed_map(v)?),
+            GenericValueRef::Tuple(v) => GenericValueBoxed::Tuple(boxed_list(v)?),
+            GenericValueRef::Struct(v) => GenericValueBoxed::Struct(boxed_map(v)?),
+            GenericValueRef::Vu8s(v) => GenericValueBoxed::Vu8s(v.to_vec()),
+            GenericValueRef::Vi8s(v) => GenericValueBoxed::Vi8s(v.to_vec()),
+            GenericValueRef::Vu16s(v) => GenericValueBoxed::Vu16s(v.to_vec()),
+            GenericValueRef::Vi16s(v) => GenericValueBoxed::Vi16s(v.to_vec()),
+            GenericValueRef::Vu32s(v) => GenericValueBoxed::Vu32s(v.to_vec()),
+            GenericValueRef::Vi32s(v) => GenericValueBoxed::Vi32s(v.to_vec()),
+            GenericValueRef::Vu64s(v) => GenericValueBoxed::Vu64s(v.to_vec()),
+            GenericValueRef::Vi64s(v) => GenericValueBoxed::Vi64s(v.to_vec()),
+            GenericValueRef::Vf32s(v) => GenericValueBoxed::Vf32s(v.to_vec()),
+            GenericValueRef::Vf64s(v) => GenericValueBoxed::Vf64s(v.to_vec()),
+            GenericValueRef::Null => GenericValueBoxed::Null,
+            GenericValueRef::Function(v) => GenericValueBoxed::Function(v),
+            GenericValueRef::Marker(v) => GenericValueBoxed::Marker(v),
+        })
+    }
+}
+
+/// Conversions over a whole run of values, such as the arguments of a call.
+pub trait AsRefGenerics {
+    fn as_refs(&self) -> Vec<GenericValueRef<'_>>;
+}
+
+impl AsRefGenerics for [GenericValueBoxed] {
+    fn as_refs(&self) -> Vec<GenericValueRef<'_>> {
+        self.iter().map(GenericValueRef::from).collect()
+    }
+}
+
+pub trait ToBoxedGenerics {
+    fn to_boxed(&self) -> io::Result<Vec<GenericValueBoxed>>;
+}
+
+impl ToBoxedGenerics for [GenericValueRef<'_>] {
+    fn to_boxed(&self) -> io::Result<Vec<GenericValueBoxed>> {
+        self.iter().map(|v| v.to_boxed()).collect()
+    }
+}
+
+fn boxed_list(list: ListRef) -> io::Result<Vec<GenericValueBoxed>> {
+    list.iter().map(GenericValueRef::to_boxed).collect()
+}
+
+fn boxed_map(map: MapRef) -> io::Result<Vec<(GenericValueBoxed, GenericValueBoxed)>> {
+    map.iter()
+        .map(|(k, v)| Ok((k.to_boxed()?, v.to_boxed()?)))
+        .collect()
+}
+
+pub type GenericValueRef<'a> = GenericValue<RefStorage<'a>>;
+pub type GenericValueBoxed = GenericValue<BoxedStorage>;
 
 macro_rules! from_generic_type {
     ($t:ty, $cons:ident) => {
-        impl<ArrType, StrType> From<$t> for GenericValue<ArrType, StrType> {
+        impl<S: GenericStorage> From<$t> for GenericValue<S> {
             fn from(value: $t) -> Self {
                 GenericValue::$cons(value)
             }
         }
+
+        impl<'a> From<&'a $t> for GenericValueRef<'a> {
+            fn from(value: &'a $t) -> Self {
+                GenericValue::$cons(*value)
+            }
+        }
     }
 }
 
@@ -75,31 +391,158 @@ from_generic_type!(usize, Vusize);
 from_generic_type!(isize, Visize);
 from_generic_type!(bool, Vbool);
 from_generic_type!(DataToken, Token);
+from_generic_type!(FunctionToken, Function);
 
-impl<'a, StrType> From<&'a [u8]> for GenericValue<&'a [u8], StrType> {
+impl<'a> From<&'a [u8]> for GenericValueRef<'a> {
     fn from(value: &'a [u8]) -> Self {
         GenericValue::Vbytes(value)
     }
 }
 
-impl<StrType> From<Vec<u8>> for GenericValue<Vec<u8>, StrType> {
+impl From<Vec<u8>> for GenericValueBoxed {
     fn from(value: Vec<u8>) -> Self {
         GenericValue::Vbytes(value)
     }
 }
 
-impl<'a, ArrType> From<&'a str> for GenericValue<ArrType, &'a str> {
+impl<'a> From<&'a Vec<u8>> for GenericValueRef<'a> {
+    fn from(value: &'a Vec<u8>) -> Self {
+        GenericValue::Vbytes(value.as_slice())
+    }
+}
+
+impl<'a> From<&'a str> for GenericValueRef<'a> {
     fn from(value: &'a str) -> Self {
         GenericValue::Vstring(value)
     }
 }
 
-impl<ArrType> From<String> for GenericValue<ArrType, String> {
+impl From<String> for GenericValueBoxed {
     fn from(value: String) -> Self {
         GenericValue::Vstring(value)
     }
 }
 
+impl<'a> From<&'a String> for GenericValueRef<'a> {
+    fn from(value: &'a String) -> Self {
+        GenericValue::Vstring(value.as_str())
+    }
+}
+
+impl<'a> From<BorrowedFd<'a>> for GenericValueRef<'a> {
+    fn from(value: BorrowedFd<'a>) -> Self {
+        GenericValue::Fd(value)
+    }
+}
+
+impl From<OwnedFd> for GenericValueBoxed {
+    fn from(value: OwnedFd) -> Self {
+        GenericValue::Fd(value)
+    }
+}
+
+impl<'a> From<&'a OwnedFd> for GenericValueRef<'a> {
+    fn from(value: &'a OwnedFd) -> Self {
+        GenericValue::Fd(value.as_fd())
+    }
+}
+
+macro_rules! from_generic_array {
+    ($t:ty, $cons:ident) => {
+        impl<'a> From<&'a [$t]> for GenericValueRef<'a> {
+            fn from(value: &'a [$t]) -> Self {
+                GenericValue::$cons(value.into())
+            }
+        }
+
+        impl From<Vec<$t>> for GenericValueBoxed {
+            fn from(value: Vec<$t>) -> Self {
+                GenericValue::$cons(value)
+            }
+        }
+
+        impl<'a> From<&'a Vec<$t>> for GenericValueRef<'a> {
+            fn from(value: &'a Vec<$t>) -> Self {
+                GenericValue::$cons(value.as_slice().into())
+            }
+        }
+    }
+}
+
+// bytes already turn into Vbytes
+from_generic_array!(i8, Vi8s);
+from_generic_array!(u16, Vu16s);
+from_generic_array!(i16, Vi16s);
+from_generic_array!(u32, Vu32s);
+from_generic_array!(i32, Vi32s);
+from_generic_array!(u64, Vu64s);
+from_generic_array!(i64, Vi64s);
+from_generic_array!(f32, Vf32s);
+from_generic_array!(f64, Vf64s);
+
+impl<'a> From<&'a [GenericValueRef<'a>]> for GenericValueRef<'a> {
+    fn from(value: &'a [GenericValueRef<'a>]) -> Self {
+        GenericValue::List(ListRef::Borrowed(value))
+    }
+}
+
+impl From<Vec<GenericValueBoxed>> for GenericValueBoxed {
+    fn from(value: Vec<GenericValueBoxed>) -> Self {
+        GenericValue::List(value)
+    }
+}
+
+impl<'a> From<&'a Vec<GenericValueBoxed>> for GenericValueRef<'a> {
+    fn from(value: &'a Vec<GenericValueBoxed>) -> Self {
+        GenericValue::List(ListRef::Boxed(value))
+    }
+}
+
+impl<'a> From<&'a [(GenericValueRef<'a>, GenericValueRef<'a>)]> for GenericValueRef<'a> {
+    fn from(value: &'a [(GenericValueRef<'a>, GenericValueRef<'a>)]) -> Self {
+        GenericValue::Map(MapRef::Borrowed(value))
+    }
+}
+
+impl From<Vec<(GenericValueBoxed, GenericValueBoxed)>> for GenericValueBoxed {
+    fn from(value: Vec<(GenericValueBoxed, GenericValueBoxed)>) -> Self {
+        GenericValue::Map(value)
+    }
+}
+
+impl<'a> From<&'a Vec<(GenericValueBoxed, GenericValueBoxed)>> for GenericValueRef<'a> {
+    fn from(value: &'a Vec<(GenericValueBoxed, GenericValueBoxed)>) -> Self {
+        GenericValue::Map(MapRef::Boxed(value))
+    }
+}
+
+// `None` is sent as Null
+impl<S: GenericStorage, T> From<Option<T>> for GenericValue<S>
+where
+    GenericValue<S>: From<T>,
+{
+    fn from(value: Option<T>) -> Self {
+        value.map_or(GenericValue::Null, GenericValue::from)
+    }
+}
+
+// one type at a time, a blanket impl over `GenericValueRef: From<&T>` sends inference in circles
+macro_rules! from_optional_ref {
+    ($($t:ty),*) => {
+        $(impl<'a> From<&'a Option<$t>> for GenericValueRef<'a> {
+            fn from(value: &'a Option<$t>) -> Self {
+                value.as_ref().map_or(GenericValue::Null, GenericValueRef::from)
+            }
+        })*
+    };
+}
+
+from_optional_ref!(
+    u8, i8, u16, i16, u32, i32, u64, i64, f32, f64, usize, isize, bool, String, Vec<u8>,
+    DataToken, FunctionToken, OwnedFd, Vec<i8>, Vec<u16>, Vec<i16>, Vec<u32>, Vec<i32>, Vec<u64>, Vec<i64>,
+    Vec<f32>, Vec<f64>, Vec<GenericValueBoxed>, GenericValueBoxed
+);
+
 macro_rules! expect_generic_type {
     ($name: ident, $cons: ident, $ex: ident, $t:ty) => {
         paste::paste! {
@@ -120,7 +563,7 @@ macro_rules! expect_generic_type {
     }
 }
 
-impl<ArrType, StrType> GenericValue<ArrType, StrType> {
+impl<S: GenericStorage> GenericValue<S> {
     fn type_of(&self) -> SerializedType {
         match self {
             GenericValue::Vu8(_) => SerializedType::Su8,
@@ -139,6 +582,23 @@ impl<ArrType, StrType> GenericValue<ArrType, StrType> {
             GenericValue::Vstring(_) => SerializedType::Sstring,
             GenericValue::Vbytes(_) => SerializedType::Sbytes,
             GenericValue::Token(_) => SerializedType::Token,
+            GenericValue::Fd(_) => SerializedType::Fd,
+            GenericValue::List(_) => SerializedType::List,
+            GenericValue::Map(_) => SerializedType::Map,
+            GenericValue::Tuple(_) => SerializedType::Tuple,
+            GenericValue::Struct(_) => SerializedType::Struct,
+            GenericValue::Vu8s(_) => SerializedType::Su8s,
+            GenericValue::Vi8s(_) => SerializedType::Si8s,
+            GenericValue::Vu16s(_) => SerializedType::Su16s,
+            GenericValue::Vi16s(_) => SerializedType::Si16s,
+            GenericValue::Vu32s(_) => SerializedType::Su32s,
+            GenericValue::Vi32s(_) => SerializedType::Si32s,
+            GenericValue::Vu64s(_) => SerializedType::Su64s,
+            GenericValue::Vi64s(_) => SerializedType::Si64s,
+            GenericValue::Vf32s(_) => SerializedType::Sf32s,
+            GenericValue::Vf64s(_) => SerializedType::Sf64s,
+            GenericValue::Null => SerializedType::Null,
+            GenericValue::Function(_) => SerializedType::Function,
             GenericValue::Marker(_) => SerializedType::Marker,
         }
     }
@@ -156,10 +616,37 @@ impl<ArrType, StrType> GenericValue<ArrType, StrType> {
     expect_generic_type!(usize, Vusize, Susize, usize);
     expect_generic_type!(isize, Visize, Sisize, isize);
     expect_generic_type!(bool, Vbool, Sbool, bool);
-    expect_generic_type!(string, Vstring, Sstring, StrType);
-    expect_generic_type!(bytes, Vbytes, Sbytes, ArrType);
+    expect_generic_type!(string, Vstring, Sstring, S::Str);
+
+    pub fn expect_bytes(&self) -> std::result::Result<&S::Bytes, UnexpectedGenericType> {
+        match self {
+            GenericValue::Vbytes(v) => Ok(v),
+            g => Err(UnexpectedGenericType{ expected_type: SerializedType::Sbytes,  actual_type: g.type_of()})
+        }
+    }
+
     expect_generic_type!(token, Token, Token, DataToken);
+    expect_generic_type!(function, Function, Function, FunctionToken);
+    expect_generic_type!(fd, Fd, Fd, S::Fd);
+    expect_generic_type!(list, List, List, S::List);
+    expect_generic_type!(map, Map, Map, S::Map);
+    expect_generic_type!(tuple, Tuple, Tuple, S::List);
+    expect_generic_type!(struct, Struct, Struct, S::Map);
+    expect_generic_type!(u8s, Vu8s, Su8s, S::Array<u8>);
+    expect_generic_type!(i8s, Vi8s, Si8s, S::Array<i8>);
+    expect_generic_type!(u16s, Vu16s, Su16s, S::Array<u16>);
+    expect_generic_type!(i16s, Vi16s, Si16s, S::Array<i16>);
+    expect_generic_type!(u32s, Vu32s, Su32s, S::Array<u32>);
+    expect_generic_type!(i32s, Vi32s, Si32s, S::Array<i32>);
+    expect_generic_type!(u64s, Vu64s, Su64s, S::Array<u64>);
+    expect_generic_type!(i64s, Vi64s, Si64s, S::Array<i64>);
+    expect_generic_type!(f32s, Vf32s, Sf32s, S::Array<f32>);
+    expect_generic_type!(f64s, Vf64s, Sf64s, S::Array<f64>);
     expect_generic_type!(marker, Marker, Marker, u8);
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, GenericValue::Null)
+    }
 }
 
 #[repr(u8)]
@@ -183,17 +670,101 @@ pub enum SerializedType {
     Token,
 
     Marker,
+
+    // added after Marker to keep the existing codes stable
+    Fd,
+    List,
+    Map,
+    Tuple,
+    Struct,
+    Su8s,
+    Si8s,
+    Su16s,
+    Si16s,
+    Su32s,
+    Si32s,
+    Su64s,
+    Si64s,
+    Sf32s,
+    Sf64s,
+    Null,
+    Function,
 }
 
+/// How deep `List`, `Map`, `Tuple` and `Struct` values may nest inside one another before a
+/// reader gives up on them.
+pub const MAX_GENERIC_DEPTH: usize = 64;
+
 pub(crate) mod sealed {
-    use std::{
-        convert::TryInto,
-        io,
-        io::{ErrorKind, Read, Write},
+    use std::{convert::TryInto, io, io::ErrorKind};
+
+    use std::os::unix::io::{BorrowedFd, OwnedFd};
+
+    use bytemuck::Pod;
+
+    use super::{
+        GenericStorage, GenericValue, GenericValueBoxed, GenericValueRef, SerializedType,
+        MAX_GENERIC_DEPTH,
     };
+    use crate::{
+        arena::ARENA_MIN_PAYLOAD,
+        endpoint::sealed::*,
+        err::*,
+        frame::{Frame, Run, Slot, Span},
+        protocol::*,
+        ListRef, MapRef,
+    };
+
+    fn whole_elements<T>(bytes: usize) -> io::Result<usize> {
+        let element = std::mem::size_of::<T>();
+        match bytes % element {
+            0 => Ok(bytes / element),
+            _ => Err(ProtocolError::ArrayLength { bytes, element }.into()),
+        }
+    }
 
-    use super::{GenericValue, SerializedType};
-    use crate::{endpoint::sealed::*, err::*, protocol::*};
+    fn check_depth(depth: usize) -> io::Result<()> {
+        match depth > MAX_GENERIC_DEPTH {
+            true => Err(ProtocolError::TooDeep(MAX_GENERIC_DEPTH).into()),
+            false => Ok(()),
+        }
+    }
+
+    // whatever would stop a value part way through being written is checked before any of it is:
+    // nesting too deep to be read back, struct keys that aren't strings, and file descriptors the
+    // transport can't pass
+    fn check_writable(value: GenericValueRef, depth: usize, passes_fds: bool) -> io::Result<()> {
+        match value {
+            GenericValue::Fd(_) if !passes_fds => Err(ProtocolError::FdPassingUnsupported.into()),
+            GenericValue::List(list) | GenericValue::Tuple(list) => {
+                check_depth(depth + 1)?;
+                list.iter().try_for_each(|v| check_writable(v, depth + 1, passes_fds))
+            }
+            GenericValue::Map(map) => {
+                check_depth(depth + 1)?;
+                map.iter().try_for_each(|(k, v)| {
+                    check_writable(k, depth + 1, passes_fds)?;
+                    check_writable(v, depth + 1, passes_fds)
+                })
+            }
+            GenericValue::Struct(fields) => {
+                check_depth(depth + 1)?;
+                fields.iter().try_for_each(|(k, v)| {
+                    k.expect_string()?;
+                    check_writable(v, depth + 1, passes_fds)
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // sent in place of a length for bytes that were put in the shared arena
+    const SHARED_BYTES: usize = usize::MAX;
+
+    // Lengths come off the wire, so nothing is allocated for more than has actually arrived: a
+    // bogus length runs into the end of the stream rather than exhausting memory.
+    pub(crate) const PREALLOCATE: usize = 1024;
+    const READ_CHUNK: usize = 64 * 1024;
 
     macro_rules! prim_rw {
         ($name: ident, $t:ty) => {
@@ -224,6 +795,7 @@ pub(crate) mod sealed {
                     })
                 }
 
+                #[allow(dead_code)]
                 fn [<write_ $name>](&mut self, v: $t) -> io::Result<&mut Self> {
                     self.write_u8(v as u8)
                 }
@@ -239,15 +811,48 @@ pub(crate) mod sealed {
 
         fn read_exact(&mut self, data: &mut [u8]) -> io::Result<&mut Self>;
 
-        //
+        fn write_fd(&mut self, fd: BorrowedFd) -> io::Result<&mut Self>;
+
+        fn read_fd(&mut self) -> io::Result<OwnedFd>;
+
+        fn passes_fds(&mut self) -> bool;
+
+        // copy into our shared arena, if there is one and the bytes fit
+        fn place_shared(&mut self, data: &[u8]) -> Option<(u64, u64)>;
+
+        // copy out of the other side's arena onto the end of `into`
+        fn take_shared<T: Pod>(&mut self, offset: u64, len: u64, into: &mut Vec<T>)
+            -> io::Result<()>;
+
+        fn read_pod_vec<T: Pod>(&mut self, count: usize) -> io::Result<Vec<T>> {
+            let chunk = (READ_CHUNK / std::mem::size_of::<T>()).max(1);
+            let mut vec = Vec::new();
+            while vec.len() < count {
+                let start = vec.len();
+                vec.resize(start + chunk.min(count - start), T::zeroed());
+                self.read_exact(bytemuck::cast_slice_mut(&mut vec[start..]))?;
+            }
+            Ok(vec)
+        }
+
         fn read_bytes(&mut self) -> io::Result<Vec<u8>> {
             let size = self.read_usize()?;
-            let mut vec = vec![0; size];
-            self.read_exact(vec.as_mut_slice())?;
-            Ok(vec)
+            if size == SHARED_BYTES {
+                let offset = self.read_u64()?;
+                let len = self.read_u64()?;
+                let mut vec = Vec::new();
+                self.take_shared(offset, len, &mut vec)?;
+                return Ok(vec);
+            }
+            self.read_pod_vec(size)
         }
 
         fn write_bytes(&mut self, value: &[u8]) -> io::Result<&mut Self> {
+            if value.len() >= ARENA_MIN_PAYLOAD {
+                if let Some((offset, len)) = self.place_shared(value) {
+                    return self.write_usize(SHARED_BYTES)?.write_u64(offset)?.write_u64(len);
+                }
+            }
             self.write_usize(value.len())?;
             self.write_all(value)?;
             Ok(self)
@@ -286,11 +891,12 @@ pub(crate) mod sealed {
             self.write_u8(p as u8)
         }
 
-        fn read_generic(&mut self) -> io::Result<GenericValue<Vec<u8>, String>> {
-            let g_type: SerializedType = SerializedType::try_from(self.read_u8()?)
-                .map_err(ProtocolError::UnknownGenericType)?;
-
-            Ok(match g_type {
+        // everything but the values that carry storage, which come back as None
+        fn read_scalar<S: GenericStorage>(
+            &mut self,
+            g_type: SerializedType,
+        ) -> io::Result<Option<GenericValue<S>>> {
+            Ok(Some(match g_type {
                 SerializedType::Su8 => GenericValue::Vu8(self.read_u8()?),
                 SerializedType::Si8 => GenericValue::Vi8(self.read_i8()?),
                 SerializedType::Su16 => GenericValue::Vu16(self.read_u16()?),
@@ -304,24 +910,202 @@ pub(crate) mod sealed {
                 SerializedType::Susize => GenericValue::Vusize(self.read_usize()?),
                 SerializedType::Sisize => GenericValue::Visize(self.read_isize()?),
                 SerializedType::Sbool => GenericValue::Vbool(self.read_bool()?),
-                SerializedType::Sstring => GenericValue::Vstring(self.read_string()?),
                 SerializedType::Marker => GenericValue::Marker(self.read_u8()?),
-                SerializedType::Sbytes => GenericValue::Vbytes(self.read_bytes()?),
+                SerializedType::Null => GenericValue::Null,
                 SerializedType::Token => GenericValue::Token(DataToken(self.read_u64()?)),
+                SerializedType::Function => {
+                    GenericValue::Function(FunctionToken(self.read_u64()?))
+                }
+                _ => return Ok(None),
+            }))
+        }
+
+        fn read_gtype(&mut self) -> io::Result<SerializedType> {
+            Ok(SerializedType::try_from(self.read_u8()?)
+                .map_err(ProtocolError::UnknownGenericType)?)
+        }
+
+        fn read_generic(&mut self, depth: usize) -> io::Result<GenericValueBoxed> {
+            let g_type = self.read_gtype()?;
+            if let Some(scalar) = self.read_scalar(g_type)? {
+                return Ok(scalar);
+            }
+
+            Ok(match g_type {
+                SerializedType::Sstring => GenericValue::Vstring(self.read_string()?),
+                SerializedType::Sbytes => GenericValue::Vbytes(self.read_bytes()?),
+                SerializedType::Fd => GenericValue::Fd(self.read_fd()?),
+                SerializedType::List => GenericValue::List(self.read_list(depth + 1)?),
+                SerializedType::Map => GenericValue::Map(self.read_map(depth + 1, false)?),
+                SerializedType::Tuple => GenericValue::Tuple(self.read_list(depth + 1)?),
+                SerializedType::Struct => GenericValue::Struct(self.read_map(depth + 1, true)?),
+                SerializedType::Su8s => GenericValue::Vu8s(self.read_array()?),
+                SerializedType::Si8s => GenericValue::Vi8s(self.read_array()?),
+                SerializedType::Su16s => GenericValue::Vu16s(self.read_array()?),
+                SerializedType::Si16s => GenericValue::Vi16s(self.read_array()?),
+                SerializedType::Su32s => GenericValue::Vu32s(self.read_array()?),
+                SerializedType::Si32s => GenericValue::Vi32s(self.read_array()?),
+                SerializedType::Su64s => GenericValue::Vu64s(self.read_array()?),
+                SerializedType::Si64s => GenericValue::Vi64s(self.read_array()?),
+                SerializedType::Sf32s => GenericValue::Vf32s(self.read_array()?),
+                SerializedType::Sf64s => GenericValue::Vf64s(self.read_array()?),
+                _ => unreachable!("read_scalar handles {:?}", g_type),
             })
         }
 
-        fn read_generic_vec(&mut self) -> io::Result<Vec<GenericValue<Vec<u8>, String>>> {
+        fn read_list(&mut self, depth: usize) -> io::Result<Vec<GenericValueBoxed>> {
+            check_depth(depth)?;
             let length = self.read_usize()?;
-            let mut vec = Vec::with_capacity(length);
+            let mut vec = Vec::with_capacity(length.min(PREALLOCATE));
             for _ in 0..length {
-                vec.push(self.read_generic()?);
+                vec.push(self.read_generic(depth)?);
             }
+            Ok(vec)
+        }
 
+        // struct fields are named, their keys are written as bare strings
+        fn read_map(
+            &mut self,
+            depth: usize,
+            named: bool,
+        ) -> io::Result<Vec<(GenericValueBoxed, GenericValueBoxed)>> {
+            check_depth(depth)?;
+            let length = self.read_usize()?;
+            let mut vec = Vec::with_capacity(length.min(PREALLOCATE));
+            for _ in 0..length {
+                let key = match named {
+                    true => GenericValue::Vstring(self.read_string()?),
+                    false => self.read_generic(depth)?,
+                };
+                vec.push((key, self.read_generic(depth)?));
+            }
             Ok(vec)
         }
 
-        fn write_generic(&mut self, value: GenericValue<&[u8], &str>) -> io::Result<&mut Self> {
+        // arrays go on the wire like bytes, so large ones can go through the arena
+        fn read_array<T: Pod>(&mut self) -> io::Result<Vec<T>> {
+            let size = self.read_usize()?;
+            if size == SHARED_BYTES {
+                let offset = self.read_u64()?;
+                let len = self.read_u64()?;
+                let mut vec = Vec::with_capacity(whole_elements::<T>(len as usize)?);
+                self.take_shared(offset, len, &mut vec)?;
+                return Ok(vec);
+            }
+            self.read_pod_vec(whole_elements::<T>(size)?)
+        }
+
+        fn read_generic_vec(&mut self) -> io::Result<Vec<GenericValueBoxed>> {
+            self.read_list(0)
+        }
+
+        // the borrowed read path, contents go into the frame instead of their own allocations
+        fn read_bytes_in(&mut self, frame: &mut Frame) -> io::Result<Span> {
+            self.read_aligned_in(frame, 1)
+        }
+
+        // the arena places everything aligned, inline bytes are padded to line up in the frame
+        fn read_aligned_in(&mut self, frame: &mut Frame, align: usize) -> io::Result<Span> {
+            let size = self.read_usize()?;
+            frame.buf.resize(frame.buf.len().next_multiple_of(align), 0);
+            let start = frame.buf.len();
+            if size == SHARED_BYTES {
+                let offset = self.read_u64()?;
+                let len = self.read_u64()?;
+                self.take_shared(offset, len, &mut frame.buf)?;
+                return Ok(Span { start, end: frame.buf.len() });
+            }
+            while frame.buf.len() - start < size {
+                let from = frame.buf.len();
+                frame.buf.resize(from + READ_CHUNK.min(size - (from - start)), 0);
+                self.read_exact(&mut frame.buf[from..])?;
+            }
+            Ok(Span { start, end: frame.buf.len() })
+        }
+
+        fn read_string_in(&mut self, frame: &mut Frame) -> io::Result<Span> {
+            let span = self.read_bytes_in(frame)?;
+            std::str::from_utf8(frame.bytes(span))
+                .map_err(|str_err| io::Error::new(ErrorKind::Other, str_err))?;
+            Ok(span)
+        }
+
+        fn read_array_in<T: Pod>(&mut self, frame: &mut Frame) -> io::Result<Span> {
+            let span = self.read_aligned_in(frame, std::mem::align_of::<T>())?;
+            whole_elements::<T>(frame.bytes(span).len())?;
+            Ok(span)
+        }
+
+        fn read_generic_in(&mut self, frame: &mut Frame, depth: usize) -> io::Result<Slot> {
+            let g_type = self.read_gtype()?;
+            if let Some(scalar) = self.read_scalar(g_type)? {
+                return Ok(scalar);
+            }
+
+            Ok(match g_type {
+                SerializedType::Sstring => GenericValue::Vstring(self.read_string_in(frame)?),
+                SerializedType::Sbytes => GenericValue::Vbytes(self.read_bytes_in(frame)?),
+                SerializedType::Fd => {
+                    let fd = self.read_fd()?;
+                    GenericValue::Fd(frame.push_fd(fd))
+                }
+                SerializedType::List => GenericValue::List(self.read_list_in(frame, depth + 1)?),
+                SerializedType::Map => {
+                    GenericValue::Map(self.read_map_in(frame, depth + 1, false)?)
+                }
+                SerializedType::Tuple => GenericValue::Tuple(self.read_list_in(frame, depth + 1)?),
+                SerializedType::Struct => {
+                    GenericValue::Struct(self.read_map_in(frame, depth + 1, true)?)
+                }
+                SerializedType::Su8s => GenericValue::Vu8s(self.read_array_in::<u8>(frame)?),
+                SerializedType::Si8s => GenericValue::Vi8s(self.read_array_in::<i8>(frame)?),
+                SerializedType::Su16s => GenericValue::Vu16s(self.read_array_in::<u16>(frame)?),
+                SerializedType::Si16s => GenericValue::Vi16s(self.read_array_in::<i16>(frame)?),
+                SerializedType::Su32s => GenericValue::Vu32s(self.read_array_in::<u32>(frame)?),
+                SerializedType::Si32s => GenericValue::Vi32s(self.read_array_in::<i32>(frame)?),
+                SerializedType::Su64s => GenericValue::Vu64s(self.read_array_in::<u64>(frame)?),
+                SerializedType::Si64s => GenericValue::Vi64s(self.read_array_in::<i64>(frame)?),
+                SerializedType::Sf32s => GenericValue::Vf32s(self.read_array_in::<f32>(frame)?),
+                SerializedType::Sf64s => GenericValue::Vf64s(self.read_array_in::<f64>(frame)?),
+                _ => unreachable!("read_scalar handles {:?}", g_type),
+            })
+        }
+
+        // the values of a list are held back until it has been read, so anything nested in them
+        // lands first and the list stays in one run
+        fn read_list_in(&mut self, frame: &mut Frame, depth: usize) -> io::Result<Run> {
+            check_depth(depth)?;
+            let length = self.read_usize()?;
+            let mark = frame.pending.len();
+            for _ in 0..length {
+                let slot = self.read_generic_in(frame, depth)?;
+                frame.pending.push(slot);
+            }
+            Ok(frame.finish_run(mark))
+        }
+
+        fn read_map_in(&mut self, frame: &mut Frame, depth: usize, named: bool) -> io::Result<Run> {
+            check_depth(depth)?;
+            let length = self.read_usize()?;
+            let mark = frame.pending.len();
+            for _ in 0..length {
+                let key = match named {
+                    true => GenericValue::Vstring(self.read_string_in(frame)?),
+                    false => self.read_generic_in(frame, depth)?,
+                };
+                frame.pending.push(key);
+                let value = self.read_generic_in(frame, depth)?;
+                frame.pending.push(value);
+            }
+            Ok(frame.finish_run(mark))
+        }
+
+        fn read_generic_vec_in(&mut self, frame: &mut Frame) -> io::Result<Run> {
+            self.read_list_in(frame, 0)
+        }
+
+        // a value that has been through `check_writable`
+        fn write_generic(&mut self, value: GenericValueRef) -> io::Result<&mut Self> {
             match value {
                 GenericValue::Vu8(v) => self.write_gtype(SerializedType::Su8)?.write_u8(v)?,
                 GenericValue::Vi8(v) => self.write_gtype(SerializedType::Si8)?.write_i8(v)?,
@@ -350,15 +1134,84 @@ pub(crate) mod sealed {
                 GenericValue::Token(DataToken(v)) => {
                     self.write_gtype(SerializedType::Token)?.write_u64(v)?
                 }
+                GenericValue::Fd(v) => self.write_gtype(SerializedType::Fd)?.write_fd(v)?,
+                GenericValue::List(v) => self.write_gtype(SerializedType::List)?.write_list(v)?,
+                GenericValue::Map(v) => self.write_gtype(SerializedType::Map)?.write_map(v)?,
+                GenericValue::Tuple(v) => self.write_gtype(SerializedType::Tuple)?.write_list(v)?,
+                GenericValue::Struct(v) => {
+                    self.write_gtype(SerializedType::Struct)?.write_fields(v)?
+                }
+                GenericValue::Vu8s(v) => {
+                    self.write_gtype(SerializedType::Su8s)?.write_bytes(v.as_bytes())?
+                }
+                GenericValue::Vi8s(v) => {
+                    self.write_gtype(SerializedType::Si8s)?.write_bytes(v.as_bytes())?
+                }
+                GenericValue::Vu16s(v) => {
+                    self.write_gtype(SerializedType::Su16s)?.write_bytes(v.as_bytes())?
+                }
+                GenericValue::Vi16s(v) => {
+                    self.write_gtype(SerializedType::Si16s)?.write_bytes(v.as_bytes())?
+                }
+                GenericValue::Vu32s(v) => {
+                    self.write_gtype(SerializedType::Su32s)?.write_bytes(v.as_bytes())?
+                }
+                GenericValue::Vi32s(v) => {
+                    self.write_gtype(SerializedType::Si32s)?.write_bytes(v.as_bytes())?
+                }
+                GenericValue::Vu64s(v) => {
+                    self.write_gtype(SerializedType::Su64s)?.write_bytes(v.as_bytes())?
+                }
+                GenericValue::Vi64s(v) => {
+                    self.write_gtype(SerializedType::Si64s)?.write_bytes(v.as_bytes())?
+                }
+                GenericValue::Vf32s(v) => {
+                    self.write_gtype(SerializedType::Sf32s)?.write_bytes(v.as_bytes())?
+                }
+                GenericValue::Vf64s(v) => {
+                    self.write_gtype(SerializedType::Sf64s)?.write_bytes(v.as_bytes())?
+                }
+                GenericValue::Null => self.write_gtype(SerializedType::Null)?,
+                GenericValue::Function(FunctionToken(v)) => {
+                    self.write_gtype(SerializedType::Function)?.write_u64(v)?
+                }
                 GenericValue::Marker(v) => self.write_gtype(SerializedType::Marker)?.write_u8(v)?,
             };
             Ok(self)
         }
 
+        fn write_list(&mut self, list: ListRef) -> io::Result<&mut Self> {
+            self.write_usize(list.len())?;
+            for v in list.iter() {
+                self.write_generic(v)?;
+            }
+            Ok(self)
+        }
+
+        fn write_map(&mut self, map: MapRef) -> io::Result<&mut Self> {
+            self.write_usize(map.len())?;
+            for (k, v) in map.iter() {
+                self.write_generic(k)?.write_generic(v)?;
+            }
+            Ok(self)
+        }
+
+        fn write_fields(&mut self, fields: MapRef) -> io::Result<&mut Self> {
+            self.write_usize(fields.len())?;
+            for (k, v) in fields.iter() {
+                self.write_string(k.expect_string_into()?)?.write_generic(v)?;
+            }
+            Ok(self)
+        }
+
         fn write_generic_vec(
             &mut self,
-            values: &[GenericValue<&[u8], &str>],
+            values: &[GenericValueRef],
         ) -> io::Result<&mut Self> {
+            let passes_fds = self.passes_fds();
+            for v in values {
+                check_writable(*v, 0, passes_fds)?;
+            }
             self.write_usize(values.len())?;
             for v in values {
                 self.write_generic(*v)?;
@@ -389,14 +1242,128 @@ pub(crate) mod sealed {
         X: Endpoint + Sized,
     {
         fn write_all(&mut self, data: &[u8]) -> io::Result<&mut Self> {
-            self.pipes().writer.write_all(data)?;
+            self.transport().write_all(data)?;
+            self.count_traffic(data.len(), 0);
             Ok(self)
         }
 
         fn read_exact(&mut self, data: &mut [u8]) -> io::Result<&mut Self> {
-            self.pipes().reader.read_exact(data)?;
+            self.transport().read_exact(data)?;
+            self.count_traffic(0, data.len());
             Ok(self)
         }
+
+        fn write_fd(&mut self, fd: BorrowedFd) -> io::Result<&mut Self> {
+            self.transport().send_fd(fd)?;
+            Ok(self)
+        }
+
+        fn read_fd(&mut self) -> io::Result<OwnedFd> {
+            self.transport().recv_fd()
+        }
+
+        fn passes_fds(&mut self) -> bool {
+            self.transport().passes_fds()
+        }
+
+        fn place_shared(&mut self, data: &[u8]) -> Option<(u64, u64)> {
+            self.arenas()?.place(data)
+        }
+
+        fn take_shared<T: Pod>(&mut self, offset: u64, len: u64, into: &mut Vec<T>)
+            -> io::Result<()> {
+            match self.arenas() {
+                Some(arenas) => arenas.take(offset, len, into),
+                None => Err(ProtocolError::NoArena.into()),
+            }
+        }
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use proptest::{collection::vec, prelude::*};
+
+    use super::sealed::SerializationEndpoint;
+    use crate::{transport::MemoryTransport, *};
+
+    // every value but file descriptors, which don't survive the trip as the same descriptor
+    fn arb_value() -> impl Strategy<Value = GenericValueBoxed> {
+        let leaf = prop_oneof![
+            any::<u8>().prop_map(GenericValue::Vu8),
+            any::<i8>().prop_map(GenericValue::Vi8),
+            any::<u16>().prop_map(GenericValue::Vu16),
+            any::<i16>().prop_map(GenericValue::Vi16),
+            any::<u32>().prop_map(GenericValue::Vu32),
+            any::<i32>().prop_map(GenericValue::Vi32),
+            any::<u64>().prop_map(GenericValue::Vu64),
+            any::<i64>().prop_map(GenericValue::Vi64),
+            any::<f32>().prop_map(GenericValue::Vf32),
+            any::<f64>().prop_map(GenericValue::Vf64),
+            any::<usize>().prop_map(GenericValue::Vusize),
+            any::<isize>().prop_map(GenericValue::Visize),
+            any::<bool>().prop_map(GenericValue::Vbool),
+            any::<String>().prop_map(GenericValue::Vstring),
+            vec(any::<u8>(), 0..64).prop_map(GenericValue::Vbytes),
+            any::<u64>().prop_map(|v| GenericValue::Token(DataToken(v))),
+            vec(any::<u8>(), 0..8).prop_map(GenericValue::Vu8s),
+            vec(any::<i8>(), 0..8).prop_map(GenericValue::Vi8s),
+            vec(any::<u16>(), 0..8).prop_map(GenericValue::Vu16s),
+            vec(any::<i16>(), 0..8).prop_map(GenericValue::Vi16s),
+            vec(any::<u32>(), 0..8).prop_map(GenericValue::Vu32s),
+            vec(any::<i32>(), 0..8).prop_map(GenericValue::Vi32s),
+            vec(any::<u64>(), 0..8).prop_map(GenericValue::Vu64s),
+            vec(any::<i64>(), 0..8).prop_map(GenericValue::Vi64s),
+            vec(any::<f32>(), 0..8).prop_map(GenericValue::Vf32s),
+            vec(any::<f64>(), 0..8).prop_map(GenericValue::Vf64s),
+            Just(()).prop_map(|_| GenericValue::Null),
+            any::<u64>().prop_map(|v| GenericValue::Function(FunctionToken(v))),
+            any::<u8>().prop_map(GenericValue::Marker),
+        ];
+        leaf.prop_recursive(4, 64, 8, |inner| {
+            prop_oneof![
+                vec(inner.clone(), 0..8).prop_map(GenericValue::List),
+                vec(inner.clone(), 0..8).prop_map(GenericValue::Tuple),
+                vec((inner.clone(), inner.clone()), 0..8).prop_map(GenericValue::Map),
+                vec((any::<String>().prop_map(GenericValue::Vstring), inner), 0..8)
+                    .prop_map(GenericValue::Struct),
+            ]
+        })
+    }
+
+    #[test]
+    fn values_that_wouldnt_read_back_are_refused_before_anything_is_written() {
+        let mut endpoint = SubordinateProcess::new(Box::new(MemoryTransport::new(&[], true)));
+        let nested = |depth| {
+            (0..depth).fold(GenericValueBoxed::Null, |inner, _| GenericValue::List(vec![inner]))
+        };
+        let numbered = GenericValueBoxed::Struct(vec![(GenericValue::Vu8(1), GenericValue::Null)]);
+        for refused in [numbered, nested(MAX_GENERIC_DEPTH + 1)] {
+            let values = [GenericValue::Vu8(1), refused.as_ref()];
+            assert!(endpoint.write_generic_vec(&values).is_err());
+        }
+
+        let deepest = nested(MAX_GENERIC_DEPTH);
+        endpoint.write_generic_vec(&[deepest.as_ref()]).unwrap();
+        assert_eq!(endpoint.read_generic_vec().unwrap(), [deepest]);
+    }
+
+    proptest! {
+        #[test]
+        fn values_read_back_as_written(values in vec(arb_value(), 0..8)) {
+            let mut endpoint = SubordinateProcess::new(Box::new(MemoryTransport::new(&[], true)));
+            for value in &values {
+                endpoint.write_generic(value.as_ref()).unwrap();
+                prop_assert_eq!(&endpoint.read_generic(0).unwrap(), value);
+            }
+
+            let mut frame = Frame::new();
+            endpoint.write_generic_vec(&values.as_refs()).unwrap();
+            let run = endpoint.read_generic_vec_in(&mut frame).unwrap();
+            let read = frame.values(run);
+            prop_assert_eq!(read.len(), values.len());
+            prop_assert!(read.iter().zip(&values).all(|(read, value)| read == *value));
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    io,
+    result::Result,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use tracing::{field::Empty, info_span, span::EnteredSpan};
+
+use crate::{serialization::sealed::SerializationEndpoint, *};
+
+// Every exchange gets a span on each side: one for each request on the controller, and one for
+// receiving each command and one for each response on the subordinate. Once the controller is
+// given a trace context, each request tells the subordinate about it with a Context message ahead
+// of the command, so the spans on both sides share a `trace_id` and can be joined. Until then
+// nothing extra goes over the wire, which a subordinate without tracing may not understand.
+
+fn new_trace_id() -> u64 {
+    static REQUESTS: AtomicU64 = AtomicU64::new(0);
+    RandomState::new().hash_one(REQUESTS.fetch_add(1, Ordering::Relaxed))
+}
+
+fn outcome(result: Result<(), &io::Error>) -> String {
+    match result {
+        Ok(_) => "ok".to_string(),
+        Err(e) => match e.get_ref().and_then(|e| e.downcast_ref::<RemoteError>()) {
+            Some(remote) => format!("{:?}", remote.err_type),
+            None => format!("failed: {}", e),
+        },
+    }
+}
+
+// bytes through an endpoint's transport, so a span can say how much its exchange took
+#[derive(Default, Clone, Copy)]
+pub(crate) struct Traffic {
+    pub(crate) sent: u64,
+    pub(crate) received: u64,
+}
+
+/// The span of one request, entered until its answer has been read.
+pub(crate) struct RequestSpan {
+    span: EnteredSpan,
+    before: Traffic,
+}
+
+impl RequestSpan {
+    pub(crate) fn enter(
+        controller: &ControllerProcess,
+        command: ProtocolConstant,
+        token: Option<u64>,
+    ) -> Self {
+        let trace_id = controller.trace_context.unwrap_or_else(new_trace_id);
+        let span = info_span!(
+            "ufo_ipc.request",
+            ?command,
+            token,
+            trace_id,
+            bytes_sent = Empty,
+            bytes_received = Empty,
+            outcome = Empty,
+        );
+        RequestSpan {
+            span: span.entered(),
+            before: controller.recorder.traffic(),
+        }
+    }
+
+    pub(crate) fn exit<V>(self, controller: &ControllerProcess, result: &io::Result<V>) {
+        let after = controller.recorder.traffic();
+        self.span.record("bytes_sent", after.sent - self.before.sent);
+        self.span.record("bytes_received", after.received - self.before.received);
+        self.span.record("outcome", outcome(result.as_ref().map(drop)).as_str());
+    }
+}
+
+impl ControllerProcess {
+    /// Send every request from now on as part of the trace `id`, or with `None`, stop sending
+    /// it. The subordinate sees the id as `SubordinateProcess::trace_context`, and both sides'
+    /// spans record it as `trace_id`. Without one, which is the default, nothing is sent and each
+    /// request's span gets a trace of its own that only the controller knows.
+    pub fn set_trace_context(&mut self, id: Option<u64>) {
+        self.trace_context = id;
+    }
+
+    pub(crate) fn send_trace_context(&mut self) -> io::Result<()> {
+        if let Some(id) = self.trace_context {
+            self.write_protocol(ProtocolConstant::Context)?.write_u64(id)?;
+        }
+        Ok(())
+    }
+}
+
+// the command a request holds, as the controller named it, and the token it is about
+pub(crate) fn describe(command: &ProtocolCommand) -> (ProtocolConstant, Option<u64>) {
+    match command {
+        ProtocolCommand::Shutdown => (ProtocolConstant::Goodbye, None),
+        ProtocolCommand::DefineFunction { token, .. } => {
+            (ProtocolConstant::DefineFunction, Some(token.0))
+        }
+        ProtocolCommand::DefineData { token, .. } => (ProtocolConstant::DefineData, Some(token.0)),
+        ProtocolCommand::Call { token, .. } => (ProtocolConstant::Call, Some(token.0)),
+        ProtocolCommand::FreeFunction(token) => (ProtocolConstant::FreeFunction, Some(token.0)),
+        ProtocolCommand::FreeData(token) => (ProtocolConstant::FreeData, Some(token.0)),
+        ProtocolCommand::Peek(_) => (ProtocolConstant::Peek, None),
+        ProtocolCommand::Poke { .. } => (ProtocolConstant::Poke, None),
+    }
+}
+
+pub(crate) fn describe_ref(command: &ProtocolCommandRef) -> (ProtocolConstant, Option<u64>) {
+    match command {
+        ProtocolCommandRef::Shutdown => (ProtocolConstant::Goodbye, None),
+        ProtocolCommandRef::DefineFunction { token, .. } => {
+            (ProtocolConstant::DefineFunction, Some(token.0))
+        }
+        ProtocolCommandRef::DefineData { token, .. } => {
+            (ProtocolConstant::DefineData, Some(token.0))
+        }
+        ProtocolCommandRef::Call { token, .. } => (ProtocolConstant::Call, Some(token.0)),
+        ProtocolCommandRef::FreeFunction(token) => (ProtocolConstant::FreeFunction, Some(token.0)),
+        ProtocolCommandRef::FreeData(token) => (ProtocolConstant::FreeData, Some(token.0)),
+        ProtocolCommandRef::Peek(_) => (ProtocolConstant::Peek, None),
+        ProtocolCommandRef::Poke { .. } => (ProtocolConstant::Poke, None),
+    }
+}
+
+/// The span of receiving one command, entered until it has been read.
+pub(crate) struct CommandSpan {
+    span: EnteredSpan,
+    before: Traffic,
+}
+
+impl CommandSpan {
+    pub(crate) fn enter(subordinate: &SubordinateProcess) -> Self {
+        let span = info_span!(
+            "ufo_ipc.recv_command",
+            command = Empty,
+            token = Empty,
+            trace_id = Empty,
+            bytes_received = Empty,
+            outcome = Empty,
+        );
+        CommandSpan {
+            span: span.entered(),
+            before: subordinate.traffic,
+        }
+    }
+
+    pub(crate) fn exit(
+        self,
+        subordinate: &SubordinateProcess,
+        result: Result<(ProtocolConstant, Option<u64>), &io::Error>,
+    ) {
+        if let Ok((command, token)) = &result {
+            self.span.record("command", tracing::field::debug(command));
+            self.span.record("token", token);
+        }
+        self.span.record("trace_id", subordinate.trace_context);
+        let received = subordinate.traffic.received - self.before.received;
+        self.span.record("bytes_received", received);
+        self.span.record("outcome", outcome(result.map(drop)).as_str());
+    }
+}
+
+/// The span of one response, entered until it has been written.
+pub(crate) struct ResponseSpan {
+    span: EnteredSpan,
+    before: Traffic,
+}
+
+impl ResponseSpan {
+    pub(crate) fn enter(
+        subordinate: &SubordinateProcess,
+        refusal: Option<RemoteErrorType>,
+    ) -> Self {
+        let span = info_span!(
+            "ufo_ipc.respond",
+            trace_id = subordinate.trace_context,
+            refusal = refusal.map(tracing::field::debug),
+            bytes_sent = Empty,
+            outcome = Empty,
+        );
+        ResponseSpan {
+            span: span.entered(),
+            before: subordinate.traffic,
+        }
+    }
+
+    pub(crate) fn exit(self, subordinate: &SubordinateProcess, result: &io::Result<()>) {
+        self.span.record("bytes_sent", subordinate.traffic.sent - self.before.sent);
+        self.span.record("outcome", outcome(result.as_ref().copied()).as_str());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::net::UnixStream, thread};
+
+    use crate::{endpoint::Subordinate, transport::SocketTransport, *};
+
+    #[test]
+    fn trace_context_reaches_the_subordinate() {
+        let (a, b) = UnixStream::pair().unwrap();
+        let subordinate = thread::spawn(move || {
+            let mut subordinate = SubordinateProcess::new(Box::new(SocketTransport::new(b)));
+            let mut seen = Vec::new();
+            while let ProtocolCommand::Peek(_) = subordinate.recv_command().unwrap().command {
+                seen.push(subordinate.trace_context());
+                subordinate.respond_to_peek(&[], &[]).unwrap();
+            }
+            seen
+        });
+        let mut controller =
+            ControllerProcess::new(Subordinate::Connected, Box::new(SocketTransport::new(a)));
+        controller.set_trace_context(Some(42));
+        controller.peek("a", &[]).unwrap();
+        controller.set_trace_context(None);
+        controller.peek("b", &[]).unwrap();
+        controller.shutdown(&[]).unwrap();
+
+        let seen = subordinate.join().unwrap();
+        assert_eq!(seen, [Some(42), None]);
+    }
+}
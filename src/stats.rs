@@ -0,0 +1,246 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    time::{Duration, Instant},
+};
+
+use crate::*;
+
+// bucket i holds durations under 2^i microseconds, the last one everything longer
+const BUCKETS: usize = 32;
+
+/// A count of durations in buckets that double in size, starting under a microsecond.
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    buckets: [u64; BUCKETS],
+    count: u64,
+    total: Duration,
+}
+
+impl Histogram {
+    fn record(&mut self, duration: Duration) {
+        let micros = duration.as_micros();
+        let bucket = (u128::BITS - micros.leading_zeros()) as usize;
+        self.buckets[bucket.min(BUCKETS - 1)] += 1;
+        self.count += 1;
+        self.total += duration;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        let count = u32::try_from(self.count).ok().filter(|&count| count > 0)?;
+        Some(self.total / count)
+    }
+
+    /// The upper bound of the bucket holding the `q`th quantile, with `q` between 0 and 1.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        self.buckets().find_map(|(bound, count)| {
+            seen += count;
+            (seen >= rank).then_some(bound)
+        })
+    }
+
+    /// Every bucket as its upper bound and how many durations fell in it, shortest first.
+    pub fn buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        self.buckets.iter().enumerate().map(|(i, &count)| {
+            let bound = match i {
+                i if i == BUCKETS - 1 => Duration::MAX,
+                i => Duration::from_micros(1 << i),
+            };
+            (bound, count)
+        })
+    }
+}
+
+/// What a controller has sent its subordinate and what came back, since the controller was
+/// started and across any restarts. See `ControllerProcess::stats`.
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    /// Requests sent, by command, whether they were answered or not.
+    pub requests: HashMap<ProtocolConstant, u64>,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Requests the subordinate refused.
+    pub errors: HashMap<RemoteErrorType, u64>,
+    /// Requests that never got an answer, because the subordinate died or answered nonsense.
+    pub failures: u64,
+    /// Functions and data defined and not yet freed.
+    pub live_functions: usize,
+    pub live_data: usize,
+    /// How long answered requests took, by command.
+    pub latency: HashMap<ProtocolConstant, Histogram>,
+}
+
+#[derive(Default)]
+pub(crate) struct StatsRecorder {
+    stats: Stats,
+    pub(crate) functions: HashSet<FunctionToken>,
+    pub(crate) data: HashSet<DataToken>,
+    // the worker label, once exporting to the metrics crate
+    #[cfg(feature = "metrics")]
+    exporter: Option<String>,
+}
+
+impl StatsRecorder {
+    pub(crate) fn count_traffic(&mut self, sent: usize, received: usize) {
+        self.stats.bytes_sent += sent as u64;
+        self.stats.bytes_received += received as u64;
+    }
+
+    #[cfg(feature = "tracing")]
+    pub(crate) fn traffic(&self) -> crate::spans::Traffic {
+        crate::spans::Traffic {
+            sent: self.stats.bytes_sent,
+            received: self.stats.bytes_received,
+        }
+    }
+
+    fn record<V>(&mut self, command: ProtocolConstant, took: Duration, result: &io::Result<V>) {
+        *self.stats.requests.entry(command).or_default() += 1;
+        let refused = result
+            .as_ref()
+            .err()
+            .and_then(|e| e.get_ref()?.downcast_ref::<RemoteError>())
+            .map(|e| e.err_type);
+        let answered = result.is_ok() || refused.is_some();
+        if let Some(err_type) = refused {
+            *self.stats.errors.entry(err_type).or_default() += 1;
+        }
+        match answered {
+            true => self.stats.latency.entry(command).or_default().record(took),
+            false => self.stats.failures += 1,
+        }
+        #[cfg(feature = "metrics")]
+        self.export(command, answered.then_some(took), refused);
+    }
+}
+
+impl ControllerProcess {
+    /// Counts of what has been sent to the subordinate and what came back, including across
+    /// restarts and the catching up after them.
+    pub fn stats(&self) -> Stats {
+        let recorder = &self.recorder;
+        Stats {
+            live_functions: recorder.functions.len(),
+            live_data: recorder.data.len(),
+            ..recorder.stats.clone()
+        }
+    }
+
+    // send one request and wait for its answer, counting and timing it, and with the tracing
+    // feature, in a span of its own. `token` is the function or data it is about
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn measured<F, V>(
+        &mut self,
+        command: ProtocolConstant,
+        token: Option<u64>,
+        request: F,
+    ) -> io::Result<V>
+    where
+        F: FnOnce(&mut Self) -> io::Result<V>,
+    {
+        let start = Instant::now();
+        #[cfg(feature = "tracing")]
+        let span = crate::spans::RequestSpan::enter(self, command, token);
+        #[cfg(feature = "tracing")]
+        let request = |s: &mut Self| s.send_trace_context().and_then(|()| request(s));
+        let result = request(self);
+        self.recorder.record(command, start.elapsed(), &result);
+        #[cfg(feature = "tracing")]
+        span.exit(self, &result);
+        result
+    }
+}
+
+// Every request is mirrored into whatever recorder the metrics crate has installed, labelled with
+// the worker and the command. Byte counts are sent as absolute values, live tokens as gauges.
+#[cfg(feature = "metrics")]
+impl StatsRecorder {
+    fn export(
+        &self,
+        command: ProtocolConstant,
+        took: Option<Duration>,
+        refused: Option<RemoteErrorType>,
+    ) {
+        use metrics::{counter, gauge, histogram};
+
+        let worker = match &self.exporter {
+            Some(worker) => worker.clone(),
+            None => return,
+        };
+        let command = format!("{:?}", command);
+        counter!("ufo_ipc_requests_total", "worker" => worker.clone(), "command" => command.clone())
+            .increment(1);
+        match took {
+            Some(took) => {
+                let labels = [("worker", worker.clone()), ("command", command)];
+                histogram!("ufo_ipc_request_seconds", &labels).record(took.as_secs_f64())
+            }
+            None => counter!("ufo_ipc_failures_total", "worker" => worker.clone()).increment(1),
+        }
+        if let Some(err_type) = refused {
+            let err_type = format!("{:?}", err_type);
+            counter!("ufo_ipc_errors_total", "worker" => worker.clone(), "error" => err_type)
+                .increment(1);
+        }
+        counter!("ufo_ipc_bytes_sent_total", "worker" => worker.clone())
+            .absolute(self.stats.bytes_sent);
+        counter!("ufo_ipc_bytes_received_total", "worker" => worker.clone())
+            .absolute(self.stats.bytes_received);
+        let live_functions = self.functions.len() as f64;
+        gauge!("ufo_ipc_live_functions", "worker" => worker.clone()).set(live_functions);
+        gauge!("ufo_ipc_live_data", "worker" => worker).set(self.data.len() as f64);
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl ControllerProcess {
+    /// Report every request from now on to the `metrics` crate as well, labelled with `worker`.
+    pub fn export_metrics(&mut self, worker: &str) {
+        self.recorder.exporter = Some(worker.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn stats_count_requests_refusals_and_live_tokens() {
+        let mut controller = MockSubordinate::new()
+            .expect_define_function(b"f", vec![])
+            .expect_define_data(vec![GenericValue::Vu8(1)])
+            .expect_call(FunctionToken(1), vec![])
+            .expect_call(FunctionToken(1), vec![])
+            .fail(RemoteErrorType::UserspaceException)
+            .expect_free_data(DataToken(2))
+            .start()
+            .unwrap();
+        let function = controller.define_function(b"f", &[], &[]).unwrap().value;
+        let data = controller.define_data(&[1u8.into()], &[]).unwrap().value;
+        controller.call_function(&function, &[], &[]).unwrap();
+        assert!(controller.call_function(&function, &[], &[]).is_err());
+        controller.free_data(&data, &[]).unwrap();
+
+        let stats = controller.stats();
+        assert_eq!(stats.requests[&ProtocolConstant::Call], 2);
+        assert_eq!(stats.errors[&RemoteErrorType::UserspaceException], 1);
+        assert_eq!(stats.failures, 0);
+        assert_eq!((stats.live_functions, stats.live_data), (1, 0));
+        assert_eq!(stats.latency[&ProtocolConstant::Call].count(), 2);
+        assert!(stats.bytes_sent > 0 && stats.bytes_received > 0);
+        controller.shutdown(&[]).unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+use std::{io, mem, process::Command};
+
+use crate::{history::SubordinateState, *};
+
+/// How many times a supervised subordinate may be brought back after crashing, and what happens
+/// to the request it crashed under.
+///
+/// Every request but a call is made again on the fresh subordinate. A call may have done
+/// something before the crash, so by default the subordinate is restarted and the call's error
+/// returned; with `retry_calls` it is made again like the rest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestartPolicy {
+    pub max_restarts: usize,
+    pub retry_calls: bool,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy {
+            max_restarts: 3,
+            retry_calls: false,
+        }
+    }
+}
+
+pub(crate) struct Supervisor {
+    command: Command,
+    policy: RestartPolicy,
+    restarts: usize,
+    pub(crate) history: SubordinateState,
+}
+
+impl Supervisor {
+    pub(crate) fn new(command: Command, policy: RestartPolicy) -> Self {
+        Supervisor {
+            command,
+            policy,
+            restarts: 0,
+            history: SubordinateState::default(),
+        }
+    }
+}
+
+impl ControllerProcess {
+    pub fn is_supervised(&self) -> bool {
+        self.supervisor.is_some()
+    }
+
+    /// How many times the subordinate has been restarted so far.
+    pub fn restarts(&self) -> usize {
+        self.supervisor.as_ref().map_or(0, |s| s.restarts)
+    }
+
+    pub(crate) fn record<F>(&mut self, update: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut SubordinateState) -> io::Result<()>,
+    {
+        match &mut self.supervisor {
+            Some(supervisor) => update(&mut supervisor.history),
+            None => Ok(()),
+        }
+    }
+
+    fn crashed(&mut self, e: &io::Error) -> bool {
+        self.supervisor.is_some() && (is_disconnect(e) || !self.is_alive())
+    }
+
+    // start a fresh subordinate, bring it up to date and take over its transport and arenas
+    fn restart(&mut self) -> io::Result<()> {
+        self.terminate();
+        let arena_size = self.arena_size();
+        // the fresh one is connected the way this one was
+        let fds = self.transport.passes_fds();
+        let mut supervisor = self.supervisor.take().expect("only supervised subordinates restart");
+        loop {
+            if supervisor.restarts >= supervisor.policy.max_restarts {
+                let max_restarts = supervisor.policy.max_restarts;
+                self.supervisor = Some(supervisor);
+                return Err(SupervisorError::RestartLimitReached(max_restarts).into());
+            }
+            supervisor.restarts += 1;
+
+            let started = if fds {
+                supervisor.command.start_subordinate_process_with_fds()
+            } else {
+                supervisor.command.start_subordinate_process()
+            };
+            let mut fresh = match started {
+                Ok(fresh) => fresh,
+                Err(e) => {
+                    self.supervisor = Some(supervisor);
+                    return Err(e);
+                }
+            };
+            // the stats carry on in the fresh controller, and count the catching up
+            mem::swap(&mut fresh.recorder, &mut self.recorder);
+            // catching up is traced as the start of a new session
+            if let Some(tracer) = &self.tracer {
+                fresh.trace_with(tracer.clone());
+                fresh.tracer = Some(tracer.clone());
+                tracer.restarted();
+            }
+            let caught_up = match arena_size {
+                Some(size) => fresh.attach_arena(size),
+                None => Ok(()),
+            }
+            .and_then(|()| supervisor.history.replay(&mut fresh));
+            mem::swap(&mut fresh.recorder, &mut self.recorder);
+            match caught_up {
+                Ok(()) => {
+                    fresh.id_ctr = self.id_ctr;
+                    fresh.recorder = mem::take(&mut self.recorder);
+                    #[cfg(feature = "tracing")]
+                    {
+                        fresh.trace_context = self.trace_context;
+                    }
+                    fresh.supervisor = Some(supervisor);
+                    *self = fresh;
+                    return Ok(());
+                }
+                // crashed again while catching up, go around
+                Err(e) if is_disconnect(&e) || !fresh.is_alive() => fresh.terminate(),
+                Err(e) => {
+                    fresh.terminate();
+                    self.supervisor = Some(supervisor);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    // run a request, and if the subordinate dies under it, restart it and try again
+    pub(crate) fn supervised<F, V>(&mut self, request: F) -> io::Result<V>
+    where
+        F: FnMut(&mut Self) -> io::Result<V>,
+    {
+        self.supervise(true, request)
+    }
+
+    // a call is only made again if the policy says so, otherwise its error is returned once the
+    // subordinate is back
+    pub(crate) fn supervised_call<F, V>(&mut self, request: F) -> io::Result<V>
+    where
+        F: FnMut(&mut Self) -> io::Result<V>,
+    {
+        let retry = self.supervisor.as_ref().is_some_and(|s| s.policy.retry_calls);
+        self.supervise(retry, request)
+    }
+
+    fn supervise<F, V>(&mut self, retry: bool, mut request: F) -> io::Result<V>
+    where
+        F: FnMut(&mut Self) -> io::Result<V>,
+    {
+        loop {
+            match request(self) {
+                Err(e) if self.crashed(&e) => {
+                    self.restart()?;
+                    if !retry {
+                        return Err(e);
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{testing::subordinate_command, *};
+
+    #[test]
+    fn a_killed_subordinate_comes_back_with_what_it_held() {
+        let policy = RestartPolicy::default();
+        let mut controller =
+            subordinate_command().start_supervised_subordinate_process(policy).unwrap();
+        let echo = controller.define_function(b"echo", &[], &[]).unwrap().value;
+        controller.poke("k", &[1u8.into()], &[]).unwrap();
+
+        controller.terminate();
+        // the call that found it dead isn't made again
+        assert!(controller.call_function(&echo, &[2u8.into()], &[]).is_err());
+        assert_eq!(controller.restarts(), 1);
+        let value = controller.call_function(&echo, &[2u8.into()], &[]).unwrap().value;
+        assert_eq!(value, [GenericValueBoxed::Vu8(2)]);
+
+        controller.terminate();
+        // a peek is, and sees what was poked before either crash
+        assert_eq!(controller.peek("k", &[]).unwrap().value, [GenericValueBoxed::Vu8(1)]);
+        assert_eq!(controller.restarts(), 2);
+        controller.shutdown(&[]).unwrap();
+
+        let policy = RestartPolicy {
+            retry_calls: true,
+            ..RestartPolicy::default()
+        };
+        let mut controller =
+            subordinate_command().start_supervised_subordinate_process(policy).unwrap();
+        let echo = controller.define_function(b"echo", &[], &[]).unwrap().value;
+        controller.terminate();
+        let value = controller.call_function(&echo, &[3u8.into()], &[]).unwrap().value;
+        assert_eq!(value, [GenericValueBoxed::Vu8(3)]);
+        controller.shutdown(&[]).unwrap();
+    }
+}
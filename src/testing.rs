@@ -0,0 +1,66 @@
+// Subordinates for tests that need one on the other end of a real process or socket.
+
+use std::{collections::HashMap, io, process::Command};
+
+use crate::*;
+
+// the test binary runs as a subordinate when started with this set, see `subordinate_command`
+const AS_SUBORDINATE: &str = "UFO_IPC_TEST_SUBORDINATE";
+
+// what a conforming subordinate looks like, see `check_conformance`
+pub(crate) fn serve(mut subordinate: SubordinateProcess) -> io::Result<()> {
+    let mut functions = HashMap::new();
+    let mut poked: HashMap<String, Vec<GenericValueBoxed>> = HashMap::new();
+    loop {
+        let Request { command, aux } = subordinate.recv_command()?;
+        let aux = aux.as_refs();
+        match command {
+            ProtocolCommand::DefineFunction {
+                token,
+                function_blob,
+                ..
+            } => {
+                functions.insert(token, function_blob);
+                subordinate.respond_to_define(&aux)?
+            }
+            ProtocolCommand::Call { token, args } => match functions.get(&token) {
+                Some(blob) if blob == b"fail" => subordinate
+                    .respond_with_error(RemoteErrorType::UserspaceException, &args.as_refs())?,
+                Some(_) => subordinate.respond_to_call(&args.as_refs(), &aux)?,
+                None => subordinate.respond_with_error(RemoteErrorType::ProtocolError, &aux)?,
+            },
+            ProtocolCommand::FreeFunction(token) => {
+                functions.remove(&token);
+                subordinate.respond_to_unregister(&aux)?
+            }
+            ProtocolCommand::DefineData { .. } => subordinate.respond_to_define(&aux)?,
+            ProtocolCommand::FreeData(_) => subordinate.respond_to_unregister(&aux)?,
+            ProtocolCommand::Peek(key) => match poked.get(&key) {
+                Some(value) => subordinate.respond_to_peek(&value.as_refs(), &aux)?,
+                None => subordinate.respond_to_peek(&[GenericValue::Null], &aux)?,
+            },
+            ProtocolCommand::Poke { key, value } => {
+                subordinate.respond_to_poke(&aux)?;
+                poked.insert(key, value);
+            }
+            ProtocolCommand::Shutdown => return Ok(()),
+        }
+    }
+}
+
+// this test binary, started so that it runs `serve` and nothing else
+pub(crate) fn subordinate_command() -> Command {
+    let mut command = Command::new(std::env::current_exe().unwrap());
+    command
+        .args(["--exact", "testing::run_as_subordinate", "--quiet", "--test-threads=1"])
+        .env(AS_SUBORDINATE, "1");
+    command
+}
+
+#[test]
+fn run_as_subordinate() {
+    if std::env::var_os(AS_SUBORDINATE).is_some() {
+        let served = subordinate_begin().and_then(serve);
+        std::process::exit(if served.is_ok() { 0 } else { 1 });
+    }
+}
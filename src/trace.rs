@@ -0,0 +1,599 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    os::unix::io::{BorrowedFd, OwnedFd},
+    path::Path,
+    result::Result,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use derive_try_from_primitive::TryFromPrimitive;
+
+use crate::{
+    endpoint::Subordinate, serialization::sealed::SerializationEndpoint, transport::Transport, *,
+};
+
+// A trace file is the magic, the role of the endpoint that wrote it, then one record per read
+// or write on the transport: kind u8, nanoseconds since the trace started u64, length u32 and
+// that many bytes, all little endian. Passing a file descriptor is a record with no bytes, and
+// a supervised subordinate being restarted starts a new session.
+const MAGIC: &[u8; 8] = b"UFOTRACE";
+
+#[repr(u8)]
+#[derive(TryFromPrimitive, Copy, Clone, Debug, PartialEq, Eq)]
+enum Record {
+    Sent,
+    Received,
+    FdSent,
+    FdReceived,
+    Restart,
+}
+
+#[repr(u8)]
+#[derive(TryFromPrimitive, Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Role {
+    Controller,
+    Subordinate,
+}
+
+struct TraceFile {
+    out: BufWriter<File>,
+    start: Instant,
+    // a trace that couldn't be written stops there, the session itself carries on
+    failed: bool,
+}
+
+impl TraceFile {
+    fn write(&mut self, write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>) {
+        if !self.failed && write(&mut self.out).is_err() {
+            self.failed = true;
+        }
+    }
+}
+
+// shared, so a restarted subordinate's transport keeps writing to the same file
+#[derive(Clone)]
+pub(crate) struct Tracer(Arc<Mutex<TraceFile>>);
+
+impl Tracer {
+    pub(crate) fn create(path: &Path, role: Role) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&[role as u8])?;
+        Ok(Tracer(Arc::new(Mutex::new(TraceFile {
+            out,
+            start: Instant::now(),
+            failed: false,
+        }))))
+    }
+
+    fn record(&self, record: Record, bytes: &[u8]) {
+        let mut file = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let nanos = file.start.elapsed().as_nanos() as u64;
+        file.write(|out| {
+            out.write_all(&[record as u8])?;
+            out.write_all(&nanos.to_le_bytes())?;
+            out.write_all(&(bytes.len() as u32).to_le_bytes())?;
+            out.write_all(bytes)
+        });
+    }
+
+    pub(crate) fn restarted(&self) {
+        self.record(Record::Restart, &[]);
+        self.flush();
+    }
+
+    fn flush(&self) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).write(|out| out.flush());
+    }
+}
+
+/// Copies everything that goes through a transport into a trace file, see `trace_to`.
+pub(crate) struct TracingTransport {
+    inner: Box<dyn Transport>,
+    tracer: Tracer,
+}
+
+impl Read for TracingTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.tracer.record(Record::Received, &buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+impl Write for TracingTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.tracer.record(Record::Sent, &buf[..n]);
+        Ok(n)
+    }
+
+    // every flush ends a message, which then makes it to the file even if we crash afterwards
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        self.tracer.flush();
+        Ok(())
+    }
+}
+
+impl Transport for TracingTransport {
+    fn send_fd(&mut self, fd: BorrowedFd) -> io::Result<()> {
+        self.inner.send_fd(fd)?;
+        self.tracer.record(Record::FdSent, &[]);
+        Ok(())
+    }
+
+    fn recv_fd(&mut self) -> io::Result<OwnedFd> {
+        let fd = self.inner.recv_fd()?;
+        self.tracer.record(Record::FdReceived, &[]);
+        Ok(fd)
+    }
+
+    fn passes_fds(&self) -> bool {
+        self.inner.passes_fds()
+    }
+
+    fn hung_up(&self) -> bool {
+        self.inner.hung_up()
+    }
+}
+
+// a stand-in while the real transport is being wrapped
+impl Transport for io::Empty {}
+
+fn wrap(transport: &mut Box<dyn Transport>, tracer: Tracer) {
+    let inner = std::mem::replace(transport, Box::new(io::empty()));
+    *transport = Box::new(TracingTransport { inner, tracer });
+}
+
+impl ControllerProcess {
+    /// Record every message sent to and received from the subordinate from here on in a trace
+    /// file at `path`, replacing anything already there. `ufo-ipc-dump` reads it back. A
+    /// supervised subordinate keeps being traced to the same file when it is restarted. Should
+    /// writing the trace fail, it stops there and the session carries on untraced.
+    pub fn trace_to<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let tracer = Tracer::create(path.as_ref(), Role::Controller)?;
+        self.trace_with(tracer.clone());
+        self.tracer = Some(tracer);
+        Ok(())
+    }
+
+    pub(crate) fn trace_with(&mut self, tracer: Tracer) {
+        wrap(&mut self.transport, tracer);
+    }
+}
+
+impl SubordinateProcess {
+    /// Record every message received from and sent to the controller from here on in a trace
+    /// file at `path`, replacing anything already there. `ufo-ipc-dump` reads it back. Should
+    /// writing the trace fail, it stops there and the session carries on untraced.
+    pub fn trace_to<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        wrap(&mut self.transport, Tracer::create(path.as_ref(), Role::Subordinate)?);
+        Ok(())
+    }
+}
+
+/*
+ * reading a trace back
+ */
+
+// the bytes that went one way in a session, with when each read or write started
+#[derive(Default)]
+struct Stream {
+    bytes: Vec<u8>,
+    chunks: Vec<(usize, u64)>,
+}
+
+impl Stream {
+    fn push(&mut self, nanos: u64, bytes: &[u8]) {
+        self.chunks.push((self.bytes.len(), nanos));
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    // when the byte at `offset` went over the transport
+    fn time_of(&self, offset: usize) -> u64 {
+        let index = self.chunks.partition_point(|&(start, _)| start <= offset);
+        index.checked_sub(1).map_or(0, |i| self.chunks[i].1)
+    }
+}
+
+#[derive(Default)]
+struct Session {
+    sent: Stream,
+    received: Stream,
+}
+
+fn read_sessions<R: Read>(trace: &mut R) -> io::Result<(Role, Vec<Session>)> {
+    let mut magic = [0u8; 9];
+    trace.read_exact(&mut magic)?;
+    if &magic[..8] != MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a ufo_ipc trace"));
+    }
+    let role = Role::try_from(magic[8])
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "unknown role in trace"))?;
+
+    let mut sessions = vec![Session::default()];
+    let mut header = [0u8; 13];
+    loop {
+        match trace.read_exact(&mut header) {
+            Ok(()) => {}
+            // a trace cut short by a crash ends wherever it ends
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        let nanos = u64::from_le_bytes(header[1..9].try_into().unwrap());
+        let len = u32::from_le_bytes(header[9..13].try_into().unwrap()) as usize;
+        let mut bytes = vec![0u8; len];
+        if trace.read_exact(&mut bytes).is_err() {
+            break;
+        }
+
+        let session = sessions.last_mut().expect("there is always a session");
+        match Record::try_from(header[0]) {
+            Ok(Record::Sent) => session.sent.push(nanos, &bytes),
+            Ok(Record::Received) => session.received.push(nanos, &bytes),
+            // file descriptors aren't in the byte stream, `Replay` makes them up as they're read
+            Ok(Record::FdSent) | Ok(Record::FdReceived) => {}
+            Ok(Record::Restart) => sessions.push(Session::default()),
+            Err(_) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown trace record"))
+            }
+        }
+    }
+    Ok((role, sessions))
+}
+
+// plays one direction of a session back to an endpoint, for the usual read path to decode
+struct Replay {
+    bytes: Vec<u8>,
+    position: Arc<AtomicUsize>,
+}
+
+impl Read for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let position = self.position.load(Ordering::Relaxed);
+        let n = buf.len().min(self.bytes.len() - position);
+        buf[..n].copy_from_slice(&self.bytes[position..position + n]);
+        self.position.store(position + n, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+impl Write for Replay {
+    // anything the endpoint would say back isn't part of the trace
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Replay {
+    fn recv_fd(&mut self) -> io::Result<OwnedFd> {
+        Ok(File::open("/dev/null")?.into())
+    }
+}
+
+fn replay(stream: &Stream) -> (Box<dyn Transport>, Arc<AtomicUsize>) {
+    let position = Arc::new(AtomicUsize::new(0));
+    let replay = Replay {
+        bytes: stream.bytes.clone(),
+        position: position.clone(),
+    };
+    (Box::new(replay), position)
+}
+
+/// Values written out as a bracketed, comma separated list, the way `ufo-ipc` and trace dumps
+/// print them.
+pub fn list(values: &[GenericValueBoxed]) -> String {
+    let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    format!("[{}]", values.join(", "))
+}
+
+/// What the controller sent in one exchange of a recorded session.
+#[derive(Debug)]
+pub enum Message {
+    Hello,
+    Arena,
+    Command(Request),
+}
+
+impl Message {
+    fn is_shutdown(&self) -> bool {
+        matches!(
+            self,
+            Message::Command(Request {
+                command: ProtocolCommand::Shutdown,
+                ..
+            })
+        )
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Message::Hello => write!(f, "Hello"),
+            Message::Arena => write!(f, "Arena"),
+            Message::Command(Request {
+                command: ProtocolCommand::Shutdown,
+                aux,
+            }) => write!(f, "Goodbye aux {}", list(aux)),
+            Message::Command(request) => {
+                write!(f, "{:?} aux {}", request.command, list(&request.aux))
+            }
+        }
+    }
+}
+
+/// What the subordinate answered. Commands that don't return values have an empty `value`.
+#[derive(Debug)]
+pub enum Reply {
+    Hello,
+    Result(Response<Vec<GenericValueBoxed>>),
+    Error(RemoteError),
+}
+
+impl fmt::Display for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reply::Hello => write!(f, "Hello"),
+            Reply::Result(response) => write!(
+                f,
+                "Result {} aux {}",
+                list(&response.value),
+                list(&response.response_aux)
+            ),
+            Reply::Error(e) => write!(f, "{:?} aux {}", e.err_type, list(&e.aux)),
+        }
+    }
+}
+
+/// A message and the reply to it, with when each went over the transport since the trace
+/// started. There is no reply to a shutdown, nor to a message the subordinate never answered.
+#[derive(Debug)]
+pub struct Exchange {
+    pub sent_at: Duration,
+    pub message: Message,
+    pub reply: Option<(Duration, Reply)>,
+}
+
+/// Everything that went over one connection to a subordinate.
+#[derive(Debug)]
+pub struct RecordedSession {
+    pub exchanges: Vec<Exchange>,
+    /// Why decoding stopped before the end of the session, if it did.
+    pub error: Option<io::Error>,
+}
+
+/// A trace written by `trace_to`, decoded. A supervised subordinate being restarted starts a
+/// new session. Byte strings that went through a shared arena aren't in the trace, decoding a
+/// session stops at the first one.
+#[derive(Debug)]
+pub struct Recording {
+    role: Role,
+    sessions: Vec<RecordedSession>,
+}
+
+impl Recording {
+    pub fn read<R: Read>(mut trace: R) -> io::Result<Self> {
+        let (role, sessions) = read_sessions(&mut trace)?;
+        let sessions = sessions
+            .iter()
+            .map(|session| match role {
+                Role::Controller => decode_session(&session.sent, &session.received),
+                Role::Subordinate => decode_session(&session.received, &session.sent),
+            })
+            .collect();
+        Ok(Recording { role, sessions })
+    }
+
+    pub fn sessions(&self) -> &[RecordedSession] {
+        &self.sessions
+    }
+}
+
+/// Decode a trace written by `trace_to` into one line per message, each command followed by the
+/// response to it.
+pub fn dump_trace<R: Read, W: Write>(trace: R, mut out: W) -> io::Result<()> {
+    let recording = Recording::read(trace)?;
+    writeln!(out, "trace written by the {:?}", recording.role)?;
+
+    for (i, session) in recording.sessions.iter().enumerate() {
+        if i > 0 {
+            writeln!(out, "-- subordinate restarted --")?;
+        }
+        for exchange in &session.exchanges {
+            let at = exchange.sent_at.as_secs_f64();
+            writeln!(out, "{:>12.6} -> {}", at, exchange.message)?;
+            match &exchange.reply {
+                Some((at, reply)) => writeln!(out, "{:>12.6} <- {}", at.as_secs_f64(), reply)?,
+                None if !exchange.message.is_shutdown() => {
+                    writeln!(out, "             <- (no response)")?
+                }
+                None => {}
+            }
+        }
+        if let Some(e) = &session.error {
+            writeln!(out, "!! {}", e)?;
+        }
+    }
+    Ok(())
+}
+
+// the remote error a response was refused with, or the error if there wasn't one
+pub(crate) fn into_remote_error(e: io::Error) -> io::Result<RemoteError> {
+    if !e.get_ref().is_some_and(|inner| inner.is::<RemoteError>()) {
+        return Err(e);
+    }
+    let inner = e.into_inner().expect("checked above");
+    Ok(*inner.downcast::<RemoteError>().expect("checked above"))
+}
+
+fn decode_session(commands: &Stream, responses: &Stream) -> RecordedSession {
+    let mut exchanges = Vec::new();
+    let error = decode_exchanges(commands, responses, &mut exchanges).err();
+    RecordedSession { exchanges, error }
+}
+
+fn decode_exchanges(
+    commands: &Stream,
+    responses: &Stream,
+    exchanges: &mut Vec<Exchange>,
+) -> io::Result<()> {
+    let (transport, command_position) = replay(commands);
+    let mut command_reader = SubordinateProcess::new(transport);
+    let (transport, response_position) = replay(responses);
+    let mut response_reader = ControllerProcess::new(Subordinate::Connected, transport);
+
+    let stamp = |stream: &Stream, position: &AtomicUsize| {
+        Duration::from_nanos(stream.time_of(position.load(Ordering::Relaxed)))
+    };
+
+    while command_position.load(Ordering::Relaxed) < commands.bytes.len() {
+        let sent_at = stamp(commands, &command_position);
+        let message = match command_reader.read_protocol()? {
+            ProtocolConstant::Hello => Message::Hello,
+            ProtocolConstant::Arena => {
+                command_reader.read_fd()?;
+                command_reader.read_fd()?;
+                Message::Arena
+            }
+            // part of the command that follows, which is stamped on its own
+            ProtocolConstant::Context => {
+                command_reader.read_u64()?;
+                continue;
+            }
+            protocol => Message::Command(command_reader.read_command_body(protocol)?),
+        };
+
+        let answered = response_position.load(Ordering::Relaxed) < responses.bytes.len();
+        if message.is_shutdown() || !answered {
+            exchanges.push(Exchange {
+                sent_at,
+                message,
+                reply: None,
+            });
+            return Ok(());
+        }
+
+        let replied_at = stamp(responses, &response_position);
+        // the value of a response depends on the command it answers
+        let reply = match &message {
+            Message::Hello => {
+                response_reader.read_protocol()?.expect(ProtocolConstant::Hello)?;
+                Reply::Hello
+            }
+            message => {
+                let returns_values = matches!(
+                    message,
+                    Message::Command(Request {
+                        command: ProtocolCommand::Call { .. } | ProtocolCommand::Peek(_),
+                        ..
+                    })
+                );
+                let response = response_reader.read_response(|r| match returns_values {
+                    true => r.read_generic_vec(),
+                    false => Ok(Vec::new()),
+                });
+                match response {
+                    Ok(response) => Reply::Result(response),
+                    Err(e) => Reply::Error(into_remote_error(e)?),
+                }
+            }
+        };
+        exchanges.push(Exchange {
+            sent_at,
+            message,
+            reply: Some((replied_at, reply)),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::net::UnixStream, thread};
+
+    use super::*;
+    use crate::transport::SocketTransport;
+
+    #[test]
+    fn a_traced_session_dumps_as_commands_and_responses() {
+        let (a, b) = UnixStream::pair().unwrap();
+        let subordinate = thread::spawn(move || {
+            let mut subordinate = SubordinateProcess::new(Box::new(SocketTransport::new(b)));
+            subordinate.hello().unwrap();
+            while let Request {
+                command: ProtocolCommand::Peek(key),
+                ..
+            } = subordinate.recv_command().unwrap()
+            {
+                subordinate.respond_to_peek(&[key.as_str().into()], &[]).unwrap();
+            }
+        });
+
+        let mut controller =
+            ControllerProcess::new(Subordinate::Connected, Box::new(SocketTransport::new(a)));
+        controller.hello().unwrap();
+        let path = std::env::temp_dir().join(format!("ufo_ipc_trace_{}", std::process::id()));
+        controller.trace_to(&path).unwrap();
+        assert!(controller.transport.passes_fds());
+        controller.peek("key", &[GenericValue::Vu8(7)]).unwrap();
+        controller.shutdown(&[]).unwrap();
+        subordinate.join().unwrap();
+
+        let mut dump = Vec::new();
+        dump_trace(File::open(&path).unwrap(), &mut dump).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let dump = String::from_utf8(dump).unwrap();
+        let lines: Vec<&str> = dump.lines().map(|l| l.trim_start().split_once(' ').unwrap().1).collect();
+        assert_eq!(
+            lines,
+            [
+                "written by the Controller",
+                "-> Peek(\"key\") aux [7]",
+                "<- Result [\"key\"] aux []",
+                "-> Goodbye aux []",
+            ]
+        );
+    }
+
+    #[test]
+    fn a_trace_that_cant_be_written_leaves_the_session_alone() {
+        let (a, b) = UnixStream::pair().unwrap();
+        let subordinate = thread::spawn(move || {
+            let mut subordinate = SubordinateProcess::new(Box::new(SocketTransport::new(b)));
+            subordinate.hello().unwrap();
+            subordinate.trace_to("/dev/full").unwrap();
+            while let Request {
+                command: ProtocolCommand::Peek(key),
+                ..
+            } = subordinate.recv_command().unwrap()
+            {
+                subordinate.respond_to_peek(&[key.as_str().into()], &[]).unwrap();
+            }
+        });
+
+        let mut controller =
+            ControllerProcess::new(Subordinate::Connected, Box::new(SocketTransport::new(a)));
+        controller.hello().unwrap();
+        controller.trace_to("/dev/full").unwrap();
+        for _ in 0..2 {
+            let response = controller.peek("key", &[]).unwrap();
+            assert_eq!(response.value, [GenericValue::from("key")]);
+        }
+        controller.shutdown(&[]).unwrap();
+        subordinate.join().unwrap();
+    }
+}
@@ -0,0 +1,286 @@
+use nix::{
+    cmsg_space,
+    sys::{
+        socket::{recv, recvmsg, sendmsg, ControlMessage, ControlMessageOwned, MsgFlags},
+        stat::{fstat, SFlag},
+        uio::IoVec,
+    },
+};
+use os_pipe::{PipeReader, PipeWriter};
+use std::{
+    collections::VecDeque,
+    io,
+    io::{Read, Write},
+    os::unix::{
+        io::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+        net::UnixStream,
+    },
+};
+
+use crate::ProtocolError;
+
+/// A byte stream between a controller and a subordinate.
+pub trait Transport: Read + Write + Send {
+    /// Send a file descriptor along with the stream, for `GenericValue::Fd`.
+    fn send_fd(&mut self, _fd: BorrowedFd) -> io::Result<()> {
+        Err(ProtocolError::FdPassingUnsupported.into())
+    }
+
+    /// Take the file descriptor sent by the matching `send_fd`.
+    fn recv_fd(&mut self) -> io::Result<OwnedFd> {
+        Err(ProtocolError::FdPassingUnsupported.into())
+    }
+
+    /// Whether `send_fd` and `recv_fd` work, checked before a value holding a file descriptor
+    /// is written.
+    fn passes_fds(&self) -> bool {
+        false
+    }
+
+    /// Whether the other end is known to have gone away, as far as can be told without
+    /// reading anything or waiting.
+    fn hung_up(&self) -> bool {
+        false
+    }
+}
+
+// a duplicate of the descriptor without close-on-exec, for handing to a child process
+pub(crate) fn inheritable_copy<F: AsRawFd>(fd: &F) -> io::Result<OwnedFd> {
+    let copy = nix::unistd::dup(fd.as_raw_fd()).map_err(io::Error::from)?;
+    Ok(unsafe { OwnedFd::from_raw_fd(copy) })
+}
+
+pub(crate) fn is_socket(fd: RawFd) -> bool {
+    fstat(fd)
+        .map(|stat| SFlag::from_bits_truncate(stat.st_mode) & SFlag::S_IFMT == SFlag::S_IFSOCK)
+        .unwrap_or(false)
+}
+
+/// The pair of pipes a subordinate can be started with.
+pub(crate) struct PipeTransport {
+    reader: PipeReader,
+    writer: PipeWriter,
+}
+
+impl PipeTransport {
+    pub(crate) fn new(reader: PipeReader, writer: PipeWriter) -> Self {
+        PipeTransport { reader, writer }
+    }
+}
+
+impl Read for PipeTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl Write for PipeTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl Transport for PipeTransport {}
+
+// how many descriptors one read can pick up, only ever one is sent at a time
+const MAX_FDS_PER_READ: usize = 4;
+
+/// A Unix stream socket, which can carry file descriptors as `SCM_RIGHTS` messages.
+pub(crate) struct SocketTransport {
+    stream: UnixStream,
+    // descriptors that arrived with bytes already read, waiting for their recv_fd
+    received: VecDeque<OwnedFd>,
+}
+
+impl SocketTransport {
+    pub(crate) fn new(stream: UnixStream) -> Self {
+        SocketTransport {
+            stream,
+            received: VecDeque::new(),
+        }
+    }
+}
+
+impl Read for SocketTransport {
+    // a plain read() would silently close any descriptors riding on the bytes it reads
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut cmsg_buffer = cmsg_space!([RawFd; MAX_FDS_PER_READ]);
+        let iov = [IoVec::from_mut_slice(buf)];
+        let msg = recvmsg(
+            self.stream.as_raw_fd(),
+            &iov,
+            Some(&mut cmsg_buffer),
+            MsgFlags::MSG_CMSG_CLOEXEC,
+        )
+        .map_err(io::Error::from)?;
+
+        for cmsg in msg.cmsgs() {
+            if let ControlMessageOwned::ScmRights(fds) = cmsg {
+                self.received
+                    .extend(fds.into_iter().map(|fd| unsafe { OwnedFd::from_raw_fd(fd) }));
+            }
+        }
+        Ok(msg.bytes)
+    }
+}
+
+impl Write for SocketTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Transport for SocketTransport {
+    // the descriptor rides on a single placeholder byte, so it arrives exactly where it was sent
+    fn send_fd(&mut self, fd: BorrowedFd) -> io::Result<()> {
+        let fds = [fd.as_raw_fd()];
+        let iov = [IoVec::from_slice(&[0u8])];
+        let cmsgs = [ControlMessage::ScmRights(&fds)];
+        sendmsg(self.stream.as_raw_fd(), &iov, &cmsgs, MsgFlags::empty(), None)
+            .map_err(io::Error::from)?;
+        Ok(())
+    }
+
+    fn recv_fd(&mut self) -> io::Result<OwnedFd> {
+        let mut placeholder = [0u8];
+        self.read_exact(&mut placeholder)?;
+        self.received
+            .pop_front()
+            .ok_or_else(|| ProtocolError::MissingFd.into())
+    }
+
+    fn passes_fds(&self) -> bool {
+        true
+    }
+
+    // a peek at the next byte, which is end of file once the peer has closed its end
+    fn hung_up(&self) -> bool {
+        let mut next = [0u8];
+        let flags = MsgFlags::MSG_PEEK | MsgFlags::MSG_DONTWAIT;
+        match recv(self.stream.as_raw_fd(), &mut next, flags) {
+            Ok(n) => n == 0,
+            Err(e) => e != nix::errno::Errno::EAGAIN && e != nix::errno::Errno::EINTR,
+        }
+    }
+}
+
+/// Bytes held in memory, for feeding an endpoint made-up input. Reads take from the bytes it
+/// was made with, writes are thrown away unless it loops back, when later reads take them too.
+#[cfg(any(test, feature = "fuzzing"))]
+pub(crate) struct MemoryTransport {
+    bytes: VecDeque<u8>,
+    loopback: bool,
+}
+
+#[cfg(any(test, feature = "fuzzing"))]
+impl MemoryTransport {
+    pub(crate) fn new(bytes: &[u8], loopback: bool) -> Self {
+        MemoryTransport {
+            bytes: bytes.iter().copied().collect(),
+            loopback,
+        }
+    }
+}
+
+#[cfg(any(test, feature = "fuzzing"))]
+impl Read for MemoryTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.bytes.read(buf)
+    }
+}
+
+#[cfg(any(test, feature = "fuzzing"))]
+impl Write for MemoryTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.loopback {
+            self.bytes.extend(buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(any(test, feature = "fuzzing"))]
+impl Transport for MemoryTransport {}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        io::{Read, Seek, Write},
+        os::unix::{io::AsFd, net::UnixStream},
+    };
+
+    use super::*;
+    use crate::{serialization::sealed::SerializationEndpoint, *};
+
+    #[test]
+    fn fd_arrives_between_the_values_it_was_sent_with() {
+        let (a, b) = UnixStream::pair().unwrap();
+        let mut sender = SubordinateProcess::new(Box::new(SocketTransport::new(a)));
+        let mut receiver = SubordinateProcess::new(Box::new(SocketTransport::new(b)));
+
+        let mut file = tempfile("fd_arrives");
+        file.write_all(b"passed along").unwrap();
+
+        sender
+            .write_generic_vec(&[
+                GenericValue::Vu8(1),
+                GenericValue::Fd(file.as_fd()),
+                GenericValue::Vu8(2),
+            ])
+            .unwrap();
+
+        let mut values = receiver.read_generic_vec().unwrap().into_iter();
+        assert!(matches!(values.next(), Some(GenericValue::Vu8(1))));
+        let mut received = File::from(values.next().unwrap().expect_fd_into().unwrap());
+        assert!(matches!(values.next(), Some(GenericValue::Vu8(2))));
+
+        let mut contents = String::new();
+        received.rewind().unwrap();
+        received.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "passed along");
+    }
+
+    #[test]
+    fn pipes_refuse_fds() {
+        let (reader, writer) = os_pipe::pipe().unwrap();
+        let (unused_reader, unused_writer) = os_pipe::pipe().unwrap();
+        let mut sender =
+            SubordinateProcess::new(Box::new(PipeTransport::new(unused_reader, writer)));
+        let mut receiver =
+            SubordinateProcess::new(Box::new(PipeTransport::new(reader, unused_writer)));
+
+        let file = tempfile("pipes_refuse");
+        let refused = [GenericValue::Vu8(1), GenericValue::Fd(file.as_fd())];
+        assert!(sender.write_generic_vec(&refused).is_err());
+        // nothing of the refused values went out ahead of the next ones
+        sender.write_generic_vec(&[GenericValue::Vu8(3)]).unwrap().flush().unwrap();
+        let values = receiver.read_generic_vec().unwrap();
+        assert!(matches!(values[..], [GenericValue::Vu8(3)]));
+    }
+
+    fn tempfile(name: &str) -> File {
+        let path = std::env::temp_dir().join(format!("ufo_ipc_{}_{}", name, std::process::id()));
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+        file
+    }
+}
@@ -0,0 +1,40 @@
+#![cfg(feature = "derive")]
+
+use ufo_ipc::*;
+
+#[derive(IntoGenerics, FromGenerics, Debug, PartialEq)]
+struct Labelled<T> {
+    label: String,
+    value: T,
+}
+
+#[derive(IntoGenerics, FromGenerics, Debug, PartialEq)]
+struct Pair<A, B>(A, Option<B>);
+
+#[test]
+fn generic_structs_derive_for_any_parameters_that_convert() {
+    let labelled = Labelled {
+        label: "answer".to_string(),
+        value: 42u32,
+    };
+    assert_eq!(
+        labelled.to_generics(),
+        [GenericValueRef::from("answer"), GenericValue::Vu32(42)]
+    );
+    let values = labelled.into_generics();
+    let read: Labelled<u32> = FromGenerics::from_generics(values).unwrap();
+    assert_eq!(read.value, 42);
+
+    let pair = Pair("left".to_string(), None::<f64>);
+    let read: Pair<String, f64> = FromGenerics::from_generics(pair.into_generics()).unwrap();
+    assert_eq!(read, Pair("left".to_string(), None));
+}
+
+#[test]
+fn fields_of_the_wrong_type_are_reported_by_name() {
+    let values = vec![GenericValue::from("answer".to_string()), GenericValue::Vu8(1)];
+    match Labelled::<String>::from_generics(values) {
+        Err(FromGenericsError::Field { field, .. }) => assert_eq!(field, "value"),
+        other => panic!("{:?}", other.map(|l| l.value)),
+    }
+}
@@ -0,0 +1,11 @@
+// The header is generated into OUT_DIR, and include/ufo_ipc.h is a checked in copy so C code can
+// use it without building first. A test fails when the copy falls behind the crate.
+fn main() {
+    let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out = std::env::var("OUT_DIR").unwrap();
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    cbindgen::generate(dir)
+        .expect("the C header to generate")
+        .write_to_file(format!("{}/ufo_ipc.h", out));
+}
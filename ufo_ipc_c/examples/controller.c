@@ -0,0 +1,66 @@
+// A controller written against the C ABI, poking text into the subordinate in subordinate.c and
+// peeking it back.
+//
+//   cargo build -p ufo_ipc_c
+//   cc -I ufo_ipc_c/include ufo_ipc_c/examples/controller.c -o controller
+//       -L target/debug -l:libufo_ipc_c.a -lm
+//   ./controller ./subordinate
+
+#include <stdio.h>
+#include <string.h>
+
+#include "ufo_ipc.h"
+
+static UfoValue string(const char *s) {
+    UfoValue value = {.tag = UFO_VALUE_VSTRING};
+    value.vstring = (UfoBytes){(const uint8_t *)s, strlen(s)};
+    return value;
+}
+
+static int fail(const char *what) {
+    fprintf(stderr, "controller: %s: %s\n", what, ufo_last_error());
+    return 1;
+}
+
+int main(int argc, char **argv) {
+    if (argc < 2) {
+        fprintf(stderr, "usage: controller SUBORDINATE [ARGS..]\n");
+        return 2;
+    }
+    UfoController *controller =
+        ufo_controller_start(argv[1], (const char *const *)argv + 2, (size_t)argc - 2);
+    if (controller == NULL) {
+        return fail("start");
+    }
+
+    // a struct is its field names and values alternating
+    UfoValue fields[] = {string("text"), string("hello from C")};
+    UfoValue poked = {.tag = UFO_VALUE_STRUCT};
+    poked.struct_ = (UfoValues){fields, 2};
+    if (ufo_controller_poke(controller, "greeting", &poked, 1, NULL, 0, NULL) != UFO_OK) {
+        return fail("poke");
+    }
+
+    UfoResponse response;
+    if (ufo_controller_peek(controller, "greeting", NULL, 0, &response) != UFO_OK) {
+        return fail("peek");
+    }
+    for (size_t i = 0; i < response.values.len; i++) {
+        const UfoValue *value = &response.values.ptr[i];
+        if (value->tag == UFO_VALUE_VSTRING) {
+            printf("greeting: %s\n", (const char *)value->vstring.ptr);
+        }
+    }
+    ufo_response_free(&response);
+
+    // the subordinate only knows peek and poke
+    int status = ufo_controller_define_data(controller, fields, 1, NULL, 0, &response);
+    if (status == UFO_REFUSED) {
+        printf("define-data refused: %s\n", ufo_last_error());
+        ufo_response_free(&response);
+    }
+
+    int shutdown = ufo_controller_shutdown(controller, NULL, 0);
+    ufo_controller_free(controller);
+    return shutdown == UFO_OK ? 0 : fail("shutdown");
+}
@@ -0,0 +1,118 @@
+// A subordinate written against the C ABI, doing what src/child.rs does: poke a string into a
+// key, and peek it back, or Null for a key never poked.
+//
+//   cargo build -p ufo_ipc_c
+//   cc -I ufo_ipc_c/include ufo_ipc_c/examples/subordinate.c -o subordinate
+//       -L target/debug -l:libufo_ipc_c.a -lm
+//   ufo-ipc ./subordinate
+
+#define _POSIX_C_SOURCE 200809L
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "ufo_ipc.h"
+
+#define MAX_KEYS 64
+
+struct poked {
+    char *key;
+    char *text;
+};
+
+static struct poked poked[MAX_KEYS];
+static size_t poked_len;
+
+static struct poked *find(const char *key) {
+    for (size_t i = 0; i < poked_len; i++) {
+        if (strcmp(poked[i].key, key) == 0) {
+            return &poked[i];
+        }
+    }
+    return NULL;
+}
+
+static UfoValue string(const char *s) {
+    UfoValue value = {.tag = UFO_VALUE_VSTRING};
+    value.vstring = (UfoBytes){(const uint8_t *)s, strlen(s)};
+    return value;
+}
+
+// the text of a poke, which is a single string, NULL if it's anything else
+static const char *poked_text(const UfoValues *value) {
+    if (value->len != 1 || value->ptr[0].tag != UFO_VALUE_VSTRING) {
+        return NULL;
+    }
+    return (const char *)value->ptr[0].vstring.ptr;
+}
+
+static int respond(UfoSubordinate *subordinate, UfoRequest *request) {
+    const char *key = (const char *)request->bytes.ptr;
+    switch (request->command) {
+    case UFO_COMMAND_PEEK: {
+        struct poked *found = find(key);
+        UfoValue value = found ? string(found->text) : (UfoValue){.tag = UFO_VALUE_NULL};
+        UfoValue aux = string(key);
+        return ufo_subordinate_respond_to_peek(subordinate, &value, 1, &aux, 1);
+    }
+    case UFO_COMMAND_POKE: {
+        const char *text = poked_text(&request->values);
+        if (text == NULL) {
+            UfoValue aux = string("expected a single string");
+            return ufo_subordinate_respond_with_error(
+                subordinate, UFO_ERROR_TYPE_GENERIC_TYPE_ERROR, &aux, 1);
+        }
+        struct poked *found = find(key);
+        if (found == NULL) {
+            if (poked_len == MAX_KEYS) {
+                UfoValue aux = string("too many keys");
+                return ufo_subordinate_respond_with_error(
+                    subordinate, UFO_ERROR_TYPE_USERSPACE_EXCEPTION, &aux, 1);
+            }
+            found = &poked[poked_len++];
+            found->key = strdup(key);
+        } else {
+            free(found->text);
+        }
+        found->text = strdup(text);
+        UfoValue aux[] = {string(key), string(text)};
+        return ufo_subordinate_respond_to_poke(subordinate, aux, 2);
+    }
+    default:
+        return ufo_subordinate_respond_with_error(
+            subordinate, UFO_ERROR_TYPE_PROTOCOL_ERROR, NULL, 0);
+    }
+}
+
+int main(void) {
+    UfoSubordinate *subordinate = ufo_subordinate_begin();
+    if (subordinate == NULL) {
+        fprintf(stderr, "subordinate: %s\n", ufo_last_error());
+        return 1;
+    }
+
+    int status = 0;
+    for (;;) {
+        UfoRequest request;
+        if (ufo_subordinate_recv(subordinate, &request) != 0) {
+            fprintf(stderr, "subordinate: %s\n", ufo_last_error());
+            status = 1;
+            break;
+        }
+        if (request.command == UFO_COMMAND_SHUTDOWN) {
+            ufo_request_free(&request);
+            break;
+        }
+        int responded = respond(subordinate, &request);
+        ufo_request_free(&request);
+        if (responded != 0) {
+            fprintf(stderr, "subordinate: %s\n", ufo_last_error());
+            status = 1;
+            break;
+        }
+    }
+
+    ufo_subordinate_free(subordinate);
+    return status;
+}
@@ -0,0 +1,451 @@
+// The C ABI of ufo_ipc, see ufo_ipc_c/src/lib.rs.
+//
+// Functions returning int return UFO_OK on success and UFO_FAILED on failure, or UFO_REFUSED for
+// a request the subordinate refused. Functions returning a pointer return NULL on failure. After
+// a failure or refusal, ufo_last_error() describes it.
+//
+// Ownership: anything ufo_ipc hands to C, a UfoRequest or UfoResponse and everything it points
+// to, belongs to C until it is given back with its free function, except the file descriptors in
+// it, which are C's to close. Anything C hands to ufo_ipc, such as the values of a request or
+// response, is only borrowed for the duration of the call and copied where it needs to be kept.
+
+
+#ifndef UFO_IPC_H
+#define UFO_IPC_H
+
+// Generated by cbindgen from ufo_ipc_c, do not edit.
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Values, or for a map or struct, keys and values alternating, so `len` is twice the entries.
+// A struct's keys are its field names, as strings.
+typedef struct UfoValues {
+  const struct UfoValue *ptr;
+  size_t len;
+} UfoValues;
+
+
+#define UFO_OK 0
+
+#define UFO_FAILED -1
+
+// The subordinate refused the request, see `UfoResponse`.
+#define UFO_REFUSED 1
+
+typedef enum UfoCommand {
+  // The controller is saying goodbye. It isn't answered, free the subordinate and exit.
+  UFO_COMMAND_SHUTDOWN,
+  UFO_COMMAND_DEFINE_FUNCTION,
+  UFO_COMMAND_DEFINE_DATA,
+  UFO_COMMAND_CALL,
+  UFO_COMMAND_FREE_FUNCTION,
+  UFO_COMMAND_FREE_DATA,
+  UFO_COMMAND_PEEK,
+  UFO_COMMAND_POKE,
+} UfoCommand;
+
+// Why a subordinate refused a command, see `RemoteErrorType`.
+typedef enum UfoErrorType {
+  // Nothing was refused.
+  UFO_ERROR_TYPE_NONE,
+  UFO_ERROR_TYPE_USERSPACE_EXCEPTION,
+  UFO_ERROR_TYPE_PROTOCOL_ERROR,
+  UFO_ERROR_TYPE_GENERIC_TYPE_ERROR,
+} UfoErrorType;
+
+typedef enum UfoLogType {
+  UFO_LOG_TYPE_STDOUT,
+  UFO_LOG_TYPE_STDERR,
+} UfoLogType;
+
+// A controller and the subordinate it talks to, from `ufo_controller_start`.
+typedef struct UfoController UfoController;
+
+// A subordinate's end of the connection to its controller, from `ufo_subordinate_begin`.
+typedef struct UfoSubordinate UfoSubordinate;
+
+// Bytes or a string, which has to be UTF-8. Those made by ufo_ipc are followed by a NUL that
+// `len` doesn't count, so strings can be used as C strings.
+typedef struct UfoBytes {
+  const uint8_t *ptr;
+  size_t len;
+} UfoBytes;
+
+// A command from the controller, filled in by `ufo_subordinate_recv` and owned by the caller
+// until it is given to `ufo_request_free`. Fields a command doesn't use are zero or empty.
+typedef struct UfoRequest {
+  enum UfoCommand command;
+  // The function or data the command defines, calls or frees.
+  uint64_t token;
+  // The key of a peek or poke, or the blob of a function definition.
+  struct UfoBytes bytes;
+  // The arguments of a call, the value of a data definition or poke, or the data associated
+  // with a function definition.
+  UfoValues values;
+  UfoValues aux;
+} UfoRequest;
+
+// A packed array of one element type.
+typedef struct UfoArray_u8 {
+  const uint8_t *ptr;
+  size_t len;
+} UfoArray_u8;
+
+// A packed array of one element type.
+typedef struct UfoArray_i8 {
+  const int8_t *ptr;
+  size_t len;
+} UfoArray_i8;
+
+// A packed array of one element type.
+typedef struct UfoArray_u16 {
+  const uint16_t *ptr;
+  size_t len;
+} UfoArray_u16;
+
+// A packed array of one element type.
+typedef struct UfoArray_i16 {
+  const int16_t *ptr;
+  size_t len;
+} UfoArray_i16;
+
+// A packed array of one element type.
+typedef struct UfoArray_u32 {
+  const uint32_t *ptr;
+  size_t len;
+} UfoArray_u32;
+
+// A packed array of one element type.
+typedef struct UfoArray_i32 {
+  const int32_t *ptr;
+  size_t len;
+} UfoArray_i32;
+
+// A packed array of one element type.
+typedef struct UfoArray_u64 {
+  const uint64_t *ptr;
+  size_t len;
+} UfoArray_u64;
+
+// A packed array of one element type.
+typedef struct UfoArray_i64 {
+  const int64_t *ptr;
+  size_t len;
+} UfoArray_i64;
+
+// A packed array of one element type.
+typedef struct UfoArray_f32 {
+  const float *ptr;
+  size_t len;
+} UfoArray_f32;
+
+// A packed array of one element type.
+typedef struct UfoArray_f64 {
+  const double *ptr;
+  size_t len;
+} UfoArray_f64;
+
+// A `GenericValue`, with the same variants.
+typedef enum UfoValue_Tag {
+  UFO_VALUE_VU8,
+  UFO_VALUE_VI8,
+  UFO_VALUE_VU16,
+  UFO_VALUE_VI16,
+  UFO_VALUE_VU32,
+  UFO_VALUE_VI32,
+  UFO_VALUE_VU64,
+  UFO_VALUE_VI64,
+  UFO_VALUE_VF32,
+  UFO_VALUE_VF64,
+  UFO_VALUE_VUSIZE,
+  UFO_VALUE_VISIZE,
+  UFO_VALUE_VBOOL,
+  UFO_VALUE_VSTRING,
+  UFO_VALUE_VBYTES,
+  UFO_VALUE_TOKEN,
+  UFO_VALUE_FD,
+  UFO_VALUE_LIST,
+  UFO_VALUE_MAP,
+  UFO_VALUE_TUPLE,
+  UFO_VALUE_STRUCT,
+  UFO_VALUE_VU8S,
+  UFO_VALUE_VI8S,
+  UFO_VALUE_VU16S,
+  UFO_VALUE_VI16S,
+  UFO_VALUE_VU32S,
+  UFO_VALUE_VI32S,
+  UFO_VALUE_VU64S,
+  UFO_VALUE_VI64S,
+  UFO_VALUE_VF32S,
+  UFO_VALUE_VF64S,
+  UFO_VALUE_NULL,
+  UFO_VALUE_FUNCTION,
+  UFO_VALUE_MARKER,
+} UfoValue_Tag;
+
+typedef struct UfoValue {
+  UfoValue_Tag tag;
+  union {
+    struct {
+      uint8_t vu8;
+    };
+    struct {
+      int8_t vi8;
+    };
+    struct {
+      uint16_t vu16;
+    };
+    struct {
+      int16_t vi16;
+    };
+    struct {
+      uint32_t vu32;
+    };
+    struct {
+      int32_t vi32;
+    };
+    struct {
+      uint64_t vu64;
+    };
+    struct {
+      int64_t vi64;
+    };
+    struct {
+      float vf32;
+    };
+    struct {
+      double vf64;
+    };
+    struct {
+      size_t vusize;
+    };
+    struct {
+      ptrdiff_t visize;
+    };
+    struct {
+      bool vbool;
+    };
+    struct {
+      struct UfoBytes vstring;
+    };
+    struct {
+      struct UfoBytes vbytes;
+    };
+    struct {
+      uint64_t token;
+    };
+    struct {
+      int fd;
+    };
+    struct {
+      UfoValues list;
+    };
+    struct {
+      UfoValues map;
+    };
+    struct {
+      UfoValues tuple;
+    };
+    struct {
+      UfoValues struct_;
+    };
+    struct {
+      struct UfoArray_u8 vu8s;
+    };
+    struct {
+      struct UfoArray_i8 vi8s;
+    };
+    struct {
+      struct UfoArray_u16 vu16s;
+    };
+    struct {
+      struct UfoArray_i16 vi16s;
+    };
+    struct {
+      struct UfoArray_u32 vu32s;
+    };
+    struct {
+      struct UfoArray_i32 vi32s;
+    };
+    struct {
+      struct UfoArray_u64 vu64s;
+    };
+    struct {
+      struct UfoArray_i64 vi64s;
+    };
+    struct {
+      struct UfoArray_f32 vf32s;
+    };
+    struct {
+      struct UfoArray_f64 vf64s;
+    };
+    struct {
+      uint64_t function;
+    };
+    struct {
+      uint8_t marker;
+    };
+  };
+} UfoValue;
+
+typedef struct UfoLog {
+  enum UfoLogType log_type;
+  struct UfoBytes line;
+} UfoLog;
+
+typedef struct UfoLogs {
+  const struct UfoLog *ptr;
+  size_t len;
+} UfoLogs;
+
+// What the subordinate answered, filled in by each request and owned by the caller until it is
+// given to `ufo_response_free`. Fields a request doesn't use are zero or empty.
+typedef struct UfoResponse {
+  // The token of a definition, to call or free it by.
+  uint64_t token;
+  // What a call returned or a peek found.
+  UfoValues values;
+  UfoValues aux;
+  struct UfoLogs logs;
+  // Why the request was refused when it returned `UFO_REFUSED`, `None` otherwise.
+  enum UfoErrorType error_type;
+} UfoResponse;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Why the last function that failed or was refused on this thread did, or NULL if none has. The
+// string stays valid until the next failure on the same thread.
+const char *ufo_last_error(void);
+
+// Connect to the controller that started this process, as `subordinate_begin` does, and greet
+// it. Free the subordinate with `ufo_subordinate_free`.
+struct UfoSubordinate *ufo_subordinate_begin(void);
+
+// Close the connection and free the subordinate. NULL is ignored.
+void ufo_subordinate_free(struct UfoSubordinate *subordinate);
+
+// Wait for the next command and fill in `request` with it. Every command but a shutdown has to
+// be answered with one of the respond functions before the next is received.
+int ufo_subordinate_recv(struct UfoSubordinate *subordinate, struct UfoRequest *request);
+
+// Free everything `ufo_subordinate_recv` allocated for `request`, but not the request itself,
+// which is the caller's, nor the file descriptors in it. Freeing it twice is harmless.
+void ufo_request_free(struct UfoRequest *request);
+
+int ufo_subordinate_respond_to_define(struct UfoSubordinate *subordinate,
+                                      const struct UfoValue *aux,
+                                      size_t aux_len);
+
+int ufo_subordinate_respond_to_call(struct UfoSubordinate *subordinate,
+                                    const struct UfoValue *values,
+                                    size_t values_len,
+                                    const struct UfoValue *aux,
+                                    size_t aux_len);
+
+// Answer a `FreeFunction` or `FreeData`.
+int ufo_subordinate_respond_to_unregister(struct UfoSubordinate *subordinate,
+                                          const struct UfoValue *aux,
+                                          size_t aux_len);
+
+int ufo_subordinate_respond_to_peek(struct UfoSubordinate *subordinate,
+                                    const struct UfoValue *values,
+                                    size_t values_len,
+                                    const struct UfoValue *aux,
+                                    size_t aux_len);
+
+int ufo_subordinate_respond_to_poke(struct UfoSubordinate *subordinate,
+                                    const struct UfoValue *aux,
+                                    size_t aux_len);
+
+// Refuse the command instead of answering it, with `aux` saying why. `error_type` can't be
+// `None`.
+int ufo_subordinate_respond_with_error(struct UfoSubordinate *subordinate,
+                                       enum UfoErrorType error_type,
+                                       const struct UfoValue *aux,
+                                       size_t aux_len);
+
+// Start `program` with `args` as a subordinate and greet it, as `start_subordinate_process`
+// does. Shut it down with `ufo_controller_shutdown` and free the controller with
+// `ufo_controller_free`.
+struct UfoController *ufo_controller_start(const char *program,
+                                           const char *const *args,
+                                           size_t args_len);
+
+// Close the connection and free the controller. A subordinate that wasn't shut down sees the
+// connection close. NULL is ignored.
+void ufo_controller_free(struct UfoController *controller);
+
+// Free everything a request allocated for `response`, but not the response itself, which is the
+// caller's, nor the file descriptors in it. Freeing it twice is harmless.
+void ufo_response_free(struct UfoResponse *response);
+
+// Say goodbye to the subordinate, which isn't answered. Free the controller after.
+int ufo_controller_shutdown(struct UfoController *controller,
+                            const struct UfoValue *aux,
+                            size_t aux_len);
+
+// The function's token is left in `response`.
+int ufo_controller_define_function(struct UfoController *controller,
+                                   const uint8_t *function_blob,
+                                   size_t function_blob_len,
+                                   const struct UfoValue *associated_data,
+                                   size_t associated_data_len,
+                                   const struct UfoValue *aux,
+                                   size_t aux_len,
+                                   struct UfoResponse *response);
+
+// What the function returned is left in `response`.
+int ufo_controller_call_function(struct UfoController *controller,
+                                 uint64_t token,
+                                 const struct UfoValue *args,
+                                 size_t args_len,
+                                 const struct UfoValue *aux,
+                                 size_t aux_len,
+                                 struct UfoResponse *response);
+
+int ufo_controller_free_function(struct UfoController *controller,
+                                 uint64_t token,
+                                 const struct UfoValue *aux,
+                                 size_t aux_len,
+                                 struct UfoResponse *response);
+
+// The data's token is left in `response`.
+int ufo_controller_define_data(struct UfoController *controller,
+                               const struct UfoValue *value,
+                               size_t value_len,
+                               const struct UfoValue *aux,
+                               size_t aux_len,
+                               struct UfoResponse *response);
+
+int ufo_controller_free_data(struct UfoController *controller,
+                             uint64_t token,
+                             const struct UfoValue *aux,
+                             size_t aux_len,
+                             struct UfoResponse *response);
+
+// The values under `key` are left in `response`.
+int ufo_controller_peek(struct UfoController *controller,
+                        const char *key,
+                        const struct UfoValue *aux,
+                        size_t aux_len,
+                        struct UfoResponse *response);
+
+int ufo_controller_poke(struct UfoController *controller,
+                        const char *key,
+                        const struct UfoValue *value,
+                        size_t value_len,
+                        const struct UfoValue *aux,
+                        size_t aux_len,
+                        struct UfoResponse *response);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* UFO_IPC_H */
@@ -0,0 +1,395 @@
+use std::{
+    ffi::{c_char, c_int, CStr, OsStr},
+    io,
+    os::unix::ffi::OsStrExt,
+    process::Command,
+    ptr, slice,
+};
+
+use ufo_ipc::{
+    AsRefGenerics, ControllerProcess, DataToken, FunctionToken, GenericValueBoxed, LogEntry,
+    LogType, RemoteError, Response, StartSubordinateProcess,
+};
+
+use crate::{
+    guarded, invalid, remember, status, values::*, UfoErrorType, UFO_FAILED, UFO_OK, UFO_REFUSED,
+};
+
+/// A controller and the subordinate it talks to, from `ufo_controller_start`.
+pub struct UfoController(ControllerProcess);
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UfoLogType {
+    Stdout,
+    Stderr,
+}
+
+#[repr(C)]
+pub struct UfoLog {
+    pub log_type: UfoLogType,
+    pub line: UfoBytes,
+}
+
+#[repr(C)]
+pub struct UfoLogs {
+    pub ptr: *const UfoLog,
+    pub len: usize,
+}
+
+/// What the subordinate answered, filled in by each request and owned by the caller until it is
+/// given to `ufo_response_free`. Fields a request doesn't use are zero or empty.
+#[repr(C)]
+pub struct UfoResponse {
+    /// The token of a definition, to call or free it by.
+    pub token: u64,
+    /// What a call returned or a peek found.
+    pub values: UfoValues,
+    pub aux: UfoValues,
+    pub logs: UfoLogs,
+    /// Why the request was refused when it returned `UFO_REFUSED`, `None` otherwise.
+    pub error_type: UfoErrorType,
+}
+
+impl UfoResponse {
+    fn new(
+        token: u64,
+        values: Vec<GenericValueBoxed>,
+        aux: Vec<GenericValueBoxed>,
+        logs: Vec<LogEntry>,
+        error_type: UfoErrorType,
+    ) -> Self {
+        let logs = logs
+            .into_iter()
+            .map(|log| UfoLog {
+                log_type: match log.log_type() {
+                    LogType::Stdout => UfoLogType::Stdout,
+                    LogType::Stderr => UfoLogType::Stderr,
+                },
+                line: bytes_to_c(log.line().as_bytes().to_vec()),
+            })
+            .collect();
+        let (ptr, len) = leak(logs);
+        UfoResponse {
+            token,
+            values: values_to_c(values),
+            aux: values_to_c(aux),
+            logs: UfoLogs { ptr, len },
+            error_type,
+        }
+    }
+}
+
+// `value` picks the token or values out of what the request returned
+fn answered<T, F>(response: Response<T>, value: F) -> UfoResponse
+where
+    F: FnOnce(T) -> (u64, Vec<GenericValueBoxed>),
+{
+    let (token, values) = value(response.value);
+    let (aux, logs) = (response.response_aux, response.logs);
+    UfoResponse::new(token, values, aux, logs, UfoErrorType::None)
+}
+
+fn refused(e: RemoteError) -> UfoResponse {
+    UfoResponse::new(0, vec![], e.aux, e.logs, e.err_type.into())
+}
+
+fn endpoint<'a>(controller: *mut UfoController) -> io::Result<&'a mut ControllerProcess> {
+    match unsafe { controller.as_mut() } {
+        Some(controller) => Ok(&mut controller.0),
+        None => Err(invalid("a NULL controller")),
+    }
+}
+
+unsafe fn c_str<'a>(s: *const c_char) -> io::Result<&'a CStr> {
+    match s.is_null() {
+        true => Err(invalid("a NULL string")),
+        false => Ok(CStr::from_ptr(s)),
+    }
+}
+
+unsafe fn utf8<'a>(s: *const c_char) -> io::Result<&'a str> {
+    c_str(s)?
+        .to_str()
+        .map_err(|_| invalid("a key that isn't UTF-8"))
+}
+
+// Send one request and hand C the answer, or the refusal, in `response`, or throw it away if
+// `response` is NULL. The values and aux of a request are copied before they are sent.
+unsafe fn exchange<F>(
+    controller: *mut UfoController,
+    response: *mut UfoResponse,
+    request: F,
+) -> c_int
+where
+    F: FnOnce(&mut ControllerProcess) -> io::Result<UfoResponse>,
+{
+    let answer = guarded(|| match request(endpoint(controller)?) {
+        Ok(answer) => Ok((UFO_OK, answer)),
+        Err(e) if e.get_ref().is_some_and(|e| e.is::<RemoteError>()) => {
+            remember(e.to_string());
+            let e = e.into_inner().and_then(|e| e.downcast::<RemoteError>().ok());
+            Ok((UFO_REFUSED, refused(*e.expect("a refusal"))))
+        }
+        Err(e) => Err(e),
+    });
+    match answer {
+        Some((status, mut answer)) => {
+            match response.is_null() {
+                true => free_response(&mut answer),
+                false => response.write(answer),
+            }
+            status
+        }
+        None => UFO_FAILED,
+    }
+}
+
+/// Start `program` with `args` as a subordinate and greet it, as `start_subordinate_process`
+/// does. Shut it down with `ufo_controller_shutdown` and free the controller with
+/// `ufo_controller_free`.
+#[no_mangle]
+pub unsafe extern "C" fn ufo_controller_start(
+    program: *const c_char,
+    args: *const *const c_char,
+    args_len: usize,
+) -> *mut UfoController {
+    let started = guarded(|| {
+        let mut command = Command::new(OsStr::from_bytes(c_str(program)?.to_bytes()));
+        for &arg in borrowed(args, args_len) {
+            command.arg(OsStr::from_bytes(c_str(arg)?.to_bytes()));
+        }
+        command.start_subordinate_process()
+    });
+    match started {
+        Some(controller) => Box::into_raw(Box::new(UfoController(controller))),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Close the connection and free the controller. A subordinate that wasn't shut down sees the
+/// connection close. NULL is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn ufo_controller_free(controller: *mut UfoController) {
+    if !controller.is_null() {
+        drop(Box::from_raw(controller));
+    }
+}
+
+/// Free everything a request allocated for `response`, but not the response itself, which is the
+/// caller's, nor the file descriptors in it. Freeing it twice is harmless.
+#[no_mangle]
+pub unsafe extern "C" fn ufo_response_free(response: *mut UfoResponse) {
+    if let Some(response) = response.as_mut() {
+        free_response(response);
+    }
+}
+
+unsafe fn free_response(response: &mut UfoResponse) {
+    free_values(&mut response.values);
+    free_values(&mut response.aux);
+    let logs = &mut response.logs;
+    if !logs.ptr.is_null() {
+        for log in slice::from_raw_parts_mut(logs.ptr as *mut UfoLog, logs.len) {
+            free_bytes(&mut log.line);
+        }
+    }
+    unleak(logs.ptr, logs.len);
+    logs.ptr = ptr::null();
+    logs.len = 0;
+}
+
+/// Say goodbye to the subordinate, which isn't answered. Free the controller after.
+#[no_mangle]
+pub unsafe extern "C" fn ufo_controller_shutdown(
+    controller: *mut UfoController,
+    aux: *const UfoValue,
+    aux_len: usize,
+) -> c_int {
+    status(guarded(|| {
+        let aux = values_from_c(aux, aux_len)?;
+        endpoint(controller)?.shutdown(&aux.as_refs())
+    }))
+}
+
+/// The function's token is left in `response`.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn ufo_controller_define_function(
+    controller: *mut UfoController,
+    function_blob: *const u8,
+    function_blob_len: usize,
+    associated_data: *const UfoValue,
+    associated_data_len: usize,
+    aux: *const UfoValue,
+    aux_len: usize,
+    response: *mut UfoResponse,
+) -> c_int {
+    exchange(controller, response, |c| {
+        let blob = borrowed(function_blob, function_blob_len);
+        let data = values_from_c(associated_data, associated_data_len)?;
+        let aux = values_from_c(aux, aux_len)?;
+        let defined = c.define_function(blob, &data.as_refs(), &aux.as_refs())?;
+        Ok(answered(defined, |token| (token.0, vec![])))
+    })
+}
+
+/// What the function returned is left in `response`.
+#[no_mangle]
+pub unsafe extern "C" fn ufo_controller_call_function(
+    controller: *mut UfoController,
+    token: u64,
+    args: *const UfoValue,
+    args_len: usize,
+    aux: *const UfoValue,
+    aux_len: usize,
+    response: *mut UfoResponse,
+) -> c_int {
+    exchange(controller, response, |c| {
+        let args = values_from_c(args, args_len)?;
+        let aux = values_from_c(aux, aux_len)?;
+        let called = c.call_function(&FunctionToken(token), &args.as_refs(), &aux.as_refs())?;
+        Ok(answered(called, |values| (0, values)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ufo_controller_free_function(
+    controller: *mut UfoController,
+    token: u64,
+    aux: *const UfoValue,
+    aux_len: usize,
+    response: *mut UfoResponse,
+) -> c_int {
+    exchange(controller, response, |c| {
+        let aux = values_from_c(aux, aux_len)?;
+        let freed = c.free_function(&FunctionToken(token), &aux.as_refs())?;
+        Ok(answered(freed, |()| (0, vec![])))
+    })
+}
+
+/// The data's token is left in `response`.
+#[no_mangle]
+pub unsafe extern "C" fn ufo_controller_define_data(
+    controller: *mut UfoController,
+    value: *const UfoValue,
+    value_len: usize,
+    aux: *const UfoValue,
+    aux_len: usize,
+    response: *mut UfoResponse,
+) -> c_int {
+    exchange(controller, response, |c| {
+        let value = values_from_c(value, value_len)?;
+        let aux = values_from_c(aux, aux_len)?;
+        let defined = c.define_data(&value.as_refs(), &aux.as_refs())?;
+        Ok(answered(defined, |token| (token.0, vec![])))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ufo_controller_free_data(
+    controller: *mut UfoController,
+    token: u64,
+    aux: *const UfoValue,
+    aux_len: usize,
+    response: *mut UfoResponse,
+) -> c_int {
+    exchange(controller, response, |c| {
+        let aux = values_from_c(aux, aux_len)?;
+        let freed = c.free_data(&DataToken(token), &aux.as_refs())?;
+        Ok(answered(freed, |()| (0, vec![])))
+    })
+}
+
+/// The values under `key` are left in `response`.
+#[no_mangle]
+pub unsafe extern "C" fn ufo_controller_peek(
+    controller: *mut UfoController,
+    key: *const c_char,
+    aux: *const UfoValue,
+    aux_len: usize,
+    response: *mut UfoResponse,
+) -> c_int {
+    exchange(controller, response, |c| {
+        let aux = values_from_c(aux, aux_len)?;
+        let peeked = c.peek(utf8(key)?, &aux.as_refs())?;
+        Ok(answered(peeked, |values| (0, values)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ufo_controller_poke(
+    controller: *mut UfoController,
+    key: *const c_char,
+    value: *const UfoValue,
+    value_len: usize,
+    aux: *const UfoValue,
+    aux_len: usize,
+    response: *mut UfoResponse,
+) -> c_int {
+    exchange(controller, response, |c| {
+        let value = values_from_c(value, value_len)?;
+        let aux = values_from_c(aux, aux_len)?;
+        let poked = c.poke(utf8(key)?, &value.as_refs(), &aux.as_refs())?;
+        Ok(answered(poked, |()| (0, vec![])))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+
+    use ufo_ipc::*;
+
+    use super::*;
+
+    #[test]
+    fn requests_and_refusals_cross_the_abi() {
+        let controller = MockSubordinate::new()
+            .expect_define_data(vec![GenericValue::Vstring("x".to_string())])
+            .expect_call(FunctionToken(5), vec![GenericValue::Vu8(1)])
+            .respond(vec![GenericValue::Vbool(true)])
+            .expect_call(FunctionToken(5), vec![])
+            .fail(RemoteErrorType::GenericTypeError)
+            .expect_free_data(DataToken(1))
+            .start()
+            .unwrap();
+        let controller = Box::into_raw(Box::new(UfoController(controller)));
+        unsafe {
+            let mut response = mem::zeroed::<UfoResponse>();
+            let x = UfoValue::Vstring(UfoBytes { ptr: b"x".as_ptr(), len: 1 });
+            let status =
+                ufo_controller_define_data(controller, &x, 1, ptr::null(), 0, &mut response);
+            assert_eq!((status, response.token), (UFO_OK, 1));
+            assert_eq!(response.error_type, UfoErrorType::None);
+            ufo_response_free(&mut response);
+
+            let one = UfoValue::Vu8(1);
+            let status =
+                ufo_controller_call_function(controller, 5, &one, 1, ptr::null(), 0, &mut response);
+            assert_eq!(status, UFO_OK);
+            let returned = values_from_c(response.values.ptr, response.values.len).unwrap();
+            assert_eq!(returned.as_refs(), [GenericValueRef::Vbool(true)]);
+            ufo_response_free(&mut response);
+
+            let (none, none_len) = (ptr::null(), 0);
+            let status = ufo_controller_call_function(
+                controller,
+                5,
+                none,
+                none_len,
+                none,
+                none_len,
+                &mut response,
+            );
+            assert_eq!(status, UFO_REFUSED);
+            assert_eq!(response.error_type, UfoErrorType::GenericTypeError);
+            ufo_response_free(&mut response);
+
+            let status = ufo_controller_free_data(controller, 1, ptr::null(), 0, ptr::null_mut());
+            assert_eq!(status, UFO_OK);
+            assert_eq!(ufo_controller_shutdown(controller, ptr::null(), 0), UFO_OK);
+            ufo_controller_free(controller);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+// The C ABI of ufo_ipc, built as a shared and a static library with the header in include/.
+//
+// Every function catches failures, errors and panics alike, so nothing unwinds into C: it returns
+// `UFO_FAILED` or NULL and leaves a description for `ufo_last_error`. Pointers passed in must be
+// valid for what the function does with them; NULL is only allowed where an array is empty.
+
+#![allow(clippy::missing_safety_doc)]
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, CString},
+    io,
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr,
+};
+
+use ufo_ipc::RemoteErrorType;
+
+mod values;
+pub use values::*;
+
+mod subordinate;
+pub use subordinate::*;
+
+mod controller;
+pub use controller::*;
+
+pub const UFO_OK: c_int = 0;
+pub const UFO_FAILED: c_int = -1;
+/// The subordinate refused the request, see `UfoResponse`.
+pub const UFO_REFUSED: c_int = 1;
+
+/// Why a subordinate refused a command, see `RemoteErrorType`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UfoErrorType {
+    /// Nothing was refused.
+    None,
+    UserspaceException,
+    ProtocolError,
+    GenericTypeError,
+}
+
+impl TryFrom<UfoErrorType> for RemoteErrorType {
+    type Error = io::Error;
+
+    fn try_from(e: UfoErrorType) -> io::Result<Self> {
+        match e {
+            UfoErrorType::None => Err(invalid("a refusal without a reason")),
+            UfoErrorType::UserspaceException => Ok(RemoteErrorType::UserspaceException),
+            UfoErrorType::ProtocolError => Ok(RemoteErrorType::ProtocolError),
+            UfoErrorType::GenericTypeError => Ok(RemoteErrorType::GenericTypeError),
+        }
+    }
+}
+
+impl From<RemoteErrorType> for UfoErrorType {
+    fn from(e: RemoteErrorType) -> Self {
+        match e {
+            RemoteErrorType::UserspaceException => UfoErrorType::UserspaceException,
+            RemoteErrorType::ProtocolError => UfoErrorType::ProtocolError,
+            RemoteErrorType::GenericTypeError => UfoErrorType::GenericTypeError,
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn remember(message: String) {
+    let message = CString::new(message.replace('\0', "\\0")).expect("no NULs left");
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+// run `f` for C, remembering why it failed if it did
+fn guarded<T>(f: impl FnOnce() -> io::Result<T>) -> Option<T> {
+    let message = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => return Some(value),
+        Ok(Err(e)) => e.to_string(),
+        Err(panic) => match panic.downcast_ref::<&str>() {
+            Some(message) => format!("panicked: {}", message),
+            None => match panic.downcast_ref::<String>() {
+                Some(message) => format!("panicked: {}", message),
+                None => "panicked".to_string(),
+            },
+        },
+    };
+    remember(message);
+    None
+}
+
+fn status(result: Option<()>) -> c_int {
+    match result {
+        Some(()) => UFO_OK,
+        None => UFO_FAILED,
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Why the last function that failed or was refused on this thread did, or NULL if none has. The
+/// string stays valid until the next failure on the same thread.
+#[no_mangle]
+pub extern "C" fn ufo_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| match &*last.borrow() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    const GENERATED: &str = concat!(env!("OUT_DIR"), "/ufo_ipc.h");
+
+    #[test]
+    fn the_checked_in_header_matches_the_crate() {
+        assert!(
+            include_str!(concat!(env!("OUT_DIR"), "/ufo_ipc.h"))
+                == include_str!("../include/ufo_ipc.h"),
+            "include/ufo_ipc.h is out of date, copy {} over it",
+            GENERATED
+        );
+    }
+}
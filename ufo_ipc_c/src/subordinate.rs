@@ -0,0 +1,293 @@
+use std::{ffi::c_int, io, ptr};
+
+use ufo_ipc::{
+    subordinate_begin, AsRefGenerics, GenericValueBoxed, GenericValueRef, ProtocolCommand,
+    SubordinateProcess,
+};
+
+use crate::{guarded, invalid, status, values::*, UfoErrorType};
+
+/// A subordinate's end of the connection to its controller, from `ufo_subordinate_begin`.
+pub struct UfoSubordinate(SubordinateProcess);
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UfoCommand {
+    /// The controller is saying goodbye. It isn't answered, free the subordinate and exit.
+    Shutdown,
+    DefineFunction,
+    DefineData,
+    Call,
+    FreeFunction,
+    FreeData,
+    Peek,
+    Poke,
+}
+
+/// A command from the controller, filled in by `ufo_subordinate_recv` and owned by the caller
+/// until it is given to `ufo_request_free`. Fields a command doesn't use are zero or empty.
+#[repr(C)]
+pub struct UfoRequest {
+    pub command: UfoCommand,
+    /// The function or data the command defines, calls or frees.
+    pub token: u64,
+    /// The key of a peek or poke, or the blob of a function definition.
+    pub bytes: UfoBytes,
+    /// The arguments of a call, the value of a data definition or poke, or the data associated
+    /// with a function definition.
+    pub values: UfoValues,
+    pub aux: UfoValues,
+}
+
+impl UfoRequest {
+    fn new(
+        command: UfoCommand,
+        token: u64,
+        bytes: Vec<u8>,
+        values: Vec<GenericValueBoxed>,
+        aux: Vec<GenericValueBoxed>,
+    ) -> Self {
+        UfoRequest {
+            command,
+            token,
+            bytes: bytes_to_c(bytes),
+            values: values_to_c(values),
+            aux: values_to_c(aux),
+        }
+    }
+}
+
+fn endpoint<'a>(subordinate: *mut UfoSubordinate) -> io::Result<&'a mut SubordinateProcess> {
+    match unsafe { subordinate.as_mut() } {
+        Some(subordinate) => Ok(&mut subordinate.0),
+        None => Err(invalid("a NULL subordinate")),
+    }
+}
+
+/// Connect to the controller that started this process, as `subordinate_begin` does, and greet
+/// it. Free the subordinate with `ufo_subordinate_free`.
+#[no_mangle]
+pub extern "C" fn ufo_subordinate_begin() -> *mut UfoSubordinate {
+    match guarded(subordinate_begin) {
+        Some(subordinate) => Box::into_raw(Box::new(UfoSubordinate(subordinate))),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Close the connection and free the subordinate. NULL is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn ufo_subordinate_free(subordinate: *mut UfoSubordinate) {
+    if !subordinate.is_null() {
+        drop(Box::from_raw(subordinate));
+    }
+}
+
+/// Wait for the next command and fill in `request` with it. Every command but a shutdown has to
+/// be answered with one of the respond functions before the next is received.
+#[no_mangle]
+pub unsafe extern "C" fn ufo_subordinate_recv(
+    subordinate: *mut UfoSubordinate,
+    request: *mut UfoRequest,
+) -> c_int {
+    status(guarded(|| {
+        if request.is_null() {
+            return Err(invalid("a NULL request"));
+        }
+        let received = endpoint(subordinate)?.recv_command()?;
+        let aux = received.aux;
+        let filled = match received.command {
+            ProtocolCommand::Shutdown => {
+                UfoRequest::new(UfoCommand::Shutdown, 0, vec![], vec![], aux)
+            }
+            ProtocolCommand::DefineFunction {
+                token,
+                function_blob,
+                associated_data,
+            } => UfoRequest::new(
+                UfoCommand::DefineFunction,
+                token.0,
+                function_blob,
+                associated_data,
+                aux,
+            ),
+            ProtocolCommand::DefineData { token, value } => {
+                UfoRequest::new(UfoCommand::DefineData, token.0, vec![], value, aux)
+            }
+            ProtocolCommand::Call { token, args } => {
+                UfoRequest::new(UfoCommand::Call, token.0, vec![], args, aux)
+            }
+            ProtocolCommand::FreeFunction(token) => {
+                UfoRequest::new(UfoCommand::FreeFunction, token.0, vec![], vec![], aux)
+            }
+            ProtocolCommand::FreeData(token) => {
+                UfoRequest::new(UfoCommand::FreeData, token.0, vec![], vec![], aux)
+            }
+            ProtocolCommand::Peek(key) => {
+                UfoRequest::new(UfoCommand::Peek, 0, key.into_bytes(), vec![], aux)
+            }
+            ProtocolCommand::Poke { key, value } => {
+                UfoRequest::new(UfoCommand::Poke, 0, key.into_bytes(), value, aux)
+            }
+        };
+        request.write(filled);
+        Ok(())
+    }))
+}
+
+/// Free everything `ufo_subordinate_recv` allocated for `request`, but not the request itself,
+/// which is the caller's, nor the file descriptors in it. Freeing it twice is harmless.
+#[no_mangle]
+pub unsafe extern "C" fn ufo_request_free(request: *mut UfoRequest) {
+    if let Some(request) = request.as_mut() {
+        free_bytes(&mut request.bytes);
+        free_values(&mut request.values);
+        free_values(&mut request.aux);
+    }
+}
+
+// the values and aux of a response are only read, and copied before they are sent
+unsafe fn respond<F>(
+    subordinate: *mut UfoSubordinate,
+    values: (*const UfoValue, usize),
+    aux: (*const UfoValue, usize),
+    respond: F,
+) -> c_int
+where
+    F: FnOnce(&mut SubordinateProcess, &[GenericValueRef], &[GenericValueRef]) -> io::Result<()>,
+{
+    status(guarded(|| {
+        let values = values_from_c(values.0, values.1)?;
+        let aux = values_from_c(aux.0, aux.1)?;
+        respond(endpoint(subordinate)?, &values.as_refs(), &aux.as_refs())
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ufo_subordinate_respond_to_define(
+    subordinate: *mut UfoSubordinate,
+    aux: *const UfoValue,
+    aux_len: usize,
+) -> c_int {
+    respond(subordinate, (ptr::null(), 0), (aux, aux_len), |s, _, aux| s.respond_to_define(aux))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ufo_subordinate_respond_to_call(
+    subordinate: *mut UfoSubordinate,
+    values: *const UfoValue,
+    values_len: usize,
+    aux: *const UfoValue,
+    aux_len: usize,
+) -> c_int {
+    respond(subordinate, (values, values_len), (aux, aux_len), |s, values, aux| {
+        s.respond_to_call(values, aux)
+    })
+}
+
+/// Answer a `FreeFunction` or `FreeData`.
+#[no_mangle]
+pub unsafe extern "C" fn ufo_subordinate_respond_to_unregister(
+    subordinate: *mut UfoSubordinate,
+    aux: *const UfoValue,
+    aux_len: usize,
+) -> c_int {
+    respond(subordinate, (ptr::null(), 0), (aux, aux_len), |s, _, aux| {
+        s.respond_to_unregister(aux)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ufo_subordinate_respond_to_peek(
+    subordinate: *mut UfoSubordinate,
+    values: *const UfoValue,
+    values_len: usize,
+    aux: *const UfoValue,
+    aux_len: usize,
+) -> c_int {
+    respond(subordinate, (values, values_len), (aux, aux_len), |s, values, aux| {
+        s.respond_to_peek(values, aux)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ufo_subordinate_respond_to_poke(
+    subordinate: *mut UfoSubordinate,
+    aux: *const UfoValue,
+    aux_len: usize,
+) -> c_int {
+    respond(subordinate, (ptr::null(), 0), (aux, aux_len), |s, _, aux| s.respond_to_poke(aux))
+}
+
+/// Refuse the command instead of answering it, with `aux` saying why. `error_type` can't be
+/// `None`.
+#[no_mangle]
+pub unsafe extern "C" fn ufo_subordinate_respond_with_error(
+    subordinate: *mut UfoSubordinate,
+    error_type: UfoErrorType,
+    aux: *const UfoValue,
+    aux_len: usize,
+) -> c_int {
+    respond(subordinate, (ptr::null(), 0), (aux, aux_len), |s, _, aux| {
+        s.respond_with_error(error_type.try_into()?, aux)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{slice, thread};
+
+    use ufo_ipc::*;
+
+    use super::*;
+
+    #[test]
+    fn requests_and_responses_cross_the_abi() {
+        let path = std::env::temp_dir().join(format!("ufo_ipc_c-{}.sock", std::process::id()));
+        let listener = SubordinateListener::bind(&path).unwrap();
+        let controller = thread::spawn({
+            let path = path.clone();
+            move || {
+                let mut controller = ControllerProcess::connect(path).unwrap();
+                let numbers = GenericValueRef::Vu8s((&[1u8, 2][..]).into());
+                let fields = [(GenericValueRef::Vstring("a"), numbers)];
+                let response = controller
+                    .poke("k", &[GenericValueRef::Struct((&fields[..]).into())], &[])
+                    .unwrap();
+                assert_eq!(response.response_aux.as_refs(), [GenericValueRef::Vstring("k")]);
+                let refused = controller.call_function(&FunctionToken(7), &[], &[]).unwrap_err();
+                let refused = refused.get_ref().unwrap().downcast_ref::<RemoteError>().unwrap();
+                assert_eq!(refused.err_type, RemoteErrorType::UserspaceException);
+                controller.shutdown(&[]).unwrap();
+            }
+        });
+
+        let subordinate = Box::into_raw(Box::new(UfoSubordinate(listener.accept().unwrap())));
+        unsafe {
+            let mut request = std::mem::zeroed::<UfoRequest>();
+
+            assert_eq!(ufo_subordinate_recv(subordinate, &mut request), 0);
+            assert_eq!(request.command, UfoCommand::Poke);
+            assert_eq!(slice::from_raw_parts(request.bytes.ptr, 2), b"k\0");
+            let value = values_from_c(request.values.ptr, request.values.len).unwrap();
+            assert_eq!(format!("{}", value[0].as_ref()), "{a: [1, 2]}");
+            let key = UfoValue::Vstring(UfoBytes { ptr: b"k".as_ptr(), len: 1 });
+            assert_eq!(ufo_subordinate_respond_to_poke(subordinate, &key, 1), 0);
+            ufo_request_free(&mut request);
+            ufo_request_free(&mut request);
+
+            assert_eq!(ufo_subordinate_recv(subordinate, &mut request), 0);
+            assert_eq!((request.command, request.token), (UfoCommand::Call, 7));
+            let error = UfoErrorType::None;
+            assert_eq!(ufo_subordinate_respond_with_error(subordinate, error, ptr::null(), 0), -1);
+            let error = UfoErrorType::UserspaceException;
+            assert_eq!(ufo_subordinate_respond_with_error(subordinate, error, ptr::null(), 0), 0);
+            ufo_request_free(&mut request);
+
+            assert_eq!(ufo_subordinate_recv(subordinate, &mut request), 0);
+            assert_eq!(request.command, UfoCommand::Shutdown);
+            ufo_request_free(&mut request);
+            ufo_subordinate_free(subordinate);
+        }
+        controller.join().unwrap();
+    }
+}
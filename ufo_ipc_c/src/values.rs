@@ -0,0 +1,273 @@
+use std::{
+    ffi::c_int,
+    io,
+    os::unix::io::{BorrowedFd, IntoRawFd},
+    ptr, slice,
+};
+
+use ufo_ipc::{DataToken, FunctionToken, GenericValueBoxed};
+
+use crate::invalid;
+
+// Values cross in both directions as a tagged union mirroring `GenericValue`. Going to C they are
+// moved into allocations of ours, which C hands back to be freed. Coming from C they are copied,
+// so C's memory is only read during the call.
+
+/// Bytes or a string, which has to be UTF-8. Those made by ufo_ipc are followed by a NUL that
+/// `len` doesn't count, so strings can be used as C strings.
+#[repr(C)]
+pub struct UfoBytes {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+/// A packed array of one element type.
+#[repr(C)]
+pub struct UfoArray<T> {
+    pub ptr: *const T,
+    pub len: usize,
+}
+
+/// Values, or for a map or struct, keys and values alternating, so `len` is twice the entries.
+/// A struct's keys are its field names, as strings.
+#[repr(C)]
+pub struct UfoValues {
+    pub ptr: *const UfoValue,
+    pub len: usize,
+}
+
+/// A `GenericValue`, with the same variants.
+#[repr(C)]
+pub enum UfoValue {
+    Vu8(u8),
+    Vi8(i8),
+    Vu16(u16),
+    Vi16(i16),
+    Vu32(u32),
+    Vi32(i32),
+    Vu64(u64),
+    Vi64(i64),
+    Vf32(f32),
+    Vf64(f64),
+    Vusize(usize),
+    Visize(isize),
+    Vbool(bool),
+    Vstring(UfoBytes),
+    Vbytes(UfoBytes),
+    Token(u64),
+    Fd(c_int),
+    List(UfoValues),
+    Map(UfoValues),
+    Tuple(UfoValues),
+    Struct(UfoValues),
+    Vu8s(UfoArray<u8>),
+    Vi8s(UfoArray<i8>),
+    Vu16s(UfoArray<u16>),
+    Vi16s(UfoArray<i16>),
+    Vu32s(UfoArray<u32>),
+    Vi32s(UfoArray<i32>),
+    Vu64s(UfoArray<u64>),
+    Vi64s(UfoArray<i64>),
+    Vf32s(UfoArray<f32>),
+    Vf64s(UfoArray<f64>),
+    Null,
+    Function(u64),
+    Marker(u8),
+}
+
+pub(crate) fn leak<T>(v: Vec<T>) -> (*const T, usize) {
+    let len = v.len();
+    (Box::into_raw(v.into_boxed_slice()) as *const T, len)
+}
+
+// takes back what `leak` gave out, or nothing once it has been freed and nulled
+pub(crate) unsafe fn unleak<T>(ptr: *const T, len: usize) {
+    if !ptr.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ptr as *mut T, len)));
+    }
+}
+
+// C's idea of an empty array may well be NULL
+pub(crate) unsafe fn borrowed<'a, T>(ptr: *const T, len: usize) -> &'a [T] {
+    match len {
+        0 => &[],
+        len => slice::from_raw_parts(ptr, len),
+    }
+}
+
+fn array<T>(v: Vec<T>) -> UfoArray<T> {
+    let (ptr, len) = leak(v);
+    UfoArray { ptr, len }
+}
+
+pub(crate) fn bytes_to_c(mut v: Vec<u8>) -> UfoBytes {
+    v.push(0);
+    let (ptr, len) = leak(v);
+    UfoBytes { ptr, len: len - 1 }
+}
+
+pub(crate) fn values_to_c(values: Vec<GenericValueBoxed>) -> UfoValues {
+    let (ptr, len) = leak(values.into_iter().map(to_c).collect());
+    UfoValues { ptr, len }
+}
+
+fn entries_to_c(entries: Vec<(GenericValueBoxed, GenericValueBoxed)>) -> UfoValues {
+    values_to_c(entries.into_iter().flat_map(|(key, value)| [key, value]).collect())
+}
+
+// file descriptors are given up to C rather than closed with the value
+pub(crate) fn to_c(value: GenericValueBoxed) -> UfoValue {
+    match value {
+        GenericValueBoxed::Vu8(v) => UfoValue::Vu8(v),
+        GenericValueBoxed::Vi8(v) => UfoValue::Vi8(v),
+        GenericValueBoxed::Vu16(v) => UfoValue::Vu16(v),
+        GenericValueBoxed::Vi16(v) => UfoValue::Vi16(v),
+        GenericValueBoxed::Vu32(v) => UfoValue::Vu32(v),
+        GenericValueBoxed::Vi32(v) => UfoValue::Vi32(v),
+        GenericValueBoxed::Vu64(v) => UfoValue::Vu64(v),
+        GenericValueBoxed::Vi64(v) => UfoValue::Vi64(v),
+        GenericValueBoxed::Vf32(v) => UfoValue::Vf32(v),
+        GenericValueBoxed::Vf64(v) => UfoValue::Vf64(v),
+        GenericValueBoxed::Vusize(v) => UfoValue::Vusize(v),
+        GenericValueBoxed::Visize(v) => UfoValue::Visize(v),
+        GenericValueBoxed::Vbool(v) => UfoValue::Vbool(v),
+        GenericValueBoxed::Vstring(v) => UfoValue::Vstring(bytes_to_c(v.into_bytes())),
+        GenericValueBoxed::Vbytes(v) => UfoValue::Vbytes(bytes_to_c(v)),
+        GenericValueBoxed::Token(v) => UfoValue::Token(v.0),
+        GenericValueBoxed::Fd(v) => UfoValue::Fd(v.into_raw_fd()),
+        GenericValueBoxed::List(v) => UfoValue::List(values_to_c(v)),
+        GenericValueBoxed::Map(v) => UfoValue::Map(entries_to_c(v)),
+        GenericValueBoxed::Tuple(v) => UfoValue::Tuple(values_to_c(v)),
+        GenericValueBoxed::Struct(v) => UfoValue::Struct(entries_to_c(v)),
+        GenericValueBoxed::Vu8s(v) => UfoValue::Vu8s(array(v)),
+        GenericValueBoxed::Vi8s(v) => UfoValue::Vi8s(array(v)),
+        GenericValueBoxed::Vu16s(v) => UfoValue::Vu16s(array(v)),
+        GenericValueBoxed::Vi16s(v) => UfoValue::Vi16s(array(v)),
+        GenericValueBoxed::Vu32s(v) => UfoValue::Vu32s(array(v)),
+        GenericValueBoxed::Vi32s(v) => UfoValue::Vi32s(array(v)),
+        GenericValueBoxed::Vu64s(v) => UfoValue::Vu64s(array(v)),
+        GenericValueBoxed::Vi64s(v) => UfoValue::Vi64s(array(v)),
+        GenericValueBoxed::Vf32s(v) => UfoValue::Vf32s(array(v)),
+        GenericValueBoxed::Vf64s(v) => UfoValue::Vf64s(array(v)),
+        GenericValueBoxed::Null => UfoValue::Null,
+        GenericValueBoxed::Function(v) => UfoValue::Function(v.0),
+        GenericValueBoxed::Marker(v) => UfoValue::Marker(v),
+    }
+}
+
+// Freeing takes a value made by `to_c` and leaves it empty, so freeing twice is harmless.
+
+pub(crate) unsafe fn free_bytes(bytes: &mut UfoBytes) {
+    unleak(bytes.ptr, bytes.len + 1);
+    bytes.ptr = ptr::null();
+    bytes.len = 0;
+}
+
+pub(crate) unsafe fn free_values(values: &mut UfoValues) {
+    if !values.ptr.is_null() {
+        for value in slice::from_raw_parts_mut(values.ptr as *mut UfoValue, values.len) {
+            free_value(value);
+        }
+    }
+    unleak(values.ptr, values.len);
+    values.ptr = ptr::null();
+    values.len = 0;
+}
+
+unsafe fn free_array<T>(array: &mut UfoArray<T>) {
+    unleak(array.ptr, array.len);
+    array.ptr = ptr::null();
+    array.len = 0;
+}
+
+pub(crate) unsafe fn free_value(value: &mut UfoValue) {
+    match value {
+        UfoValue::Vstring(v) | UfoValue::Vbytes(v) => free_bytes(v),
+        UfoValue::List(v) | UfoValue::Tuple(v) | UfoValue::Map(v) | UfoValue::Struct(v) => {
+            free_values(v)
+        }
+        UfoValue::Vu8s(v) => free_array(v),
+        UfoValue::Vi8s(v) => free_array(v),
+        UfoValue::Vu16s(v) => free_array(v),
+        UfoValue::Vi16s(v) => free_array(v),
+        UfoValue::Vu32s(v) => free_array(v),
+        UfoValue::Vi32s(v) => free_array(v),
+        UfoValue::Vu64s(v) => free_array(v),
+        UfoValue::Vi64s(v) => free_array(v),
+        UfoValue::Vf32s(v) => free_array(v),
+        UfoValue::Vf64s(v) => free_array(v),
+        _ => {}
+    }
+    *value = UfoValue::Null;
+}
+
+pub(crate) unsafe fn string_from_c(bytes: &UfoBytes) -> io::Result<String> {
+    String::from_utf8(borrowed(bytes.ptr, bytes.len).to_vec())
+        .map_err(|_| invalid("a string that isn't UTF-8"))
+}
+
+pub(crate) unsafe fn values_from_c(
+    ptr: *const UfoValue,
+    len: usize,
+) -> io::Result<Vec<GenericValueBoxed>> {
+    borrowed(ptr, len).iter().map(|v| from_c(v)).collect()
+}
+
+unsafe fn entries_from_c(
+    entries: &UfoValues,
+) -> io::Result<Vec<(GenericValueBoxed, GenericValueBoxed)>> {
+    if !entries.len.is_multiple_of(2) {
+        return Err(invalid("a map with a key but no value"));
+    }
+    borrowed(entries.ptr, entries.len)
+        .chunks(2)
+        .map(|entry| Ok((from_c(&entry[0])?, from_c(&entry[1])?)))
+        .collect()
+}
+
+unsafe fn array_from_c<T: Copy>(array: &UfoArray<T>) -> Vec<T> {
+    borrowed(array.ptr, array.len).to_vec()
+}
+
+// file descriptors are duplicated, C keeps its own
+pub(crate) unsafe fn from_c(value: &UfoValue) -> io::Result<GenericValueBoxed> {
+    Ok(match value {
+        UfoValue::Vu8(v) => GenericValueBoxed::Vu8(*v),
+        UfoValue::Vi8(v) => GenericValueBoxed::Vi8(*v),
+        UfoValue::Vu16(v) => GenericValueBoxed::Vu16(*v),
+        UfoValue::Vi16(v) => GenericValueBoxed::Vi16(*v),
+        UfoValue::Vu32(v) => GenericValueBoxed::Vu32(*v),
+        UfoValue::Vi32(v) => GenericValueBoxed::Vi32(*v),
+        UfoValue::Vu64(v) => GenericValueBoxed::Vu64(*v),
+        UfoValue::Vi64(v) => GenericValueBoxed::Vi64(*v),
+        UfoValue::Vf32(v) => GenericValueBoxed::Vf32(*v),
+        UfoValue::Vf64(v) => GenericValueBoxed::Vf64(*v),
+        UfoValue::Vusize(v) => GenericValueBoxed::Vusize(*v),
+        UfoValue::Visize(v) => GenericValueBoxed::Visize(*v),
+        UfoValue::Vbool(v) => GenericValueBoxed::Vbool(*v),
+        UfoValue::Vstring(v) => GenericValueBoxed::Vstring(string_from_c(v)?),
+        UfoValue::Vbytes(v) => GenericValueBoxed::Vbytes(borrowed(v.ptr, v.len).to_vec()),
+        UfoValue::Token(v) => GenericValueBoxed::Token(DataToken(*v)),
+        UfoValue::Fd(fd) if *fd < 0 => return Err(invalid("a negative file descriptor")),
+        UfoValue::Fd(fd) => {
+            GenericValueBoxed::Fd(BorrowedFd::borrow_raw(*fd).try_clone_to_owned()?)
+        }
+        UfoValue::List(v) => GenericValueBoxed::List(values_from_c(v.ptr, v.len)?),
+        UfoValue::Map(v) => GenericValueBoxed::Map(entries_from_c(v)?),
+        UfoValue::Tuple(v) => GenericValueBoxed::Tuple(values_from_c(v.ptr, v.len)?),
+        UfoValue::Struct(v) => GenericValueBoxed::Struct(entries_from_c(v)?),
+        UfoValue::Vu8s(v) => GenericValueBoxed::Vu8s(array_from_c(v)),
+        UfoValue::Vi8s(v) => GenericValueBoxed::Vi8s(array_from_c(v)),
+        UfoValue::Vu16s(v) => GenericValueBoxed::Vu16s(array_from_c(v)),
+        UfoValue::Vi16s(v) => GenericValueBoxed::Vi16s(array_from_c(v)),
+        UfoValue::Vu32s(v) => GenericValueBoxed::Vu32s(array_from_c(v)),
+        UfoValue::Vi32s(v) => GenericValueBoxed::Vi32s(array_from_c(v)),
+        UfoValue::Vu64s(v) => GenericValueBoxed::Vu64s(array_from_c(v)),
+        UfoValue::Vi64s(v) => GenericValueBoxed::Vi64s(array_from_c(v)),
+        UfoValue::Vf32s(v) => GenericValueBoxed::Vf32s(array_from_c(v)),
+        UfoValue::Vf64s(v) => GenericValueBoxed::Vf64s(array_from_c(v)),
+        UfoValue::Null => GenericValueBoxed::Null,
+        UfoValue::Function(v) => GenericValueBoxed::Function(FunctionToken(*v)),
+        UfoValue::Marker(v) => GenericValueBoxed::Marker(*v),
+    })
+}
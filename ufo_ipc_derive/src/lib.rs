@@ -0,0 +1,154 @@
+use proc_macro::TokenStream;
+use proc_macro2::{TokenStream as TokenStream2, TokenTree};
+use quote::quote;
+use syn::{
+    parse_macro_input, parse_quote, Data, DeriveInput, Fields, Generics, Ident, Index, Member,
+    Type, WherePredicate,
+};
+
+// the fields of a struct in order, with the names errors report them by
+fn fields(input: &DeriveInput) -> syn::Result<Vec<(Member, String, &Type)>> {
+    let data = match &input.data {
+        Data::Struct(data) => data,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "generics can only be derived for structs",
+            ))
+        }
+    };
+
+    Ok(match &data.fields {
+        Fields::Named(fields) => fields
+            .named
+            .iter()
+            .map(|f| {
+                let ident = f.ident.clone().expect("named fields have names");
+                let name = ident.to_string();
+                (Member::Named(ident), name, &f.ty)
+            })
+            .collect(),
+        Fields::Unnamed(fields) => fields
+            .unnamed
+            .iter()
+            .enumerate()
+            .map(|(i, f)| (Member::Unnamed(Index::from(i)), i.to_string(), &f.ty))
+            .collect(),
+        Fields::Unit => Vec::new(),
+    })
+}
+
+fn mentions(tokens: TokenStream2, params: &[&Ident]) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Ident(ident) => params.contains(&&ident),
+        TokenTree::Group(group) => mentions(group.stream(), params),
+        _ => false,
+    })
+}
+
+// the struct's generics, with `bound` added for the type of every field that depends on a type
+// parameter, so `struct S<T> { t: T }` gets its impl only where `T` has what it takes
+fn bounded<F>(input: &DeriveInput, bound: F) -> syn::Result<Generics>
+where
+    F: Fn(&Type) -> Vec<WherePredicate>,
+{
+    let mut generics = input.generics.clone();
+    let params: Vec<&Ident> = input.generics.type_params().map(|p| &p.ident).collect();
+    let predicates: Vec<WherePredicate> = fields(input)?
+        .into_iter()
+        .filter(|(_, _, ty)| mentions(quote!(#ty), &params))
+        .flat_map(|(_, _, ty)| bound(ty))
+        .collect();
+    generics.make_where_clause().predicates.extend(predicates);
+    Ok(generics)
+}
+
+/// Spread a struct over a vector of generic values, one per field in order.
+#[proc_macro_derive(IntoGenerics)]
+pub fn derive_into_generics(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    into_generics(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn into_generics(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let generics = bounded(input, |ty| {
+        vec![
+            parse_quote!(::ufo_ipc::GenericValueBoxed: ::std::convert::From<#ty>),
+            parse_quote!(
+                for<'__a> ::ufo_ipc::GenericValueRef<'__a>: ::std::convert::From<&'__a #ty>
+            ),
+        ]
+    })?;
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    let fields = fields(input)?;
+    let members: Vec<&Member> = fields.iter().map(|(m, _, _)| m).collect();
+    // spelled out, as a bound on a field's type would otherwise be taken for every field
+    let types: Vec<&Type> = fields.iter().map(|(_, _, ty)| *ty).collect();
+
+    Ok(quote! {
+        impl #impl_generics ::ufo_ipc::IntoGenerics for #name #type_generics #where_clause {
+            fn into_generics(self) -> ::std::vec::Vec<::ufo_ipc::GenericValueBoxed> {
+                ::std::vec![#(
+                    <::ufo_ipc::GenericValueBoxed as ::std::convert::From<#types>>::from(
+                        self.#members,
+                    )
+                ),*]
+            }
+
+            fn to_generics(&self) -> ::std::vec::Vec<::ufo_ipc::GenericValueRef<'_>> {
+                ::std::vec![#(
+                    <::ufo_ipc::GenericValueRef<'_> as ::std::convert::From<&#types>>::from(
+                        &self.#members,
+                    )
+                ),*]
+            }
+        }
+    })
+}
+
+/// Build a struct from a vector of generic values, one per field in order.
+#[proc_macro_derive(FromGenerics)]
+pub fn derive_from_generics(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    from_generics(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn from_generics(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let generics = bounded(input, |ty| vec![parse_quote!(#ty: ::ufo_ipc::FromGenericValue)])?;
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    let fields = fields(input)?;
+    let count = fields.len();
+    let members = fields.iter().map(|(m, _, _)| m);
+    let names = fields.iter().map(|(_, n, _)| n);
+
+    Ok(quote! {
+        impl #impl_generics ::ufo_ipc::FromGenerics for #name #type_generics #where_clause {
+            fn from_generics(
+                values: ::std::vec::Vec<::ufo_ipc::GenericValueBoxed>,
+            ) -> ::std::result::Result<Self, ::ufo_ipc::FromGenericsError> {
+                if values.len() != #count {
+                    return ::std::result::Result::Err(::ufo_ipc::FromGenericsError::WrongCount {
+                        expected: #count,
+                        actual: values.len(),
+                    });
+                }
+                let mut values = values.into_iter();
+                ::std::result::Result::Ok(#name {
+                    #(#members: ::ufo_ipc::FromGenericValue::from_generic_value(
+                        values.next().expect("counted above"),
+                    )
+                    .map_err(|source| ::ufo_ipc::FromGenericsError::Field {
+                        field: #names,
+                        source,
+                    })?,)*
+                })
+            }
+        }
+    })
+}